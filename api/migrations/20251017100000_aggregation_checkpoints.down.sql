@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS aggregation_checkpoints;
+
+DROP TABLE IF EXISTS aggregation_runs;
+
+DROP TYPE IF EXISTS PAGE_STATUS_TYPE;
@@ -0,0 +1,39 @@
+CREATE TYPE PAGE_STATUS_TYPE AS ENUM (
+    'in_flight',
+    'completed',
+    'failed'
+);
+
+CREATE TABLE IF NOT EXISTS aggregation_runs (
+    id uuid PRIMARY KEY DEFAULT uuid_generate_v4 (),
+    source varchar(255) NOT NULL,
+    total_pages int,
+    finished_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_aggregation_runs_source ON aggregation_runs (source, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS aggregation_checkpoints (
+    id uuid PRIMARY KEY DEFAULT uuid_generate_v4 (),
+    run_id uuid NOT NULL,
+    page int NOT NULL,
+    status PAGE_STATUS_TYPE NOT NULL,
+    attempts int NOT NULL DEFAULT 0,
+    error text,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_run FOREIGN KEY (run_id) REFERENCES aggregation_runs (id) ON DELETE CASCADE,
+    CONSTRAINT uq_run_page UNIQUE (run_id, page)
+);
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON aggregation_runs
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON aggregation_checkpoints
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
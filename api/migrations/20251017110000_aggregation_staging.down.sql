@@ -0,0 +1 @@
+DROP SCHEMA IF EXISTS aggregation_staging CASCADE;
@@ -0,0 +1,11 @@
+-- Sources using the `staged` commit policy write into these tables during a run, and merge them
+-- into the live tables once every page was fetched.
+CREATE SCHEMA IF NOT EXISTS aggregation_staging;
+
+CREATE TABLE IF NOT EXISTS aggregation_staging.foods (
+    LIKE public.foods INCLUDING DEFAULTS INCLUDING INDEXES
+);
+
+CREATE TABLE IF NOT EXISTS aggregation_staging.food_nutrients (
+    LIKE public.food_nutrients INCLUDING DEFAULTS INCLUDING INDEXES
+);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS aggregation_dead_letters;
+
+DROP TYPE IF EXISTS DEAD_LETTER_KIND_TYPE;
@@ -0,0 +1,25 @@
+CREATE TYPE DEAD_LETTER_KIND_TYPE AS ENUM (
+    'fetch',
+    'persist'
+);
+
+CREATE TABLE IF NOT EXISTS aggregation_dead_letters (
+    id uuid PRIMARY KEY DEFAULT uuid_generate_v4 (),
+    run_id uuid NOT NULL,
+    source varchar(255) NOT NULL,
+    page int NOT NULL,
+    kind DEAD_LETTER_KIND_TYPE NOT NULL,
+    error text NOT NULL,
+    attempts int NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_run FOREIGN KEY (run_id) REFERENCES aggregation_runs (id) ON DELETE CASCADE,
+    CONSTRAINT uq_dead_letter_run_page UNIQUE (run_id, page)
+);
+
+CREATE INDEX IF NOT EXISTS idx_aggregation_dead_letters_source ON aggregation_dead_letters (source);
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON aggregation_dead_letters
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS aggregation_food_diffs;
+
+DROP TYPE IF EXISTS FOOD_DIFF_KIND_TYPE;
+
+ALTER TABLE aggregation_runs
+    DROP COLUMN IF EXISTS dry_run;
@@ -0,0 +1,33 @@
+ALTER TABLE aggregation_runs
+    ADD COLUMN IF NOT EXISTS dry_run bool NOT NULL DEFAULT FALSE;
+
+CREATE TYPE FOOD_DIFF_KIND_TYPE AS ENUM (
+    'added',
+    'renamed',
+    'nutrient_changed',
+    'removed',
+    'unchanged'
+);
+
+CREATE TABLE IF NOT EXISTS aggregation_food_diffs (
+    id uuid PRIMARY KEY DEFAULT uuid_generate_v4 (),
+    run_id uuid NOT NULL,
+    external_id int NOT NULL,
+    kind FOOD_DIFF_KIND_TYPE NOT NULL,
+    name text NOT NULL,
+    previous_name text,
+    nutrient varchar(255),
+    unit varchar(255),
+    previous_value float4,
+    value float4,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_run FOREIGN KEY (run_id) REFERENCES aggregation_runs (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_aggregation_food_diffs_run ON aggregation_food_diffs (run_id, external_id);
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON aggregation_food_diffs
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
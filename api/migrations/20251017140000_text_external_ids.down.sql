@@ -0,0 +1,8 @@
+ALTER TABLE aggregation_food_diffs
+    ALTER COLUMN external_id TYPE int USING external_id::int;
+
+ALTER TABLE aggregation_staging.foods
+    ALTER COLUMN external_id TYPE int USING external_id::int;
+
+ALTER TABLE foods
+    ALTER COLUMN external_id TYPE int USING external_id::int;
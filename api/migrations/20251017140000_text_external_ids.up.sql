@@ -0,0 +1,9 @@
+-- Barcodes used by packaged food sources don't fit an int, and may carry leading zeros
+ALTER TABLE foods
+    ALTER COLUMN external_id TYPE text USING external_id::text;
+
+ALTER TABLE aggregation_staging.foods
+    ALTER COLUMN external_id TYPE text USING external_id::text;
+
+ALTER TABLE aggregation_food_diffs
+    ALTER COLUMN external_id TYPE text USING external_id::text;
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS aggregation_run_history;
+
+DROP TYPE IF EXISTS AGGREGATION_OUTCOME_TYPE;
@@ -0,0 +1,34 @@
+CREATE TYPE AGGREGATION_OUTCOME_TYPE AS ENUM (
+    'running',
+    'finished',
+    'postponed',
+    'failed',
+    'cancelled',
+    'interrupted'
+);
+
+CREATE TABLE IF NOT EXISTS aggregation_run_history (
+    id uuid PRIMARY KEY DEFAULT uuid_generate_v4 (),
+    run_id uuid NOT NULL,
+    source varchar(255) NOT NULL,
+    outcome AGGREGATION_OUTCOME_TYPE NOT NULL DEFAULT 'running',
+    started_at timestamptz NOT NULL DEFAULT NOW(),
+    ended_at timestamptz,
+    pages_fetched bigint NOT NULL DEFAULT 0,
+    pages_retried bigint NOT NULL DEFAULT 0,
+    pages_failed bigint NOT NULL DEFAULT 0,
+    foods_inserted bigint NOT NULL DEFAULT 0,
+    foods_updated bigint NOT NULL DEFAULT 0,
+    nutrients_written bigint NOT NULL DEFAULT 0,
+    errors text[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_run FOREIGN KEY (run_id) REFERENCES aggregation_runs (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_aggregation_run_history_source ON aggregation_run_history (source, started_at DESC);
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON aggregation_run_history
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS aggregation_schedules (
+    source varchar(255) PRIMARY KEY,
+    schedule text NOT NULL,
+    last_run_at timestamptz,
+    next_run_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON aggregation_schedules
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
+
+-- USDA used to be the only source, aggregated every 30 days since the last run recorded in
+-- aggregation_metadata. Carrying that run over keeps it from aggregating again on deploy
+INSERT INTO aggregation_schedules (source, schedule, last_run_at, next_run_at)
+SELECT
+    'usda',
+    'every 30d',
+    MAX(last_run),
+    MAX(last_run) + INTERVAL '30 days'
+FROM
+    aggregation_metadata
+HAVING
+    MAX(last_run) IS NOT NULL
+ON CONFLICT (source)
+    DO NOTHING;
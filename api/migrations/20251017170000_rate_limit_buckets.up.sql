@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS rate_limit_buckets (
+    source varchar(255) PRIMARY KEY,
+    tokens double precision NOT NULL,
+    refilled_at timestamptz NOT NULL DEFAULT NOW(),
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    updated_at timestamptz NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER trg_set_updated_at
+    BEFORE UPDATE ON rate_limit_buckets
+    FOR EACH ROW
+    EXECUTE FUNCTION set_updated_at ();
@@ -0,0 +1,9 @@
+UPDATE aggregation_staging.food_nutrients SET value = 0 WHERE value IS NULL;
+
+ALTER TABLE aggregation_staging.food_nutrients
+    ALTER COLUMN value SET NOT NULL;
+
+UPDATE food_nutrients SET value = 0 WHERE value IS NULL;
+
+ALTER TABLE food_nutrients
+    ALTER COLUMN value SET NOT NULL;
@@ -0,0 +1,7 @@
+-- Sources don't report every nutrient of a food, and an unreported value has to stay unknown
+-- instead of reading as zero
+ALTER TABLE food_nutrients
+    ALTER COLUMN value DROP NOT NULL;
+
+ALTER TABLE aggregation_staging.food_nutrients
+    ALTER COLUMN value DROP NOT NULL;
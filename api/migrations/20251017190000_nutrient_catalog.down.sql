@@ -0,0 +1,9 @@
+ALTER TABLE nutrients
+    DROP CONSTRAINT IF EXISTS fk_default_unit,
+    DROP COLUMN IF EXISTS nutrient_group,
+    DROP COLUMN IF EXISTS display_order,
+    DROP COLUMN IF EXISTS default_unit_id,
+    DROP COLUMN IF EXISTS tagname,
+    DROP COLUMN IF EXISTS number;
+
+DROP TYPE IF EXISTS NUTRIENT_GROUP_TYPE;
@@ -0,0 +1,16 @@
+CREATE TYPE NUTRIENT_GROUP_TYPE AS ENUM (
+    'macro',
+    'lipid',
+    'mineral',
+    'vitamin'
+);
+
+-- Nutrients of the canonical catalog are identified by their USDA nutrient number and INFOODS
+-- tagname, nutrients only known to a single source have none of the catalog columns set
+ALTER TABLE nutrients
+    ADD COLUMN number varchar(16) UNIQUE,
+    ADD COLUMN tagname varchar(32) UNIQUE,
+    ADD COLUMN default_unit_id uuid,
+    ADD COLUMN display_order int,
+    ADD COLUMN nutrient_group NUTRIENT_GROUP_TYPE,
+    ADD CONSTRAINT fk_default_unit FOREIGN KEY (default_unit_id) REFERENCES units (id);
@@ -0,0 +1,11 @@
+-- Foods written by each configured source. Configured sources can share a food source, like the
+-- USDA API and bulk downloads both writing USDA foods, so the food source alone can't tell which
+-- of them a food came from. Foods written before this table existed are linked by the next run
+CREATE TABLE IF NOT EXISTS aggregation_source_foods (
+    source varchar(255) NOT NULL,
+    food_source_id uuid NOT NULL,
+    external_id text NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (source, food_source_id, external_id),
+    CONSTRAINT fk_food_source FOREIGN KEY (food_source_id) REFERENCES food_sources (id)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE aggregation_run_history
+    DROP COLUMN IF EXISTS lease_pid,
+    DROP COLUMN IF EXISTS lease_backend_start;
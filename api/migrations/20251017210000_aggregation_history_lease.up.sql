@@ -0,0 +1,6 @@
+-- Session holding the source lease while the attempt ran. An attempt still marked as running is
+-- only cut short once that session is gone, attempts of live sessions on other instances are left
+-- alone
+ALTER TABLE aggregation_run_history
+    ADD COLUMN lease_pid integer,
+    ADD COLUMN lease_backend_start timestamptz;
@@ -0,0 +1,4 @@
+-- Admins manage the food aggregator, triggering and cancelling aggregations and handling dead
+-- letters. Granted by hand, nobody is an admin by default
+ALTER TABLE users
+    ADD COLUMN is_admin bool NOT NULL DEFAULT FALSE;
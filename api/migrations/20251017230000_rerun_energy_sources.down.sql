@@ -0,0 +1 @@
+-- Nothing to undo, the sources were only scheduled to run earlier
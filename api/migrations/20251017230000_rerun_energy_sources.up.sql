@@ -0,0 +1,21 @@
+-- USDA reports energy as `Energy` both in kcal and in kJ, and before nutrients were matched by
+-- their number both landed on the same row, keeping whichever was written last with the unit of
+-- the first. Sources that wrote energy values are due right away, so their next run writes every
+-- row again with the right value and unit. USDA aggregated before sources recorded their foods,
+-- so it is matched by name as well
+UPDATE
+    aggregation_schedules s
+SET
+    next_run_at = NOW()
+WHERE
+    s.source IN ('usda', 'usda-bulk')
+    OR EXISTS (
+        SELECT
+            1
+        FROM
+            aggregation_source_foods asf
+            JOIN food_nutrients fn ON fn.source_id = asf.food_source_id
+            JOIN nutrients n ON n.id = fn.nutrient_id
+        WHERE
+            asf.source = s.source
+            AND n.name = 'Energy');
@@ -2,6 +2,7 @@ use axum::Json;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use derive_more::{Display, Error, From};
+use food_aggregator::AggregatorError;
 use serde::Serialize;
 
 use crate::services::clerk::ClerkError;
@@ -13,11 +14,26 @@ pub enum AppError {
     #[error(ignore)]
     ServerError(String),
 
+    #[display("Could not find {_0}")]
+    #[error(ignore)]
+    NotFound(String),
+
+    #[display("Not allowed to {_0}")]
+    #[error(ignore)]
+    Forbidden(String),
+
+    #[display("Conflict: {_0}")]
+    #[error(ignore)]
+    Conflict(String),
+
     #[from]
     Unauthorized(ClerkError),
 
     #[from]
     Search(SearchError),
+
+    #[from]
+    Aggregator(AggregatorError),
 }
 
 #[derive(Serialize)]
@@ -51,8 +67,12 @@ impl AppError {
     fn error_code(&self) -> StatusCode {
         match self {
             AppError::ServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
             AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
             AppError::Search(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Aggregator(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use food_aggregator::config::{AggregatorConfig, ConfigError, SourceConfig};
+use food_aggregator::dead_letters::{self, ReplaySummary};
+use food_aggregator::models::aggregation_history::AggregationHistory;
+use food_aggregator::models::aggregation_runs::{AggregationRun, AggregationRunProgress};
+use food_aggregator::models::aggregation_schedules::AggregationSchedule;
+use food_aggregator::models::dead_letters::DeadLetter;
+use food_aggregator::monitor::{ProgressEvent, SourceState, SourceStatus};
+use food_aggregator::registry::{AggregatorRegistry, BoxedAggregator};
+use food_aggregator::{AggregatorError, run_aggregators, run_built_aggregators};
+use serde::Serialize;
+use sqlx::types::Uuid;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::AppState;
+use crate::error::AppError;
+
+#[derive(Debug, Serialize)]
+pub struct TriggerSummary {
+    pub started: Vec<String>,
+    /// Sources left alone because they were already being aggregated
+    pub already_running: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SourceProgress {
+    #[serde(flatten)]
+    pub status: SourceStatus,
+    /// Run being aggregated, or the latest one when the source is idle
+    pub run: Option<AggregationRunProgress>,
+    pub schedule: Option<AggregationSchedule>,
+}
+
+/// Starts aggregating `source`, or every enabled source, in the background
+#[tracing::instrument(skip(state))]
+pub async fn trigger_aggregation(
+    state: &AppState,
+    source: Option<&str>,
+) -> Result<TriggerSummary, AppError> {
+    let registry = state.aggregator_registry.clone();
+    let config = state.aggregator_config.clone();
+    let db = state.db.clone();
+    let shutdown = state.shutdown.clone();
+
+    let source_config = source
+        .map(|source| enabled_source(&config, source).cloned())
+        .transpose()?;
+    let sources = match source {
+        Some(source) => vec![source.to_string()],
+        None => config
+            .enabled_sources()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    };
+    let (already_running, started) = sources
+        .into_iter()
+        .partition::<Vec<_>, _>(|source| registry.monitor().is_active(source));
+
+    let (Some(source), Some(source_config)) = (source, source_config) else {
+        let aggregators = registry
+            .build_enabled(&config)
+            .map_err(AggregatorError::from)?;
+        state.tasks.spawn(async move {
+            let monitor = registry.monitor();
+            if let Err(e) =
+                run_built_aggregators(db, monitor, &config, aggregators, &shutdown).await
+            {
+                tracing::error!(error = ?e, "Triggered aggregation failed");
+            }
+        });
+
+        return Ok(TriggerSummary {
+            started,
+            already_running,
+        });
+    };
+
+    if !started.is_empty() {
+        let aggregator = build_source(&registry, source, &source_config)?;
+        let source = source.to_string();
+
+        state.tasks.spawn(async move {
+            let aggregators = vec![(source, aggregator)];
+            if let Err(e) = run_aggregators(db, registry.monitor(), aggregators, &shutdown).await {
+                tracing::error!(error = ?e, "Triggered aggregation failed");
+            }
+        });
+    }
+
+    Ok(TriggerSummary {
+        started,
+        already_running,
+    })
+}
+
+/// Live state of every source known to this process, along with the progress of its run
+#[tracing::instrument(skip(state))]
+pub async fn aggregation_status(state: &AppState) -> Result<Vec<SourceProgress>, AppError> {
+    let monitor = state.aggregator_registry.monitor();
+    let mut statuses = monitor.status();
+
+    for (name, _) in state.aggregator_config.enabled_sources() {
+        if monitor.source_status(name).is_none() {
+            statuses.push(SourceStatus::idle(name));
+        }
+    }
+
+    let mut conn = state.db.acquire().await?;
+    let run_ids = statuses
+        .iter()
+        .filter_map(|status| status.run_id)
+        .collect::<Vec<_>>();
+    let mut runs = AggregationRun::get_progress_for_runs(conn.as_mut(), &run_ids)
+        .await?
+        .into_iter()
+        .map(|run| (run.id, run))
+        .collect::<HashMap<_, _>>();
+    let mut latest = AggregationRun::get_latest_progress(conn.as_mut())
+        .await?
+        .into_iter()
+        .map(|run| (run.source.clone(), run))
+        .collect::<HashMap<_, _>>();
+    let mut schedules = AggregationSchedule::get_all(conn.as_mut())
+        .await?
+        .into_iter()
+        .map(|schedule| (schedule.source.clone(), schedule))
+        .collect::<HashMap<_, _>>();
+
+    let progress = statuses
+        .into_iter()
+        .map(|status| {
+            let run = match (status.run_id, &status.state) {
+                (Some(run_id), _) => runs.remove(&run_id),
+                (None, SourceState::Idle) => latest.remove(&status.source),
+                (None, _) => None,
+            };
+            let schedule = schedules.remove(&status.source);
+            SourceProgress {
+                status,
+                run,
+                schedule,
+            }
+        })
+        .collect();
+
+    Ok(progress)
+}
+
+/// Cancels the aggregation of `source`, or of every source being aggregated
+#[tracing::instrument(skip(state))]
+pub async fn cancel_aggregation(
+    state: &AppState,
+    source: Option<&str>,
+) -> Result<Vec<String>, AppError> {
+    let monitor = state.aggregator_registry.monitor();
+
+    let Some(source) = source else {
+        let cancelled = monitor
+            .active_sources()
+            .into_iter()
+            .filter(|source| monitor.cancel(source))
+            .collect();
+        return Ok(cancelled);
+    };
+
+    if !monitor.cancel(source) {
+        return Err(AppError::NotFound(format!(
+            "running aggregation of {source}"
+        )));
+    }
+
+    Ok(vec![source.to_string()])
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn list_runs(
+    state: &AppState,
+    source: Option<&str>,
+    limit: i64,
+) -> Result<Vec<AggregationRunProgress>, AppError> {
+    let mut conn = state.db.acquire().await?;
+    let runs = AggregationRun::list_progress(conn.as_mut(), source, limit).await?;
+    Ok(runs)
+}
+
+/// Progress events published from now on, for `source` or every source
+pub fn progress_events(
+    state: &AppState,
+    source: Option<String>,
+) -> impl Stream<Item = ProgressEvent> + use<> {
+    let receiver = state.aggregator_registry.monitor().subscribe();
+
+    let events = BroadcastStream::new(receiver).filter_map(move |event| match event {
+        Ok(event) if source.as_ref().is_none_or(|source| *source == event.source) => Some(event),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!(error = %e, "Progress subscriber fell behind, skipping events");
+            None
+        }
+    });
+
+    // The server only shuts down once every connection is closed, which a stream of events
+    // never does on its own
+    futures_util::StreamExt::take_until(events, state.shutdown.clone().cancelled_owned())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn list_history(
+    state: &AppState,
+    source: Option<&str>,
+    limit: i64,
+) -> Result<Vec<AggregationHistory>, AppError> {
+    let mut conn = state.db.acquire().await?;
+    let history = AggregationHistory::list(conn.as_mut(), source, limit).await?;
+    Ok(history)
+}
+
+/// Configuration of `source`. Unconfigured sources would run with the default settings, and
+/// disabled ones are meant to stay off until they are enabled again
+fn enabled_source<'a>(
+    config: &'a AggregatorConfig,
+    source: &str,
+) -> Result<&'a SourceConfig, AppError> {
+    match config.sources.get(source) {
+        Some(source_config) if source_config.enabled => Ok(source_config),
+        Some(_) => Err(AppError::Conflict(format!("source {source} is disabled"))),
+        None => Err(AppError::NotFound(format!("source {source}"))),
+    }
+}
+
+fn build_source(
+    registry: &AggregatorRegistry,
+    source: &str,
+    config: &SourceConfig,
+) -> Result<BoxedAggregator, AppError> {
+    match registry.build(source, config) {
+        Ok(aggregator) => Ok(aggregator),
+        Err(ConfigError::UnknownSource(kind)) => Err(AppError::NotFound(format!("source {kind}"))),
+        Err(e) => Err(AggregatorError::from(e).into()),
+    }
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn list_dead_letters(
+    state: &AppState,
+    source: Option<&str>,
+) -> Result<Vec<DeadLetter>, AppError> {
+    let mut conn = state.db.acquire().await?;
+    let dead_letters = DeadLetter::get_all(conn.as_mut(), source).await?;
+    Ok(dead_letters)
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn replay_dead_letter(state: &AppState, id: Uuid) -> Result<bool, AppError> {
+    let dead_letter = {
+        let mut conn = state.db.acquire().await?;
+        DeadLetter::get(conn.as_mut(), id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("dead letter {id}")))?
+    };
+
+    let replayed = dead_letters::replay_dead_letter(
+        state.db.clone(),
+        &state.aggregator_registry,
+        &state.aggregator_config,
+        &dead_letter,
+    )
+    .await?;
+
+    Ok(replayed)
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn replay_dead_letters(
+    state: &AppState,
+    source: Option<&str>,
+) -> Result<ReplaySummary, AppError> {
+    let summary = dead_letters::replay_dead_letters(
+        state.db.clone(),
+        &state.aggregator_registry,
+        &state.aggregator_config,
+        source,
+    )
+    .await?;
+
+    Ok(summary)
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn delete_dead_letter(state: &AppState, id: Uuid) -> Result<(), AppError> {
+    let mut conn = state.db.acquire().await?;
+
+    if !DeadLetter::delete(conn.as_mut(), id).await? {
+        return Err(AppError::NotFound(format!("dead letter {id}")));
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn purge_dead_letters(state: &AppState, source: Option<&str>) -> Result<u64, AppError> {
+    let mut conn = state.db.acquire().await?;
+    let purged = DeadLetter::purge(conn.as_mut(), source).await?;
+    Ok(purged)
+}
@@ -1 +1,2 @@
+pub mod aggregator;
 pub mod auth;
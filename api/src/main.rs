@@ -5,6 +5,9 @@ mod models;
 mod routes;
 mod services;
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use axum::Router;
 use axum::middleware::from_fn_with_state;
 use clerk_rs::ClerkConfiguration;
@@ -12,8 +15,13 @@ use clerk_rs::clerk::Clerk;
 use clerk_rs::validators::axum::ClerkLayer;
 use clerk_rs::validators::jwks::MemoryCacheJwksProvider;
 use food_aggregator::AggregateStatus;
+use food_aggregator::config::AggregatorConfig;
+use food_aggregator::registry::AggregatorRegistry;
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
@@ -25,6 +33,13 @@ pub struct AppState {
     pub clerk: Clerk,
     pub db: PgPool,
     pub search_service: SearchService,
+    pub aggregator_registry: Arc<AggregatorRegistry>,
+    pub aggregator_config: Arc<AggregatorConfig>,
+    /// Cancelled once the server is asked to stop
+    pub shutdown: CancellationToken,
+    /// Aggregations running in the background, waited on before exiting so the pages they have
+    /// in flight get persisted
+    pub tasks: TaskTracker,
 }
 
 async fn db_connect() -> sqlx::Result<PgPool> {
@@ -56,20 +71,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let clerk = Clerk::new(config);
     let db = db_connect().await?;
 
+    let aggregator_config = Arc::new(AggregatorConfig::from_env()?);
+    let aggregator_registry = Arc::new(AggregatorRegistry::from_config(&aggregator_config)?);
+
+    let shutdown = food_aggregator::shutdown::token();
+    let tasks = TaskTracker::new();
+
     let cron_db = db.clone();
-    tokio::spawn(async move {
+    let cron_registry = aggregator_registry.clone();
+    let cron_config = aggregator_config.clone();
+    let cron_shutdown = shutdown.clone();
+    tasks.spawn(async move {
+        // How long to wait before checking the schedules again when no source is scheduled, or
+        // the last check failed
+        const RECHECK_DELAY: Duration = Duration::from_secs(60 * 60);
+
         loop {
-            // TODO: don't ignore the error here
-            match food_aggregator::aggregate_food_data(cron_db.clone()).await {
-                Ok(status) => match status {
-                    AggregateStatus::Finished => tracing::info!("Aggregation finished"),
-                    AggregateStatus::PendingUntil(_) => tracing::info!("Aggregation postponed"),
-                },
-                Err(_) => tracing::error!("Aggregation failed to execute"),
+            let wake_time = match food_aggregator::aggregate_food_data(
+                cron_db.clone(),
+                &cron_registry,
+                &cron_config,
+                &cron_shutdown,
+            )
+            .await
+            {
+                Ok(AggregateStatus::PendingUntil(wake_time)) => wake_time,
+                Ok(AggregateStatus::Cancelled) => break,
+                Ok(AggregateStatus::Finished) => {
+                    tracing::info!("No source scheduled for aggregation");
+                    Instant::now() + RECHECK_DELAY
+                }
+                Err(e) => {
+                    tracing::error!(error = ?e, "Aggregation failed to execute");
+                    Instant::now() + RECHECK_DELAY
+                }
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(wake_time) => {},
+                _ = cron_shutdown.cancelled() => break,
             }
-
-            const ONE_DAY: u64 = 60 * 60 * 24;
-            tokio::time::sleep(tokio::time::Duration::from_secs(ONE_DAY)).await;
         }
     });
 
@@ -82,6 +123,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         clerk: clerk.clone(),
         search_service,
         db,
+        aggregator_registry,
+        aggregator_config,
+        shutdown: shutdown.clone(),
+        tasks: tasks.clone(),
     };
 
     let clerk_layer = ClerkLayer::new(MemoryCacheJwksProvider::new(clerk), None, true);
@@ -100,7 +145,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}")).await?;
     tracing::info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+
+    tracing::info!("Server stopped, waiting for aggregations to wind down");
+    tasks.close();
+    tasks.wait().await;
 
     Ok(())
 }
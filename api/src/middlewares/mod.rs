@@ -5,6 +5,7 @@ use axum::response::Response;
 use clerk_rs::validators::authorizer::ClerkJwt;
 
 use crate::error::AppError;
+use crate::models::users::User;
 use crate::{AppState, models, services};
 
 #[tracing::instrument(skip_all)]
@@ -22,3 +23,17 @@ pub async fn attach_user(
 
     Ok(next.run(req).await)
 }
+
+/// Only lets admins through, the user is attached by [`attach_user`], which has to run first
+#[tracing::instrument(skip_all)]
+pub async fn require_admin(
+    Extension(user): Extension<User>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !user.is_admin {
+        return Err(AppError::Forbidden(String::from("manage the aggregator")));
+    }
+
+    Ok(next.run(req).await)
+}
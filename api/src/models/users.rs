@@ -15,6 +15,8 @@ pub struct User {
     pub email: String,
     pub has_image: bool,
     pub image_url: Option<String>,
+    /// Allowed to manage the food aggregator
+    pub is_admin: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -1,17 +1,158 @@
-use axum::routing::get;
-use axum::{Extension, Json, Router};
+use std::convert::Infallible;
+
+use axum::extract::{Path, Query, State};
+use axum::middleware::from_fn;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use food_aggregator::dead_letters::ReplaySummary;
+use food_aggregator::models::aggregation_history::AggregationHistory;
+use food_aggregator::models::aggregation_runs::AggregationRunProgress;
+use food_aggregator::models::dead_letters::DeadLetter;
+use serde::Deserialize;
+use sqlx::types::Uuid;
+use tokio_stream::{Stream, StreamExt};
 
 use super::HttpResponse;
-use crate::AppState;
 use crate::error::AppError;
-use crate::models::users::User;
+use crate::handlers::aggregator::{SourceProgress, TriggerSummary};
+use crate::middlewares::require_admin;
+use crate::{AppState, handlers};
 
 pub fn aggregator_routes() -> Router<AppState> {
-    Router::new().route("/aggregate", get(run_aggregators))
+    Router::new()
+        .route("/status", get(aggregation_status))
+        .route("/runs", get(list_runs))
+        .route("/history", get(list_history))
+        .route("/dead-letters", get(list_dead_letters))
+        .merge(admin_routes())
+}
+
+/// Routes that act on the aggregation, or stream its every event, are kept to admins
+fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/runs", post(trigger_aggregation))
+        .route("/runs/cancel", post(cancel_aggregation))
+        .route("/events", get(progress_events))
+        .route("/dead-letters", delete(purge_dead_letters))
+        .route("/dead-letters/replay", post(replay_dead_letters))
+        .route("/dead-letters/{id}", delete(delete_dead_letter))
+        .route("/dead-letters/{id}/replay", post(replay_dead_letter))
+        .route_layer(from_fn(require_admin))
+}
+
+#[derive(Debug, Deserialize)]
+struct SourceParams {
+    source: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RunsParams {
+    source: Option<String>,
+    #[serde(default = "default_runs_limit")]
+    limit: i64,
+}
+
+fn default_runs_limit() -> i64 {
+    50
+}
+
+async fn trigger_aggregation(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+) -> Result<Json<HttpResponse<TriggerSummary>>, AppError> {
+    let summary =
+        handlers::aggregator::trigger_aggregation(&state, params.source.as_deref()).await?;
+    Ok(Json(summary.into()))
+}
+
+async fn aggregation_status(
+    State(state): State<AppState>,
+) -> Result<Json<HttpResponse<Vec<SourceProgress>>>, AppError> {
+    let status = handlers::aggregator::aggregation_status(&state).await?;
+    Ok(Json(status.into()))
+}
+
+async fn cancel_aggregation(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+) -> Result<Json<HttpResponse<Vec<String>>>, AppError> {
+    let cancelled =
+        handlers::aggregator::cancel_aggregation(&state, params.source.as_deref()).await?;
+    Ok(Json(cancelled.into()))
 }
 
-pub async fn run_aggregators(
-    Extension(_user): Extension<User>,
+async fn list_runs(
+    State(state): State<AppState>,
+    Query(params): Query<RunsParams>,
+) -> Result<Json<HttpResponse<Vec<AggregationRunProgress>>>, AppError> {
+    let runs =
+        handlers::aggregator::list_runs(&state, params.source.as_deref(), params.limit).await?;
+    Ok(Json(runs.into()))
+}
+
+async fn list_dead_letters(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+) -> Result<Json<HttpResponse<Vec<DeadLetter>>>, AppError> {
+    let dead_letters =
+        handlers::aggregator::list_dead_letters(&state, params.source.as_deref()).await?;
+    Ok(Json(dead_letters.into()))
+}
+
+async fn replay_dead_letters(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+) -> Result<Json<HttpResponse<ReplaySummary>>, AppError> {
+    let summary =
+        handlers::aggregator::replay_dead_letters(&state, params.source.as_deref()).await?;
+    Ok(Json(summary.into()))
+}
+
+async fn replay_dead_letter(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<HttpResponse<bool>>, AppError> {
+    let replayed = handlers::aggregator::replay_dead_letter(&state, id).await?;
+    Ok(Json(replayed.into()))
+}
+
+async fn delete_dead_letter(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
 ) -> Result<Json<HttpResponse<bool>>, AppError> {
-    Ok(Json(HttpResponse::from(true)))
+    handlers::aggregator::delete_dead_letter(&state, id).await?;
+    Ok(Json(true.into()))
+}
+
+async fn purge_dead_letters(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+) -> Result<Json<HttpResponse<u64>>, AppError> {
+    let purged = handlers::aggregator::purge_dead_letters(&state, params.source.as_deref()).await?;
+    Ok(Json(purged.into()))
+}
+
+async fn list_history(
+    State(state): State<AppState>,
+    Query(params): Query<RunsParams>,
+) -> Result<Json<HttpResponse<Vec<AggregationHistory>>>, AppError> {
+    let history =
+        handlers::aggregator::list_history(&state, params.source.as_deref(), params.limit).await?;
+    Ok(Json(history.into()))
+}
+
+async fn progress_events(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = handlers::aggregator::progress_events(&state, params.source).map(|event| {
+        let sse_event = Event::default().event(event.kind.name());
+        // A single event failing to serialize shouldn't end the stream for the dashboard
+        Ok(sse_event
+            .json_data(&event)
+            .unwrap_or_else(|e| Event::default().comment(e.to_string())))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
 }
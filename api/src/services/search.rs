@@ -22,7 +22,6 @@ pub enum SearchError {
 #[derive(Clone)]
 pub struct SearchService {
     index: Index,
-    schema: Schema,
     reader: IndexReader,
     id_field: Field,
     name_field: Field,
@@ -50,7 +49,6 @@ impl SearchService {
         let source_field = schema.get_field("source")?;
 
         Ok(SearchService {
-            schema,
             index,
             reader,
             id_field,
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use sqlx::PgPool;
+use sqlx::types::Uuid;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{CommitPolicy, RestartPolicy, SinkConfig, SourceConfig};
+use crate::models::aggregation_checkpoints::AggregationCheckpoint;
+use crate::models::aggregation_history::{AggregationHistory, AggregationOutcome};
+use crate::models::aggregation_runs::AggregationRun;
+use crate::monitor::{ProgressEventKind, SourceMonitor};
+use crate::rate_limit::{RateLimit, SourceLimiter};
+use crate::registry::AggregatorContext;
+use crate::supervisor::{
+    AggregatorSupervisor, FoodData, PageCommitter, ResumePoint, SupervisorConfig,
+};
+use crate::{AggregateStatus, Aggregator, AggregatorError, BoxFuture, FoodSource};
+
+/// Aggregates any paginated [`FoodSource`], fetching the first page on its own to learn how many
+/// pages there are and handing the rest to an [`AggregatorSupervisor`].
+#[derive(Debug)]
+pub struct PagedAggregator<C>
+where
+    C: FoodSource,
+{
+    name: String,
+    limiter: SourceLimiter,
+    client: Arc<C>,
+    supervisor_config: SupervisorConfig,
+    sink: SinkConfig,
+    monitor: SourceMonitor,
+}
+
+impl<C> PagedAggregator<C>
+where
+    C: FoodSource,
+{
+    pub fn new(
+        name: impl Into<String>,
+        client: C,
+        rate_limit: Option<RateLimit>,
+        supervisor_config: SupervisorConfig,
+        sink: SinkConfig,
+        monitor: SourceMonitor,
+    ) -> Self {
+        let name = name.into();
+        let limiter = SourceLimiter::new(name.clone(), rate_limit);
+
+        Self {
+            name,
+            limiter,
+            client: Arc::new(client),
+            supervisor_config,
+            sink,
+            monitor,
+        }
+    }
+
+    /// Aggregator for a source read from disk. Pages are not rate limited unless the source
+    /// configures a limit, as there is no remote service to protect
+    pub fn from_file(
+        name: &str,
+        client: C,
+        config: &SourceConfig,
+        context: &AggregatorContext,
+    ) -> Self {
+        Self::new(
+            name,
+            client,
+            config.rate_limit.clone(),
+            config.supervisor(),
+            config.sink.clone(),
+            context.monitor.source(name),
+        )
+    }
+}
+
+impl<C> PagedAggregator<C>
+where
+    C: FoodSource + 'static,
+    C::Data: Send + Sync + 'static,
+{
+    /// Picks `run` up from its checkpoints, fetching pages until it finishes, gets rate limited
+    /// or is cancelled
+    async fn aggregate_run(
+        &mut self,
+        pool: &PgPool,
+        run: &AggregationRun,
+        committer: &mut PageCommitter,
+        cancel: CancellationToken,
+    ) -> Result<AggregateStatus, AggregatorError> {
+        let mut conn = pool.acquire().await?;
+        let checkpoints = AggregationCheckpoint::get_for_run(conn.as_mut(), run.id).await?;
+        let mut resume = ResumePoint::from_checkpoints(&checkpoints);
+
+        if let Some(total_pages) = run.total_pages
+            && resume.is_complete(total_pages as usize)
+        {
+            tracing::info!(run_id = %run.id, "Run already fetched every page");
+            committer.finish().await?;
+            AggregationRun::finish(conn.as_mut(), run.id).await?;
+            return Ok(AggregateStatus::Finished);
+        }
+
+        if cancel.is_cancelled() {
+            return Ok(AggregateStatus::Cancelled);
+        }
+
+        // Use one entry from limiter to account for the first request. The budget is shared with
+        // other processes and survives restarts, so it may have been spent already
+        if let Err(exhausted) = self.limiter.check(pool).await? {
+            tracing::info!(run_id = %run.id, "Rate limit budget spent, postponing run");
+            return Ok(AggregateStatus::PendingUntil(exhausted.until));
+        }
+
+        // This first request is made separately in order to fetch the total_pages from the
+        // source, so that we can coordinate the concurrent syncing. When resuming a run, it is
+        // the first page that still has to be fetched instead of page 1
+        let first_page = resume.take_next();
+        committer.start_page(first_page, 0).await?;
+        self.monitor.publish(ProgressEventKind::PageStarted {
+            worker_id: None,
+            page: first_page,
+            retries: 0,
+        });
+
+        let data = match self.client.fetch(first_page).await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!(error = ?e, page = %first_page, "Failed to fetch first page");
+                return Err(e.into());
+            }
+        };
+
+        let total_pages = data.total_pages();
+        tracing::info!(run_id = %run.id, %total_pages, %first_page, "Starting sync");
+        AggregationRun::set_total_pages(conn.as_mut(), run.id, total_pages).await?;
+        self.monitor.publish(ProgressEventKind::RunStarted {
+            total_pages,
+            first_page,
+        });
+
+        let now = std::time::Instant::now();
+        if let Err(e) = committer.persist(first_page, 0, data).await {
+            tracing::error!(error = ?e, "Failed to persist first page food data");
+            return Err(e.into());
+        };
+        self.monitor.publish(ProgressEventKind::PagePersisted {
+            worker_id: None,
+            page: first_page,
+            retries: 0,
+            persist_ms: now.elapsed().as_millis() as u64,
+        });
+
+        let client = self.client.clone();
+        let mut supervisor = AggregatorSupervisor::new(
+            &self.limiter,
+            &mut *committer,
+            client,
+            total_pages,
+            self.supervisor_config,
+            self.monitor.clone(),
+        );
+
+        match supervisor.run(pool, resume, cancel).await {
+            Ok(status) => {
+                tracing::info!(?status, "Sync complete");
+                if let AggregateStatus::Finished = status {
+                    committer.finish().await?;
+                    AggregationRun::finish(conn.as_mut(), run.id).await?;
+                }
+                Ok(status)
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "Sync failed");
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl<C> Aggregator for PagedAggregator<C>
+where
+    C: FoodSource + 'static,
+    C::Data: Send + Sync + 'static,
+{
+    #[tracing::instrument(skip(self, pool, cancel))]
+    fn aggregate(
+        &mut self,
+        pool: PgPool,
+        cancel: CancellationToken,
+    ) -> BoxFuture<'_, Result<AggregateStatus, AggregatorError>> {
+        Box::pin(async move {
+            let dry_run = self.supervisor_config.dry_run;
+            let (run, history_id) = {
+                let mut conn = pool.acquire().await?;
+                let run =
+                    AggregationRun::get_or_create_unfinished(conn.as_mut(), &self.name, dry_run)
+                        .await?;
+                let history_id =
+                    AggregationHistory::start(conn.as_mut(), run.id, &self.name).await?;
+                (run, history_id)
+            };
+            self.monitor.set_run(run.id);
+
+            let sink = self.sink.build(&self.name, pool.clone(), dry_run);
+            let mut committer =
+                PageCommitter::new(run.id, &self.name, self.supervisor_config.commit, sink)
+                    .with_history(history_id);
+
+            let result = self
+                .aggregate_run(&pool, &run, &mut committer, cancel)
+                .await;
+            let outcome = match &result {
+                Ok(AggregateStatus::Finished) => AggregationOutcome::Finished,
+                Ok(AggregateStatus::PendingUntil(_)) => AggregationOutcome::Postponed,
+                Ok(AggregateStatus::Cancelled) => AggregationOutcome::Cancelled,
+                Err(e) => {
+                    committer.stats_mut().record_error(e.to_string());
+                    AggregationOutcome::Failed
+                }
+            };
+
+            let mut conn = pool.acquire().await?;
+            AggregationHistory::finish(conn.as_mut(), history_id, outcome, committer.stats())
+                .await?;
+            result
+        })
+    }
+
+    fn restart_policy(&self) -> RestartPolicy {
+        self.supervisor_config.restart
+    }
+
+    #[tracing::instrument(skip(self, pool))]
+    fn replay(
+        &mut self,
+        pool: PgPool,
+        run_id: Uuid,
+        page: usize,
+    ) -> BoxFuture<'_, Result<(), AggregatorError>> {
+        Box::pin(async move {
+            if self.limiter.check(&pool).await?.is_err() {
+                return Err(AggregatorError::UnexpectedRateLimit);
+            }
+
+            let data = self.client.fetch(page).await?;
+
+            // Replayed pages are never staged, as the run they belong to might have already been
+            // finished
+            let sink = self.sink.build(&self.name, pool.clone(), false);
+            let mut committer = PageCommitter::new(run_id, &self.name, CommitPolicy::PerPage, sink);
+            committer.persist(page, 0, data).await?;
+
+            tracing::info!(%run_id, %page, "Replayed page");
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use crate::supervisor::WorkerId;
+
+/// Fraction of the limit kept when the source shows signs of being overloaded
+const BACKOFF_FACTOR: f64 = 0.5;
+/// Weight of the latest latency in the recent latency average
+const LATENCY_SMOOTHING: f64 = 0.3;
+/// How much slower than the best latency seen pages can get before it counts as overload
+const LATENCY_TOLERANCE: f64 = 2.0;
+/// Pages fetched faster than this are never slow, jitter alone easily doubles the latency of
+/// local sources
+const LATENCY_FLOOR: Duration = Duration::from_millis(100);
+
+/// What a finished fetch tells about the load on the source
+#[derive(Debug, Clone, Copy)]
+pub enum Signal {
+    /// The page was fetched, taking the given time
+    Healthy(Duration),
+    /// The source rate limited us, failed, or didn't answer in time
+    Overloaded,
+}
+
+/// Adjusts how many workers fetch pages at once with additive increase, multiplicative decrease.
+///
+/// Starts at the upper bound, as most sources handle it fine. Every healthy page grows the limit
+/// by a fraction of a worker, so it takes a full window of healthy pages to add one, while every
+/// sign of overload halves it. Latency counts as overload once the recent average gets much
+/// slower than the best it has been, so remote sources back off before they start failing.
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+    min: usize,
+    max: usize,
+    limit: f64,
+    recent_latency: Option<Duration>,
+    best_latency: Option<Duration>,
+    /// Workers spawned before the last decrease were already in flight when it happened, so
+    /// their signals describe the load that caused it and shouldn't decrease the limit again
+    decreased_at: WorkerId,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(min: usize, max: usize) -> Self {
+        // Runs with no pages left past the first one still refetch the pages interrupted by the
+        // previous attempt, so there is always room for at least one worker
+        let max = max.max(1);
+        let min = min.clamp(1, max);
+        Self {
+            min,
+            max,
+            limit: max as f64,
+            recent_latency: None,
+            best_latency: None,
+            decreased_at: WorkerId::default(),
+        }
+    }
+
+    /// How many workers are allowed to be in flight
+    pub fn limit(&self) -> usize {
+        (self.limit as usize).clamp(self.min, self.max)
+    }
+
+    /// Feeds the signal of `worker` into the limit, `next_worker` being the id the next spawned
+    /// worker will get. Returns the new limit when it changed.
+    pub fn observe(
+        &mut self,
+        worker: WorkerId,
+        next_worker: WorkerId,
+        signal: Signal,
+    ) -> Option<usize> {
+        let previous = self.limit();
+
+        let overloaded = match signal {
+            Signal::Healthy(latency) => self.is_slow(latency),
+            Signal::Overloaded => true,
+        };
+
+        if !overloaded {
+            self.limit = (self.limit + 1.0 / self.limit.max(1.0)).min(self.max as f64);
+        } else if worker >= self.decreased_at {
+            self.limit = (self.limit * BACKOFF_FACTOR).max(self.min as f64);
+            self.decreased_at = next_worker;
+            // The best latency was measured under a different load, it is learned again
+            self.best_latency = self.recent_latency;
+        }
+
+        let limit = self.limit();
+        (limit != previous).then_some(limit)
+    }
+
+    fn is_slow(&mut self, latency: Duration) -> bool {
+        let recent = match self.recent_latency {
+            Some(recent) => {
+                recent.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING)
+            }
+            None => latency,
+        };
+        self.recent_latency = Some(recent);
+
+        let best = self.best_latency.map_or(recent, |best| best.min(recent));
+        self.best_latency = Some(best);
+
+        recent > LATENCY_FLOOR && recent > best.mul_f64(LATENCY_TOLERANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAST: Duration = Duration::from_millis(10);
+
+    fn worker(id: usize) -> WorkerId {
+        let mut worker = WorkerId::default();
+        (0..id).for_each(|_| worker.next());
+        worker
+    }
+
+    #[test]
+    fn bounds_never_go_below_one_worker() {
+        assert_eq!(ConcurrencyLimit::new(1, 0).limit(), 1);
+        assert_eq!(ConcurrencyLimit::new(0, 0).limit(), 1);
+        assert_eq!(ConcurrencyLimit::new(4, 0).limit(), 1);
+        assert_eq!(ConcurrencyLimit::new(0, 3).limit(), 3);
+        assert_eq!(ConcurrencyLimit::new(5, 3).limit(), 3);
+    }
+
+    #[test]
+    fn overload_halves_down_to_min() {
+        let mut limit = ConcurrencyLimit::new(2, 8);
+        assert_eq!(
+            limit.observe(worker(0), worker(1), Signal::Overloaded),
+            Some(4)
+        );
+        assert_eq!(
+            limit.observe(worker(1), worker(2), Signal::Overloaded),
+            Some(2)
+        );
+        assert_eq!(
+            limit.observe(worker(2), worker(3), Signal::Overloaded),
+            None
+        );
+        assert_eq!(limit.limit(), 2);
+    }
+
+    #[test]
+    fn workers_in_flight_before_a_decrease_dont_decrease_again() {
+        let mut limit = ConcurrencyLimit::new(1, 8);
+        assert_eq!(
+            limit.observe(worker(0), worker(5), Signal::Overloaded),
+            Some(4)
+        );
+        assert_eq!(
+            limit.observe(worker(3), worker(6), Signal::Overloaded),
+            None
+        );
+        assert_eq!(
+            limit.observe(worker(4), worker(6), Signal::Overloaded),
+            None
+        );
+        assert_eq!(
+            limit.observe(worker(5), worker(7), Signal::Overloaded),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn healthy_pages_grow_one_worker_per_window() {
+        let mut limit = ConcurrencyLimit::new(1, 4);
+        limit.observe(worker(0), worker(1), Signal::Overloaded);
+        assert_eq!(limit.limit(), 2);
+
+        // 2 + 1/2 + 1/2.5 is still short of 3, the third healthy page gets it there
+        assert_eq!(
+            limit.observe(worker(1), worker(2), Signal::Healthy(FAST)),
+            None
+        );
+        assert_eq!(
+            limit.observe(worker(2), worker(3), Signal::Healthy(FAST)),
+            None
+        );
+        assert_eq!(
+            limit.observe(worker(3), worker(4), Signal::Healthy(FAST)),
+            Some(3)
+        );
+
+        for id in 4..20 {
+            limit.observe(worker(id), worker(id + 1), Signal::Healthy(FAST));
+        }
+        assert_eq!(limit.limit(), 4);
+    }
+
+    #[test]
+    fn latency_far_above_the_best_counts_as_overload() {
+        let mut limit = ConcurrencyLimit::new(1, 8);
+        let best = Duration::from_millis(200);
+        assert_eq!(
+            limit.observe(worker(0), worker(1), Signal::Healthy(best)),
+            None
+        );
+
+        let slow = Duration::from_secs(2);
+        let decreased =
+            (1..10).find_map(|id| limit.observe(worker(id), worker(id + 1), Signal::Healthy(slow)));
+        assert_eq!(decreased, Some(4));
+    }
+}
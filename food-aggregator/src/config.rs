@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use derive_more::{Display, Error, From};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use sqlx::PgPool;
+
+use crate::http::HttpConfig;
+use crate::rate_limit::RateLimit;
+use crate::schedule::{self, Schedule};
+use crate::sink::{FoodSink, IngestMethod, PostgresSink, SnapshotFormat, SnapshotSink};
+use crate::supervisor::SupervisorConfig;
+
+#[derive(Debug, Display, Error, From)]
+pub enum ConfigError {
+    #[from]
+    Io(std::io::Error),
+    #[from]
+    Http(reqwest::Error),
+    #[from]
+    Parse(toml::de::Error),
+    #[from]
+    ParseYaml(serde_yaml_ng::Error),
+    #[display("no food source registered with name `{_0}`")]
+    #[error(ignore)]
+    UnknownSource(String),
+    #[display("food source `{_0}` does not read files, so it can't import one")]
+    #[error(ignore)]
+    NotFileSource(String),
+    #[display("invalid options for food source `{source}`: {error}")]
+    InvalidOptions {
+        #[error(ignore)]
+        source: String,
+        error: toml::de::Error,
+    },
+}
+
+/// Configuration for every food source the aggregator knows about, keyed by the name the source
+/// was registered with in the [`AggregatorRegistry`](crate::registry::AggregatorRegistry).
+///
+/// ```toml
+/// [http]
+/// read_timeout_secs = 60
+///
+/// [sources.usda]
+/// enabled = true
+/// min_workers = 1
+/// max_workers = 10
+/// max_retries = 3
+/// commit = { strategy = "batch", pages = 20 }
+/// restart = { max_restarts = 3, backoff = "1m", max_backoff = "1h" }
+/// schedule = { strategy = "interval", every = "30d" }
+/// rate_limit = { requests = 30, per = "1h" }
+/// sink = { kind = "snapshot", format = "parquet", path = "snapshots" }
+/// page_size = 200
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggregatorConfig {
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceConfig>,
+}
+
+impl AggregatorConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Loads the configuration file pointed by `AGGREGATOR_CONFIG`, falling back to the default
+    /// configuration when the variable is not set.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        match dotenvy::var("AGGREGATOR_CONFIG") {
+            Ok(path) => Self::from_file(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Turns every source into dry-run mode, see [`SourceConfig::dry_run`]
+    pub fn into_dry_run(mut self) -> Self {
+        for source in self.sources.values_mut() {
+            source.dry_run = true;
+        }
+        self
+    }
+
+    pub fn enabled_sources(&self) -> impl Iterator<Item = (&str, &SourceConfig)> {
+        self.sources
+            .iter()
+            .filter(|(_, config)| config.enabled)
+            .map(|(name, config)| (name.as_str(), config))
+    }
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        let sources = BTreeMap::from([(String::from("usda"), SourceConfig::default())]);
+        Self {
+            http: HttpConfig::default(),
+            sources,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceConfig {
+    /// Factory the source is built with, defaults to the name of the source. Lets generic
+    /// sources like `mapped` be configured more than once under different names
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Workers the supervisor keeps fetching pages no matter how overloaded the source looks
+    #[serde(default = "default_min_workers")]
+    pub min_workers: usize,
+    /// Workers the supervisor starts with, backing off when the source gets slow or fails
+    #[serde(default = "default_max_workers")]
+    pub max_workers: usize,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: usize,
+    #[serde(default)]
+    pub commit: CommitPolicy,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// When the scheduler aggregates the source, every 30 days by default
+    #[serde(default)]
+    pub schedule: Schedule,
+    /// Requests the source accepts, sources fetched over the network fall back to the limit
+    /// their API documents
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Fetches every page but only records how it differs from the live tables instead of
+    /// persisting it
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Where the fetched foods are written to, the live tables by default
+    #[serde(default)]
+    pub sink: SinkConfig,
+    /// Every other key of the source table, interpreted by the source itself
+    #[serde(flatten)]
+    pub options: toml::Table,
+}
+
+impl SourceConfig {
+    pub fn supervisor(&self) -> SupervisorConfig {
+        SupervisorConfig {
+            min_workers: self.min_workers,
+            max_workers: self.max_workers,
+            max_retries: self.max_retries,
+            commit: self.commit,
+            restart: self.restart,
+            dry_run: self.dry_run,
+        }
+    }
+
+    pub fn options<T: DeserializeOwned>(&self, source: &str) -> Result<T, ConfigError> {
+        toml::Value::Table(self.options.clone())
+            .try_into()
+            .map_err(|error| ConfigError::InvalidOptions {
+                source: source.to_string(),
+                error,
+            })
+    }
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            kind: None,
+            enabled: default_enabled(),
+            min_workers: default_min_workers(),
+            max_workers: default_max_workers(),
+            max_retries: default_max_retries(),
+            commit: CommitPolicy::default(),
+            restart: RestartPolicy::default(),
+            schedule: Schedule::default(),
+            rate_limit: None,
+            dry_run: false,
+            sink: SinkConfig::default(),
+            options: toml::Table::default(),
+        }
+    }
+}
+
+/// Where the foods fetched from a source are written to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SinkConfig {
+    Postgres {
+        #[serde(default)]
+        ingest: IngestMethod,
+    },
+    /// Dataset snapshot files, one file per page under `<path>/<source>/<run id>`
+    Snapshot {
+        path: PathBuf,
+        #[serde(default)]
+        format: SnapshotFormat,
+    },
+}
+
+impl SinkConfig {
+    /// Sink the pages of a run of `source` are written to. Dry runs only ever diff pages against
+    /// the live tables, whatever the sink
+    pub fn build(&self, source: &str, pool: PgPool, dry_run: bool) -> Box<dyn FoodSink> {
+        if dry_run {
+            return Box::new(PostgresSink::new(pool, IngestMethod::default()).with_dry_run(true));
+        }
+
+        match self {
+            SinkConfig::Postgres { ingest } => Box::new(PostgresSink::new(pool, *ingest)),
+            SinkConfig::Snapshot { path, format } => {
+                Box::new(SnapshotSink::new(pool, path.join(source), *format))
+            }
+        }
+    }
+}
+
+impl Default for SinkConfig {
+    fn default() -> Self {
+        SinkConfig::Postgres {
+            ingest: IngestMethod::default(),
+        }
+    }
+}
+
+/// Controls when pages persisted by the supervisor become durable
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum CommitPolicy {
+    /// Every page is committed in its own transaction
+    #[default]
+    PerPage,
+    /// Pages share a transaction that is committed every `pages` pages, a page failing to persist
+    /// only rolls back its own savepoint
+    Batch { pages: usize },
+    /// Pages are committed into staging tables, and only merged into the live tables once every
+    /// page of the run was fetched
+    Staged,
+}
+
+/// How an aggregation that failed or panicked is restarted. The run resumes from its checkpoints,
+/// so a restart only fetches the pages that were not persisted yet.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RestartPolicy {
+    /// Restarts in a row before giving up on the source until it is triggered again
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: usize,
+    /// Wait before the first restart, doubling with every restart in a row
+    #[serde(default = "default_backoff", deserialize_with = "schedule::duration")]
+    pub backoff: Duration,
+    #[serde(
+        default = "default_max_backoff",
+        deserialize_with = "schedule::duration"
+    )]
+    pub max_backoff: Duration,
+}
+
+impl RestartPolicy {
+    /// Wait before restarting an aggregation that already restarted `restarts` times in a row,
+    /// `None` once it ran out of restarts
+    pub fn delay(&self, restarts: usize) -> Option<Duration> {
+        if restarts >= self.max_restarts {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(restarts as u32);
+        Some(self.backoff.saturating_mul(factor).min(self.max_backoff))
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: default_max_restarts(),
+            backoff: default_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_min_workers() -> usize {
+    1
+}
+
+fn default_max_workers() -> usize {
+    10
+}
+
+fn default_max_retries() -> usize {
+    3
+}
+
+fn default_max_restarts() -> usize {
+    3
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(60 * 60)
+}
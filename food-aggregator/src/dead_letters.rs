@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::config::{AggregatorConfig, SourceConfig};
+use crate::models::dead_letters::DeadLetter;
+use crate::registry::{AggregatorRegistry, BoxedAggregator};
+use crate::{Aggregator, AggregatorError};
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplaySummary {
+    pub replayed: usize,
+    pub failed: usize,
+    /// Dead letters left untouched because their source ran out of rate limit budget, or couldn't
+    /// be built from the configuration
+    pub skipped: usize,
+}
+
+/// Replays a single dead letter, removing it from the queue when the page is persisted.
+///
+/// Returns whether the page was replayed successfully, a failed replay is recorded in the dead
+/// letter itself.
+pub async fn replay_dead_letter(
+    pool: PgPool,
+    registry: &AggregatorRegistry,
+    config: &AggregatorConfig,
+    dead_letter: &DeadLetter,
+) -> Result<bool, AggregatorError> {
+    let mut aggregator = build_aggregator(registry, config, &dead_letter.source)?;
+    replay_with(aggregator.as_mut(), &pool, dead_letter).await
+}
+
+/// Replays every dead letter, or only the ones of `source`.
+#[tracing::instrument(skip(pool, registry, config))]
+pub async fn replay_dead_letters(
+    pool: PgPool,
+    registry: &AggregatorRegistry,
+    config: &AggregatorConfig,
+    source: Option<&str>,
+) -> Result<ReplaySummary, AggregatorError> {
+    let dead_letters = {
+        let mut conn = pool.acquire().await?;
+        DeadLetter::get_all(conn.as_mut(), source).await?
+    };
+
+    let mut summary = ReplaySummary::default();
+    // Aggregators are built once per source and reused across its dead letters
+    let mut aggregators = HashMap::<String, BoxedAggregator>::new();
+    // Sources whose remaining dead letters are left untouched
+    let mut skipped = Vec::<String>::new();
+
+    for dead_letter in dead_letters.iter() {
+        if skipped.contains(&dead_letter.source) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let aggregator = match aggregators.get_mut(&dead_letter.source) {
+            Some(aggregator) => aggregator,
+            None => match build_aggregator(registry, config, &dead_letter.source) {
+                Ok(aggregator) => aggregators
+                    .entry(dead_letter.source.clone())
+                    .or_insert(aggregator),
+                Err(e) => {
+                    tracing::warn!(
+                        source = %dead_letter.source,
+                        error = ?e,
+                        "Failed to build source, skipping its dead letters"
+                    );
+                    skipped.push(dead_letter.source.clone());
+                    summary.skipped += 1;
+                    continue;
+                }
+            },
+        };
+
+        match replay_with(aggregator.as_mut(), &pool, dead_letter).await {
+            Ok(true) => summary.replayed += 1,
+            Ok(false) => summary.failed += 1,
+            Err(AggregatorError::UnexpectedRateLimit) => {
+                tracing::warn!(source = %dead_letter.source, "Rate limited while replaying");
+                skipped.push(dead_letter.source.clone());
+                summary.skipped += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    tracing::info!(?summary, "Finished replaying dead letters");
+    Ok(summary)
+}
+
+async fn replay_with(
+    aggregator: &mut dyn Aggregator,
+    pool: &PgPool,
+    dead_letter: &DeadLetter,
+) -> Result<bool, AggregatorError> {
+    let page = dead_letter.page as usize;
+    let result = aggregator
+        .replay(pool.clone(), dead_letter.run_id, page)
+        .await;
+    let mut conn = pool.acquire().await?;
+
+    match result {
+        Ok(()) => {
+            DeadLetter::delete(conn.as_mut(), dead_letter.id).await?;
+            Ok(true)
+        }
+        Err(AggregatorError::UnexpectedRateLimit) => Err(AggregatorError::UnexpectedRateLimit),
+        Err(e) => {
+            tracing::error!(id = %dead_letter.id, %page, error = ?e, "Failed to replay dead letter");
+            DeadLetter::record_failed_replay(conn.as_mut(), dead_letter.id, &e.to_string()).await?;
+            Ok(false)
+        }
+    }
+}
+
+fn build_aggregator(
+    registry: &AggregatorRegistry,
+    config: &AggregatorConfig,
+    source: &str,
+) -> Result<BoxedAggregator, AggregatorError> {
+    // Dead letters of sources that were since disabled or removed from the configuration can
+    // still be replayed with the default settings
+    let default_config = SourceConfig::default();
+    let source_config = config.sources.get(source).unwrap_or(&default_config);
+    Ok(registry.build(source, source_config)?)
+}
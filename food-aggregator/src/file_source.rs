@@ -0,0 +1,257 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::OnceCell;
+
+use crate::SourceError;
+
+/// Where each page of a file starts and ends, along with whatever else reading a page needs,
+/// like the header of a CSV table
+#[derive(Debug)]
+pub struct FileIndex<H> {
+    pub header: H,
+    pub pages: Vec<Range<u64>>,
+}
+
+/// A file on disk read one page at a time. The file is scanned once, on the first fetch, to find
+/// the byte range of every page, so a fetch only ever parses its own page no matter how large the
+/// file is.
+#[derive(Debug)]
+pub struct PagedFile<H> {
+    path: PathBuf,
+    total_pages: AtomicUsize,
+    index: OnceCell<Arc<FileIndex<H>>>,
+}
+
+impl<H> PagedFile<H>
+where
+    H: Send + Sync + 'static,
+{
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            total_pages: AtomicUsize::new(0),
+            index: OnceCell::new(),
+        }
+    }
+
+    pub fn is_finished(&self, current_page: usize) -> bool {
+        current_page > self.total_pages.load(Ordering::SeqCst)
+    }
+
+    /// Reads `current_page` with `read`, given the index header and the bytes of the page. The
+    /// file is indexed with `index` on the first call, pages past its end are empty
+    pub async fn read<T, I, R>(
+        &self,
+        current_page: usize,
+        index: I,
+        read: R,
+    ) -> Result<(usize, T), SourceError>
+    where
+        T: Default + Send + 'static,
+        I: FnOnce(&Path) -> Result<FileIndex<H>, SourceError> + Send + 'static,
+        R: FnOnce(&Path, &H, Vec<u8>) -> Result<T, SourceError> + Send + 'static,
+    {
+        let file_index = self
+            .index
+            .get_or_try_init(|| {
+                let path = self.path.clone();
+                async move {
+                    let file_index = blocking(move || index(&path)).await?;
+                    tracing::info!(pages = %file_index.pages.len(), "Indexed source file");
+                    Ok::<_, SourceError>(Arc::new(file_index))
+                }
+            })
+            .await?
+            .clone();
+
+        let total_pages = file_index.pages.len();
+        self.total_pages.store(total_pages, Ordering::SeqCst);
+
+        let Some(range) = current_page
+            .checked_sub(1)
+            .and_then(|page| file_index.pages.get(page))
+            .cloned()
+        else {
+            return Ok((total_pages, T::default()));
+        };
+
+        let path = self.path.clone();
+        let page = blocking(move || {
+            let bytes = read_range(&path, range)?;
+            read(&path, &file_index.header, bytes)
+        })
+        .await?;
+
+        Ok((total_pages, page))
+    }
+}
+
+/// Runs blocking file work off the async runtime
+pub async fn blocking<T, F>(work: F) -> Result<T, SourceError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, SourceError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| SourceError::Decode(format!("file task failed: {e}")))?
+}
+
+pub fn read_range(path: &Path, range: Range<u64>) -> Result<Vec<u8>, SourceError> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+
+    let mut buffer = Vec::with_capacity((range.end - range.start) as usize);
+    file.take(range.end - range.start)
+        .read_to_end(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+/// Groups the items of a file, like lines, rows or json objects, into pages of `page_size` items.
+/// A page spans from the start of its first item to the end of its last one.
+#[derive(Debug)]
+pub struct PageSplitter {
+    page_size: usize,
+    pages: Vec<Range<u64>>,
+    current: Option<Range<u64>>,
+    items: usize,
+}
+
+impl PageSplitter {
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            page_size: page_size.max(1),
+            pages: vec![],
+            current: None,
+            items: 0,
+        }
+    }
+
+    /// Adds an item spanning `range`, starting a new page when the current one is full
+    pub fn push(&mut self, range: Range<u64>) {
+        if self.items == self.page_size
+            && let Some(page) = self.current.take()
+        {
+            self.pages.push(page);
+            self.items = 0;
+        }
+
+        match &mut self.current {
+            Some(page) => page.end = range.end,
+            None => self.current = Some(range),
+        }
+        self.items += 1;
+    }
+
+    /// Extends the last item up to `end`, for items spanning more than one row
+    pub fn extend(&mut self, end: u64) {
+        if let Some(page) = &mut self.current {
+            page.end = end;
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Range<u64>> {
+        self.pages.extend(self.current);
+        self.pages
+    }
+}
+
+/// Splits a line delimited file into pages of `page_size` lines, skipping blank lines and the
+/// first `skip` bytes, like a header
+pub fn index_lines(
+    path: &Path,
+    skip: u64,
+    page_size: usize,
+) -> Result<Vec<Range<u64>>, SourceError> {
+    use std::io::BufRead;
+
+    let mut reader = std::io::BufReader::with_capacity(1 << 20, File::open(path)?);
+    reader.seek(SeekFrom::Start(skip))?;
+
+    let mut splitter = PageSplitter::new(page_size);
+    let mut offset = skip;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+
+        let start = offset;
+        offset += read as u64;
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            splitter.push(start..offset);
+        }
+    }
+
+    Ok(splitter.finish())
+}
+
+/// Splits the records of a CSV file into pages of `page_size` records, returning the header of
+/// the file along with the pages. `key` picks the column identifying the item a record belongs
+/// to, consecutive records of the same item always end up in the same page
+pub fn index_csv(
+    path: &Path,
+    builder: &csv::ReaderBuilder,
+    page_size: usize,
+    key: impl FnOnce(&[String]) -> Result<Option<usize>, SourceError>,
+) -> Result<(Vec<String>, Vec<Range<u64>>), SourceError> {
+    let mut reader = builder.from_path(path).map_err(csv_error)?;
+    let headers = csv_headers(&mut reader)?;
+    let key = key(&headers)?;
+
+    let mut splitter = PageSplitter::new(page_size);
+    let mut record = csv::ByteRecord::new();
+    let mut last_key = None::<Vec<u8>>;
+    loop {
+        let start = reader.position().byte();
+        if !reader.read_byte_record(&mut record).map_err(csv_error)? {
+            break;
+        }
+        let end = reader.position().byte();
+
+        let record_key = key.and_then(|key| record.get(key));
+        match record_key {
+            Some(record_key) if last_key.as_deref() == Some(record_key) => splitter.extend(end),
+            _ => splitter.push(start..end),
+        }
+        last_key = record_key.map(<[u8]>::to_vec);
+    }
+
+    Ok((headers, splitter.finish()))
+}
+
+pub fn csv_headers<R: std::io::Read>(
+    reader: &mut csv::Reader<R>,
+) -> Result<Vec<String>, SourceError> {
+    let headers = reader
+        .byte_headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(|header| {
+            // Tables exported from spreadsheets often start with a byte order mark
+            let header = String::from_utf8_lossy(header);
+            header.trim_start_matches('\u{feff}').trim().to_string()
+        })
+        .collect();
+
+    Ok(headers)
+}
+
+pub fn column_position(headers: &[String], column: &str) -> Result<usize, SourceError> {
+    headers
+        .iter()
+        .position(|header| header == column)
+        .ok_or_else(|| SourceError::Decode(format!("column `{column}` not found in table header")))
+}
+
+pub fn csv_error(error: csv::Error) -> SourceError {
+    SourceError::Decode(error.to_string())
+}
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+
+use crate::SourceError;
+
+/// Settings of the HTTP client shared by every food source.
+///
+/// ```toml
+/// [http]
+/// connect_timeout_secs = 10
+/// read_timeout_secs = 60
+/// user_agent = "myfitself-food-aggregator"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+}
+
+impl HttpConfig {
+    pub fn build_client(&self) -> Result<Client, reqwest::Error> {
+        let keep_alive = Duration::from_secs(self.keep_alive_secs);
+
+        Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(self.read_timeout_secs))
+            .tcp_keepalive(keep_alive)
+            .pool_idle_timeout(keep_alive)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .user_agent(&self.user_agent)
+            .gzip(true)
+            .brotli(true)
+            .build()
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+            keep_alive_secs: default_keep_alive_secs(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_read_timeout_secs() -> u64 {
+    60
+}
+
+fn default_keep_alive_secs() -> u64 {
+    90
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    16
+}
+
+fn default_user_agent() -> String {
+    format!("myfitself-food-aggregator/{}", env!("CARGO_PKG_VERSION"))
+}
+
+impl From<reqwest::Error> for SourceError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return SourceError::Timeout;
+        }
+
+        if error.is_decode() {
+            return SourceError::Decode(error.to_string());
+        }
+
+        match error.status() {
+            Some(status) if status.is_server_error() => SourceError::Server {
+                status,
+                retry_after: None,
+            },
+            Some(status) if status.is_client_error() => SourceError::Client { status },
+            _ => SourceError::Transport(error),
+        }
+    }
+}
+
+/// Turns a non-successful response into the matching [`SourceError`].
+pub async fn error_for_status(response: Response) -> Result<Response, SourceError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+
+    let error = match status {
+        StatusCode::TOO_MANY_REQUESTS => SourceError::RateLimited { retry_after },
+        status if status.is_server_error() => SourceError::Server {
+            status,
+            retry_after,
+        },
+        status => SourceError::Client { status },
+    };
+
+    // The body usually explains why the request was rejected, but it is not worth failing over
+    match response.text().await {
+        Ok(body) => tracing::warn!(%status, %body, "Source responded with an error"),
+        Err(e) => tracing::warn!(%status, error = ?e, "Source responded with an error"),
+    }
+
+    Err(error)
+}
+
+/// Parses a `Retry-After` header, which is either an amount of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
@@ -0,0 +1,103 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Connection, PgConnection, PgPool};
+
+/// Name the advisory lock key is hashed from, every instance sharing the database competes for
+/// the same key
+const LEASE_NAME: &str = "food-aggregator:scheduler";
+/// Prefix of the names source leases are hashed from, followed by the name of the source
+const SOURCE_LEASE_PREFIX: &str = "food-aggregator:source:";
+
+/// Session holding a lease, the pid alone could be reused by a later session
+#[derive(Debug, Clone, Copy)]
+pub struct LeaseHolder {
+    pub pid: i32,
+    pub backend_start: DateTime<Utc>,
+}
+
+/// Exclusive right over an aggregation among the instances sharing the database, either
+/// leadership over the scheduled aggregation or over a single source.
+///
+/// Backed by a session level advisory lock held on a connection taken out of the pool. The lock
+/// lives as long as that connection, so when the holder dies Postgres releases it as soon as the
+/// session ends and another instance takes over on its next attempt.
+pub struct AggregationLease {
+    conn: PgConnection,
+    name: String,
+}
+
+impl AggregationLease {
+    /// Takes the scheduler lease without waiting, `None` when another instance holds it
+    pub async fn try_acquire(pool: &PgPool) -> sqlx::Result<Option<Self>> {
+        Self::try_acquire_named(pool, LEASE_NAME.to_string()).await
+    }
+
+    /// Takes the lease over `source` without waiting, `None` when another instance is already
+    /// aggregating the source
+    pub async fn try_acquire_source(pool: &PgPool, source: &str) -> sqlx::Result<Option<Self>> {
+        Self::try_acquire_named(pool, source_lease_name(source)).await
+    }
+
+    /// Session currently holding the lease over `source`, `None` when nobody is aggregating it
+    pub async fn source_holder(
+        executor: &mut PgConnection,
+        source: &str,
+    ) -> sqlx::Result<Option<LeaseHolder>> {
+        // Advisory locks on a bigint key show up in pg_locks split into its two halves
+        sqlx::query_as!(
+            LeaseHolder,
+            r#"
+            SELECT
+                l.pid AS "pid!",
+                a.backend_start AS "backend_start!"
+            FROM
+                pg_locks l
+                JOIN pg_stat_activity a ON a.pid = l.pid
+            WHERE
+                l.locktype = 'advisory'
+                AND l.granted
+                AND l.objsubid = 1
+                AND ((l.classid::bigint << 32) | l.objid::bigint) = hashtextextended($1, 0)
+            LIMIT 1;
+            "#,
+            source_lease_name(source)
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    async fn try_acquire_named(pool: &PgPool, name: String) -> sqlx::Result<Option<Self>> {
+        // A connection returned to the pool would keep the lock alive after the lease is gone
+        let mut conn = pool.acquire().await?.detach();
+
+        let acquired = sqlx::query_scalar!(
+            r#"SELECT pg_try_advisory_lock(hashtextextended($1, 0)) AS "acquired!";"#,
+            name
+        )
+        .fetch_one(&mut conn)
+        .await?;
+
+        if !acquired {
+            conn.close().await?;
+            return Ok(None);
+        }
+
+        Ok(Some(Self { conn, name }))
+    }
+
+    /// Hands the lease over to the other instances, dropping the lease releases it as well once
+    /// the connection is gone
+    pub async fn release(mut self) -> sqlx::Result<()> {
+        sqlx::query_scalar!(
+            r#"SELECT pg_advisory_unlock(hashtextextended($1, 0)) AS "released!";"#,
+            self.name
+        )
+        .fetch_one(&mut self.conn)
+        .await?;
+
+        self.conn.close().await
+    }
+}
+
+fn source_lease_name(source: &str) -> String {
+    format!("{SOURCE_LEASE_PREFIX}{source}")
+}
@@ -1,20 +1,48 @@
+mod aggregator;
+mod concurrency;
+pub mod config;
+pub mod dead_letters;
+mod file_source;
+pub mod http;
+mod lease;
+mod mapped;
 pub mod models;
+pub mod monitor;
+pub mod nutrient_catalog;
+mod open_food_facts;
+pub mod rate_limit;
+pub mod registry;
+pub mod schedule;
+pub mod shutdown;
+pub mod sink;
 mod supervisor;
 mod usda;
 
-use std::collections::BinaryHeap;
+use std::any::Any;
+use std::collections::{BinaryHeap, HashMap};
+use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
-use chrono::Duration;
+use chrono::TimeDelta;
+use config::{AggregatorConfig, ConfigError, RestartPolicy, SourceConfig};
 use derive_more::{Display, Error, From};
+use futures_util::FutureExt;
+use lease::AggregationLease;
+use models::aggregation_history::{AggregationHistory, AggregationOutcome};
 use models::aggregation_metadata::AggregateMetadataModel;
+use models::aggregation_schedules::AggregationSchedule;
+use monitor::{AggregationMonitor, ProgressEventKind, SourceState};
+use registry::{AggregatorRegistry, BoxedAggregator};
+use sqlx::PgPool;
+use sqlx::types::Uuid;
 use sqlx::types::chrono::Utc;
-use sqlx::{PgConnection, PgPool};
 use supervisor::{FoodData, SupervisorError};
 use tokio::sync::{Mutex, Notify};
 use tokio::time::Instant;
-use usda::{UsdaAggregator, UsdaClient};
+use tokio_util::sync::CancellationToken;
 
 pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
@@ -22,8 +50,52 @@ pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 pub enum SourceError {
     #[from]
     Database(sqlx::Error),
+    #[display("request to the source timed out")]
+    Timeout,
+    #[display("rate limited by the source, retry after {retry_after:?}")]
+    RateLimited {
+        retry_after: Option<std::time::Duration>,
+    },
+    #[display("source failed with status {status}")]
+    Server {
+        status: reqwest::StatusCode,
+        retry_after: Option<std::time::Duration>,
+    },
+    #[display("source rejected the request with status {status}")]
+    Client { status: reqwest::StatusCode },
+    #[display("failed to decode source data: {_0}")]
+    #[error(ignore)]
+    Decode(String),
+    #[display("failed to reach the source: {_0}")]
+    Transport(reqwest::Error),
+    #[display("failed to read the source file: {_0}")]
     #[from]
-    Deserialize(reqwest::Error),
+    Io(std::io::Error),
+}
+
+impl SourceError {
+    /// Whether fetching the same page again could succeed, errors like a malformed page or a
+    /// rejected request will fail the same way no matter how many times they are retried
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SourceError::Database(_)
+            | SourceError::Timeout
+            | SourceError::RateLimited { .. }
+            | SourceError::Server { .. }
+            | SourceError::Transport(_) => true,
+            SourceError::Client { .. } | SourceError::Decode(_) | SourceError::Io(_) => false,
+        }
+    }
+
+    /// How long the source asked us to wait before trying again
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            SourceError::RateLimited { retry_after } | SourceError::Server { retry_after, .. } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
 }
 
 pub trait FoodSource: Send + Sync {
@@ -38,12 +110,16 @@ pub trait FoodSource: Send + Sync {
 pub enum AggregateStatus {
     Finished,
     PendingUntil(Instant),
+    /// Stopped before finishing, the run resumes from its checkpoints the next time it runs
+    Cancelled,
 }
 
 #[derive(Debug, Display, Error, From)]
 pub enum AggregatorError {
     UnexpectedRateLimit,
     #[from]
+    Config(ConfigError),
+    #[from]
     Database(sqlx::Error),
     #[from]
     Supervisor(SupervisorError),
@@ -52,14 +128,101 @@ pub enum AggregatorError {
 }
 
 pub trait Aggregator: Send + Sync {
-    fn aggregate(&mut self, conn: PgPool) -> BoxFuture<Result<AggregateStatus, AggregatorError>>;
+    /// Aggregates the source until it finishes, gets rate limited or `cancel` is cancelled, in
+    /// which case the pages in flight are persisted before returning
+    fn aggregate(
+        &mut self,
+        conn: PgPool,
+        cancel: CancellationToken,
+    ) -> BoxFuture<'_, Result<AggregateStatus, AggregatorError>>;
+
+    /// How the aggregation is restarted when it fails or panics
+    fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy::default()
+    }
+
+    /// Fetches and persists a single page of a previous run again, used to replay dead letters
+    fn replay(
+        &mut self,
+        conn: PgPool,
+        run_id: Uuid,
+        page: usize,
+    ) -> BoxFuture<'_, Result<(), AggregatorError>>;
 }
 
 struct ScheduledAggregator {
-    aggregator: Box<dyn Aggregator + Send + Sync>,
+    name: String,
+    aggregator: BoxedAggregator,
+    cancel: CancellationToken,
+    /// Restarts in a row after failing or panicking, reset once an attempt goes through
+    restarts: usize,
     wake_time: Instant,
 }
 
+impl ScheduledAggregator {
+    /// Runs the aggregator once, turning a panic into an error recorded in the run history
+    async fn attempt(&mut self, pool: &PgPool) -> Result<AggregateStatus, String> {
+        // Aggregators keep their progress in the checkpoints rather than in themselves, so one
+        // that panicked can safely run again
+        let aggregate = self.aggregator.aggregate(pool.clone(), self.cancel.clone());
+        match AssertUnwindSafe(aggregate).catch_unwind().await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(panic) => {
+                let error = format!("aggregation panicked: {}", panic_message(&*panic));
+                record_panic(pool, &self.name, &error).await;
+                Err(error)
+            }
+        }
+    }
+
+    /// Schedules a restart of the aggregation that failed with `error` according to its restart
+    /// policy, returning whether it has to be queued again
+    fn restart(&mut self, monitor: &AggregationMonitor, error: String) -> bool {
+        let source = monitor.source(&self.name);
+        let restarts = self.restarts;
+
+        // Nothing left to restart for once the aggregation is cancelled
+        if self.cancel.is_cancelled() {
+            tracing::error!(source = %self.name, %error, "Aggregation failed");
+            monitor.fail(&self.name, error);
+            return false;
+        }
+
+        let Some(delay) = self.aggregator.restart_policy().delay(restarts) else {
+            tracing::error!(
+                source = %self.name,
+                %error,
+                restarts,
+                alert = true,
+                "Aggregation failed, giving up"
+            );
+            source.publish(ProgressEventKind::RestartsExhausted {
+                restarts,
+                error: error.clone(),
+            });
+            monitor.fail(&self.name, error);
+            return false;
+        };
+
+        tracing::warn!(
+            source = %self.name,
+            %error,
+            restarts,
+            ?delay,
+            "Aggregation failed, restarting"
+        );
+        source.publish(ProgressEventKind::RestartScheduled {
+            restarts: restarts + 1,
+            delay_ms: delay.as_millis() as u64,
+            error,
+        });
+        self.restarts += 1;
+        self.wake_time = Instant::now() + delay;
+        monitor.wait(&self.name, self.wake_time);
+        true
+    }
+}
+
 impl Ord for ScheduledAggregator {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         other.wake_time.cmp(&self.wake_time)
@@ -80,29 +243,235 @@ impl PartialEq for ScheduledAggregator {
 
 impl Eq for ScheduledAggregator {}
 
+/// Error a source fails with when another instance is already aggregating it
+const LEASE_HELD_ERROR: &str = "source is being aggregated by another instance";
+
+/// How long an instance standing by waits before trying to take over the aggregation lease
+const LEASE_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+/// How long a scheduled source that didn't finish waits before it is aggregated again
+const FAILED_SOURCE_RETRY_DELAY: TimeDelta = TimeDelta::minutes(15);
+
+/// Aggregates every enabled source whose schedule is due, returning when the next source is
+/// due so the caller knows when to come back. Only the instance holding the aggregation lease
+/// runs the schedules, the others stand by until they get to take it.
+///
+/// Sources interrupted by `cancel` stay due, so their runs resume the next time this is called.
+/// Only sources that finish move on to their next scheduled run, the others are retried after a
+/// short delay.
 #[tracing::instrument(skip_all)]
-pub async fn aggregate_food_data(pool: PgPool) -> Result<AggregateStatus, AggregatorError> {
+pub async fn aggregate_food_data(
+    pool: PgPool,
+    registry: &AggregatorRegistry,
+    config: &AggregatorConfig,
+    cancel: &CancellationToken,
+) -> Result<AggregateStatus, AggregatorError> {
+    let Some(lease) = AggregationLease::try_acquire(&pool).await? else {
+        tracing::info!("Another instance holds the aggregation lease, standing by");
+        return Ok(AggregateStatus::PendingUntil(
+            Instant::now() + LEASE_RETRY_DELAY,
+        ));
+    };
+
+    let status = run_due_sources(pool, registry, config, cancel).await;
+    if let Err(e) = lease.release().await {
+        tracing::warn!(error = ?e, "Failed to release the aggregation lease");
+    }
+
+    status
+}
+
+async fn run_due_sources(
+    pool: PgPool,
+    registry: &AggregatorRegistry,
+    config: &AggregatorConfig,
+    cancel: &CancellationToken,
+) -> Result<AggregateStatus, AggregatorError> {
     tracing::info!("Starting aggregation workflow");
+    let now = Utc::now();
+    let stored = {
+        let mut conn = pool.acquire().await?;
+        AggregationSchedule::get_all(conn.as_mut()).await?
+    };
+    let stored = stored
+        .into_iter()
+        .map(|schedule| (schedule.source.clone(), schedule))
+        .collect::<HashMap<_, _>>();
+
+    let mut due = vec![];
+    let mut next_runs = vec![];
     let mut conn = pool.acquire().await?;
+    for (name, source) in config.enabled_sources() {
+        let declared = source.schedule.to_string();
+        let stored = stored.get(name);
+        let last_run = stored.and_then(|stored| stored.last_run_at);
+        let next_run = match (stored, last_run) {
+            (Some(stored), _) if stored.schedule == declared => stored.next_run_at,
+            (_, Some(last_run)) => source.schedule.next_run(last_run, now),
+            (_, None) => source.schedule.first_run(now),
+        };
+
+        if next_run.is_some_and(|next_run| next_run <= now) {
+            due.push((name, source, last_run));
+            continue;
+        }
 
-    if !should_run_aggregation(&mut conn).await? {
-        return Ok(calculate_next_run_time(&mut conn).await?);
+        AggregationSchedule::upsert(conn.as_mut(), name, &declared, last_run, next_run).await?;
+        next_runs.extend(next_run);
+    }
+    drop(conn);
+
+    if due.is_empty() {
+        tracing::info!("No source is due for aggregation");
+    } else {
+        let sources = due
+            .iter()
+            .map(|(name, source, _)| (*name, *source))
+            .collect::<Vec<_>>();
+        run_sources(pool.clone(), registry, &sources, cancel).await?;
+
+        let mut conn = pool.acquire().await?;
+        for (name, source, last_run) in due {
+            let state = registry
+                .monitor()
+                .source_status(name)
+                .map(|status| status.state);
+            if cancel.is_cancelled() && state == Some(SourceState::Cancelled) {
+                continue;
+            }
+
+            let declared = source.schedule.to_string();
+            let scheduled = source.schedule.next_run(now, Utc::now());
+            // Only a finished aggregation counts as a run, sources that failed, gave up restarting
+            // or were cancelled on their own are tried again after a while instead of waiting for
+            // their next scheduled run
+            let (last_run, next_run) = match state {
+                Some(SourceState::Finished) => (Some(now), scheduled),
+                _ => {
+                    let retry = Utc::now() + FAILED_SOURCE_RETRY_DELAY;
+                    let next_run = scheduled.map_or(retry, |scheduled| scheduled.min(retry));
+                    tracing::warn!(source = %name, %next_run, "Aggregation didn't finish, retrying later");
+                    (last_run, Some(next_run))
+                }
+            };
+
+            AggregationSchedule::upsert(conn.as_mut(), name, &declared, last_run, next_run).await?;
+            next_runs.extend(next_run);
+        }
     }
 
-    let client = UsdaClient::new();
-    let usda_aggregator = UsdaAggregator::new(client);
+    if cancel.is_cancelled() {
+        return Ok(AggregateStatus::Cancelled);
+    }
+
+    let Some(next_run) = next_runs.into_iter().min() else {
+        return Ok(AggregateStatus::Finished);
+    };
 
+    tracing::info!(%next_run, "Next aggregation scheduled");
+    let wait = (next_run - Utc::now()).to_std().unwrap_or_default();
+    Ok(AggregateStatus::PendingUntil(Instant::now() + wait))
+}
+
+/// Runs every enabled source right away and records the aggregation, skipping sources that are
+/// already being aggregated.
+#[tracing::instrument(skip_all)]
+pub async fn run_enabled_aggregators(
+    pool: PgPool,
+    registry: &AggregatorRegistry,
+    config: &AggregatorConfig,
+    cancel: &CancellationToken,
+) -> Result<(), AggregatorError> {
+    let aggregators = registry.build_enabled(config)?;
+    run_built_aggregators(pool, registry.monitor(), config, aggregators, cancel).await
+}
+
+/// Runs the aggregators built from the enabled sources of `config`, like
+/// [`run_enabled_aggregators`] does once it built them
+pub async fn run_built_aggregators(
+    pool: PgPool,
+    monitor: &AggregationMonitor,
+    config: &AggregatorConfig,
+    aggregators: Vec<(String, BoxedAggregator)>,
+    cancel: &CancellationToken,
+) -> Result<(), AggregatorError> {
+    run_aggregators(pool.clone(), monitor, aggregators, cancel).await?;
+    let persisted = config.enabled_sources().any(|(_, source)| !source.dry_run);
+    record_aggregation(&pool, persisted).await
+}
+
+async fn run_sources(
+    pool: PgPool,
+    registry: &AggregatorRegistry,
+    sources: &[(&str, &SourceConfig)],
+    cancel: &CancellationToken,
+) -> Result<(), AggregatorError> {
+    let aggregators = sources
+        .iter()
+        .map(|(name, source)| Ok((name.to_string(), registry.build(name, source)?)))
+        .collect::<Result<Vec<_>, ConfigError>>()?;
+    run_aggregators(pool.clone(), registry.monitor(), aggregators, cancel).await?;
+    let persisted = sources.iter().any(|(_, source)| !source.dry_run);
+    record_aggregation(&pool, persisted).await
+}
+
+/// Records when the sources were last aggregated. Dry runs leave the live tables untouched, so
+/// they don't count as an aggregation
+async fn record_aggregation(pool: &PgPool, persisted: bool) -> Result<(), AggregatorError> {
+    if persisted {
+        let mut conn = pool.acquire().await?;
+        AggregateMetadataModel::create(conn.as_mut()).await?;
+        tracing::info!("Aggregation metadata stored");
+    }
+
+    Ok(())
+}
+
+/// Runs the given aggregators right away, regardless of when the last aggregation happened,
+/// rescheduling each of them until it finishes or is cancelled through the monitor. Every attempt
+/// holds the lease over its source, sources another instance is aggregating fail right away.
+///
+/// Cancelling `cancel` drops the aggregators still waiting and winds down the running ones, which
+/// persist the pages they have in flight before stopping.
+#[tracing::instrument(skip_all)]
+pub async fn run_aggregators(
+    pool: PgPool,
+    monitor: &AggregationMonitor,
+    aggregators: Vec<(String, BoxedAggregator)>,
+    cancel: &CancellationToken,
+) -> Result<(), AggregatorError> {
     let queue = Arc::new(Mutex::new(BinaryHeap::new()));
     let active_handles = Arc::new(Mutex::new(Vec::new()));
+    // A task notifies before its handle reports as finished, so whether work is still running is
+    // tracked separately from the handles
+    let running = Arc::new(AtomicUsize::new(0));
     let notify = Arc::new(Notify::new());
 
-    queue.lock().await.push(ScheduledAggregator {
-        aggregator: Box::new(usda_aggregator),
-        wake_time: Instant::now(),
-    });
+    for (name, aggregator) in aggregators {
+        // Two runs of the same source would fetch and checkpoint the same pages
+        let source_cancel = cancel.child_token();
+        if !monitor.queue(&name, notify.clone(), source_cancel.clone()) {
+            tracing::warn!(source = %name, "Source is already being aggregated, skipping");
+            continue;
+        }
+
+        tracing::info!(source = %name, "Scheduling aggregator");
+        queue.lock().await.push(ScheduledAggregator {
+            name,
+            aggregator,
+            cancel: source_cancel,
+            restarts: 0,
+            wake_time: Instant::now(),
+        });
+    }
 
     loop {
         let mut queue_guard = queue.lock().await;
+        if cancel.is_cancelled() {
+            for task in queue_guard.iter() {
+                monitor.cancel(&task.name);
+            }
+        }
+        queue_guard.retain(|task| !monitor.is_cancelled(&task.name));
         let maybe_task_time = queue_guard.peek().map(|task| task.wake_time);
 
         match maybe_task_time {
@@ -110,95 +479,171 @@ pub async fn aggregate_food_data(pool: PgPool) -> Result<AggregateStatus, Aggreg
             // or a new notification is received, which could be from a task that has a earlier
             // wait time and should become the new binary heap head
             Some(wake_time) if wake_time > Instant::now() => {
+                drop(queue_guard);
                 tokio::select! {
                     _ = tokio::time::sleep_until(wake_time) => {},
                     _ = notify.notified() => {},
+                    _ = cancel.cancelled() => {},
                 }
                 continue;
             }
             // When the task can be started, we add it to the active handles vector and spawn a
             // task for it, the task will notify once its finished, no matter the result
             Some(_) => {
-                let mut task = queue_guard.pop().unwrap();
+                let Some(mut task) = queue_guard.pop() else { continue };
 
                 let pool = pool.clone();
                 let queue = queue.clone();
-                let notify = notify.clone();
+                let task_monitor = monitor.clone();
+                let name = task.name.clone();
+                let guard = RunningGuard::new(running.clone(), notify.clone());
 
+                // Marked before spawning, as a quick task could finish before we get back here
+                monitor.start(&name);
                 let handle = tokio::spawn(async move {
-                    match task.aggregator.aggregate(pool.clone()).await {
-                        Err(_) => {}
+                    // Dropping the guard also covers the task panicking
+                    let _guard = guard;
+                    let monitor = task_monitor;
+
+                    // The monitor only knows about this process, instances sharing the database
+                    // like the scheduler, the API and the CLI are kept apart by the source lease
+                    let lease = match AggregationLease::try_acquire_source(&pool, &task.name).await
+                    {
+                        Ok(Some(lease)) => lease,
+                        Ok(None) => {
+                            tracing::warn!(
+                                source = %task.name,
+                                "Source is being aggregated by another instance, skipping"
+                            );
+                            monitor.fail(&task.name, String::from(LEASE_HELD_ERROR));
+                            return;
+                        }
+                        Err(e) => {
+                            if task.restart(&monitor, e.to_string()) {
+                                queue.lock().await.push(task);
+                            }
+                            return;
+                        }
+                    };
+
+                    let result = task.attempt(&pool).await;
+                    if let Err(e) = lease.release().await {
+                        tracing::warn!(source = %task.name, error = ?e, "Failed to release the source lease");
+                    }
+
+                    match result {
+                        Err(error) => {
+                            if task.restart(&monitor, error) {
+                                queue.lock().await.push(task);
+                            }
+                        }
                         Ok(AggregateStatus::Finished) => {
-                            tracing::info!("Finished aggregation");
+                            tracing::info!(source = %task.name, "Finished aggregation");
+                            monitor.finish(&task.name);
                         }
                         Ok(AggregateStatus::PendingUntil(when)) => {
+                            monitor.wait(&task.name, when);
+                            task.restarts = 0;
                             task.wake_time = when;
                             queue.lock().await.push(task);
                         }
+                        Ok(AggregateStatus::Cancelled) => {
+                            tracing::info!(source = %task.name, "Aggregation cancelled");
+                            monitor.cancelled(&task.name);
+                        }
                     }
-
-                    notify.notify_one();
-                    Ok::<(), AggregatorError>(())
                 });
 
-                active_handles.lock().await.push(handle);
+                active_handles.lock().await.push((name, handle));
             }
             // Drains every completed handle from the handles vector, and waits them to check if
-            // the task succeeded or errored, and if after draining everything, both the active
-            // handles and the queue are empty, we are done syncing
+            // the task succeeded or errored, and if after draining everything, no task is running
+            // and the queue is empty, we are done syncing
             None => {
                 let mut handles_guard = active_handles.lock().await;
                 let (complete, pending) = handles_guard
                     .drain(..)
-                    .partition::<Vec<_>, _>(|h| h.is_finished());
+                    .partition::<Vec<_>, _>(|(_, handle)| handle.is_finished());
 
                 *handles_guard = pending;
 
-                for handle in complete {
-                    if handle.await.is_err() {
-                        todo!();
-                    }
+                for (name, handle) in complete {
+                    // Panics of the aggregator are caught within the task, this is only reached
+                    // when handling its outcome panicked, so there is nothing left to restart
+                    let Err(e) = handle.await else { continue };
+                    let error = format!("aggregation task panicked: {e}");
+                    tracing::error!(
+                        source = %name,
+                        %error,
+                        alert = true,
+                        "Aggregation task panicked"
+                    );
+                    monitor.fail(&name, error.clone());
+                    record_panic(&pool, &name, &error).await;
                 }
 
-                if queue_guard.is_empty() && handles_guard.is_empty() {
+                if queue_guard.is_empty() && running.load(Ordering::SeqCst) == 0 {
                     tracing::info!("No more work to be done");
                     break;
                 }
 
+                // Running tasks need the queue to reschedule themselves before notifying
+                drop(handles_guard);
+                drop(queue_guard);
                 notify.notified().await;
             }
         }
     }
 
-    AggregateMetadataModel::create(conn.as_mut()).await?;
-    tracing::info!("Aggregation metadata stored");
-
-    Ok(AggregateStatus::Finished)
+    Ok(())
 }
 
-async fn should_run_aggregation(conn: &mut PgConnection) -> Result<bool, AggregatorError> {
-    // TODO: probably not just propagate the error up here
-    let last_run_entry =
-        models::aggregation_metadata::AggregateMetadataModel::get_last_run(conn.as_mut()).await?;
-
-    let should_run = match &last_run_entry {
-        Some(entry) => Utc::now() - entry.last_run >= Duration::days(30),
-        None => true,
+/// Ends the attempt of `source` that was cut short by a panic, keeping the panic in its errors
+async fn record_panic(pool: &PgPool, source: &str, error: &str) {
+    let ended = match pool.acquire().await {
+        Ok(mut conn) => {
+            AggregationHistory::end_running(
+                conn.as_mut(),
+                source,
+                AggregationOutcome::Failed,
+                Some(error),
+            )
+            .await
+        }
+        Err(e) => Err(e),
     };
 
-    Ok(should_run)
+    if let Err(e) = ended {
+        tracing::error!(%source, error = ?e, "Failed to record panicked aggregation in history");
+    }
 }
 
-async fn calculate_next_run_time(
-    conn: &mut PgConnection,
-) -> Result<AggregateStatus, AggregatorError> {
-    tracing::info!("Not enough time has passed since last aggregation");
-    let last_run_entry =
-        models::aggregation_metadata::AggregateMetadataModel::get_last_run(conn.as_mut()).await?;
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
 
-    let Some(entry) = last_run_entry else { return Ok(AggregateStatus::Finished) };
-    let next_run_time = entry.last_run + Duration::days(30);
-    let wait_until = (next_run_time - Utc::now()).to_std().unwrap_or_default();
+/// Counts a spawned aggregation as running until it is dropped, waking the scheduling loop
+struct RunningGuard {
+    running: Arc<AtomicUsize>,
+    notify: Arc<Notify>,
+}
 
-    Ok(AggregateStatus::PendingUntil(Instant::now() + wait_until))
+impl RunningGuard {
+    fn new(running: Arc<AtomicUsize>, notify: Arc<Notify>) -> Self {
+        running.fetch_add(1, Ordering::SeqCst);
+        Self { running, notify }
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
 }
@@ -0,0 +1,321 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use food_aggregator::config::{AggregatorConfig, SourceConfig};
+use food_aggregator::models::aggregation_history::AggregationHistory;
+use food_aggregator::models::aggregation_metadata::AggregateMetadataModel;
+use food_aggregator::models::aggregation_runs::AggregationRun;
+use food_aggregator::models::aggregation_schedules::AggregationSchedule;
+use food_aggregator::models::food_diffs::{FoodDiff, FoodDiffReport};
+use food_aggregator::models::rate_limit_buckets::RateLimitBucket;
+use food_aggregator::registry::AggregatorRegistry;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Runs the food aggregation jobs without booting the HTTP API
+#[derive(Debug, Parser)]
+#[command(name = "food-aggregator", version)]
+struct Cli {
+    /// Aggregator configuration file, defaults to the one pointed by `AGGREGATOR_CONFIG`
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Aggregates every enabled source right away, or only the given one
+    Run {
+        #[arg(long)]
+        source: Option<String>,
+    },
+    /// Shows when aggregation last ran, when each source is scheduled to run next, the rate limit
+    /// budget left for each source and the progress of the latest run of each source
+    Status,
+    /// Lists past aggregation attempts with what each of them fetched and wrote
+    History {
+        #[arg(long)]
+        source: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+        /// Prints the history as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Imports a local dataset through a file based source
+    Import {
+        file: PathBuf,
+        #[arg(long)]
+        source: String,
+    },
+    /// Fetches every enabled source, or only the given one, and reports what would change in the
+    /// stored foods without persisting anything
+    DryRun {
+        #[arg(long)]
+        source: Option<String>,
+        /// Prints the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Replays the pages kept in the dead letter queue
+    ReplayFailed {
+        #[arg(long)]
+        source: Option<String>,
+    },
+}
+
+async fn db_connect() -> sqlx::Result<PgPool> {
+    let database_url = dotenvy::var("DATABASE_URL").expect("DATABASE_URL env var must be set");
+
+    let db = PgPoolOptions::new()
+        .max_connections(20)
+        .connect(&database_url)
+        .await?;
+
+    sqlx::migrate!("../api/migrations").run(&db).await?;
+
+    Ok(db)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => AggregatorConfig::from_file(path)?,
+        None => AggregatorConfig::from_env()?,
+    };
+    let registry = AggregatorRegistry::from_config(&config)?;
+    let db = db_connect().await?;
+    // Stopping the CLI lets the pages in flight be persisted, so the run resumes cleanly
+    let cancel = food_aggregator::shutdown::token();
+
+    match cli.command {
+        Command::Run {
+            source: Some(source),
+        } => {
+            let source_config = config.sources.get(&source).cloned().unwrap_or_default();
+            let aggregator = registry.build(&source, &source_config)?;
+            let aggregators = vec![(source, aggregator)];
+            food_aggregator::run_aggregators(db, registry.monitor(), aggregators, &cancel).await?;
+        }
+        Command::Run { source: None } => {
+            food_aggregator::run_enabled_aggregators(db, &registry, &config, &cancel).await?;
+        }
+        Command::Status => print_status(&db).await?,
+        Command::History {
+            source,
+            limit,
+            json,
+        } => {
+            let mut conn = db.acquire().await?;
+            let history = AggregationHistory::list(conn.as_mut(), source.as_deref(), limit).await?;
+
+            if json {
+                println!("{}", serde_json::to_string(&history)?);
+            } else {
+                history.iter().for_each(print_history);
+            }
+        }
+        Command::Import { file, source } => {
+            let source_config = config.sources.get(&source).cloned().unwrap_or_default();
+            let aggregator = registry.build_import(&source, &source_config, &file)?;
+            let aggregators = vec![(source, aggregator)];
+            food_aggregator::run_aggregators(db, registry.monitor(), aggregators, &cancel).await?;
+        }
+        Command::DryRun { source, json } => {
+            let config = config.into_dry_run();
+            let aggregators = match source {
+                Some(source) => {
+                    let source_config = config.sources.get(&source).cloned().unwrap_or_default();
+                    let source_config = SourceConfig {
+                        dry_run: true,
+                        ..source_config
+                    };
+                    vec![(source.clone(), registry.build(&source, &source_config)?)]
+                }
+                None => registry.build_enabled(&config)?,
+            };
+
+            let sources = aggregators
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            food_aggregator::run_aggregators(db.clone(), registry.monitor(), aggregators, &cancel)
+                .await?;
+
+            let mut conn = db.acquire().await?;
+            for source in sources {
+                let Some(run) = AggregationRun::get_latest_dry_run(conn.as_mut(), &source).await?
+                else {
+                    continue;
+                };
+
+                let report = FoodDiff::get_report(conn.as_mut(), run.id).await?;
+                if json {
+                    println!("{}", serde_json::json!({ "run": run, "report": report }));
+                } else {
+                    print_report(&run, &report);
+                }
+            }
+        }
+        Command::ReplayFailed { source } => {
+            let summary = food_aggregator::dead_letters::replay_dead_letters(
+                db,
+                &registry,
+                &config,
+                source.as_deref(),
+            )
+            .await?;
+
+            println!(
+                "replayed: {}, failed: {}, skipped: {}",
+                summary.replayed, summary.failed, summary.skipped
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn print_report(run: &AggregationRun, report: &FoodDiffReport) {
+    let state = match run.finished_at {
+        Some(_) => "finished",
+        None => "unfinished, some pages are still pending",
+    };
+    println!(
+        "{source}: dry run {id} {state}",
+        source = run.source,
+        id = run.id
+    );
+
+    if report.is_empty() {
+        println!("  no changes");
+        return;
+    }
+
+    println!("  new foods: {}", report.added.len());
+    for diff in report.added.iter() {
+        println!("    + [{}] {}", diff.external_id, diff.name);
+    }
+
+    println!("  renamed foods: {}", report.renamed.len());
+    for diff in report.renamed.iter() {
+        let previous_name = diff.previous_name.as_deref().unwrap_or_default();
+        println!(
+            "    ~ [{}] {previous_name} -> {}",
+            diff.external_id, diff.name
+        );
+    }
+
+    println!(
+        "  changed nutrient values: {}",
+        report.nutrients_changed.len()
+    );
+    for diff in report.nutrients_changed.iter() {
+        let previous_value = diff
+            .previous_value
+            .map_or_else(|| String::from("none"), |value| value.to_string());
+        println!(
+            "    ~ [{}] {}: {} {previous_value} -> {} {}",
+            diff.external_id,
+            diff.name,
+            diff.nutrient.as_deref().unwrap_or_default(),
+            diff.value
+                .map_or_else(|| String::from("unknown"), |value| value.to_string()),
+            diff.unit.as_deref().unwrap_or_default(),
+        );
+    }
+
+    println!("  removed foods: {}", report.removed.len());
+    for diff in report.removed.iter() {
+        println!("    - [{}] {}", diff.external_id, diff.name);
+    }
+}
+
+fn print_history(attempt: &AggregationHistory) {
+    let kind = if attempt.dry_run { "dry run" } else { "run" };
+    let duration = attempt.ended_at.map_or_else(
+        || String::from("still running"),
+        |ended_at| format!("took {}s", (ended_at - attempt.started_at).num_seconds()),
+    );
+
+    println!(
+        "{started} {source}: {kind} {run_id} {outcome:?}, {duration}",
+        started = attempt.started_at,
+        source = attempt.source,
+        run_id = attempt.run_id,
+        outcome = attempt.outcome,
+    );
+    println!(
+        "  pages: {} fetched, {} retried, {} failed",
+        attempt.pages_fetched, attempt.pages_retried, attempt.pages_failed
+    );
+    println!(
+        "  foods: {} inserted, {} updated, {} nutrient rows written",
+        attempt.foods_inserted, attempt.foods_updated, attempt.nutrients_written
+    );
+    for error in attempt.errors.iter() {
+        println!("  error: {error}");
+    }
+}
+
+async fn print_status(db: &PgPool) -> sqlx::Result<()> {
+    let mut conn = db.acquire().await?;
+
+    match AggregateMetadataModel::get_last_run(conn.as_mut()).await? {
+        Some(metadata) => println!("last aggregation: {}", metadata.last_run),
+        None => println!("last aggregation: never"),
+    }
+
+    for schedule in AggregationSchedule::get_all(conn.as_mut()).await? {
+        let next_run = schedule
+            .next_run_at
+            .map_or_else(|| String::from("never"), |next_run| next_run.to_string());
+        println!(
+            "{source}: scheduled {declared}, next run {next_run}",
+            source = schedule.source,
+            declared = schedule.schedule,
+        );
+    }
+
+    for bucket in RateLimitBucket::get_all(conn.as_mut()).await? {
+        println!(
+            "{source}: {tokens:.1} requests left as of {refilled_at}",
+            source = bucket.source,
+            tokens = bucket.tokens,
+            refilled_at = bucket.refilled_at,
+        );
+    }
+
+    for run in AggregationRun::get_latest_progress(conn.as_mut()).await? {
+        let state = match run.finished_at {
+            Some(finished_at) => format!("finished at {finished_at}"),
+            None => String::from("in progress"),
+        };
+        let kind = if run.dry_run { "dry run" } else { "run" };
+        let total_pages = run
+            .total_pages
+            .map_or_else(|| String::from("?"), |total| total.to_string());
+
+        println!(
+            "{source}: {kind} {id} started at {started}, {state}, pages {completed}/{total_pages} completed, {failed} failed, {in_flight} in flight",
+            source = run.source,
+            id = run.id,
+            started = run.created_at,
+            completed = run.completed_pages,
+            failed = run.failed_pages,
+            in_flight = run.in_flight_pages,
+        );
+    }
+
+    Ok(())
+}
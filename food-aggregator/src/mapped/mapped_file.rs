@@ -0,0 +1,253 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::mapped_types::{MappedPage, MappedRow, group_rows};
+use super::{ColumnMapping, MappedFormat, MappingSpec};
+use crate::file_source::{self, FileIndex, PageSplitter, PagedFile, column_position, csv_error};
+use crate::{FoodSource, SourceError};
+
+/// Reads a food composition table from disk according to a [`MappingSpec`].
+///
+/// The table is scanned once to split its rows into pages of whole foods, so every fetch only
+/// parses the rows of its own page and a food is never split across pages.
+pub struct MappedFile {
+    /// Indexed with the header of CSV tables, empty for JSONL tables
+    file: PagedFile<Vec<String>>,
+    spec: Arc<MappingSpec>,
+    format: MappedFormat,
+    page_size: usize,
+}
+
+impl MappedFile {
+    pub fn new(path: impl Into<PathBuf>, spec: MappingSpec, page_size: usize) -> Self {
+        let path = path.into();
+        let format = spec
+            .format
+            .unwrap_or_else(|| MappedFormat::from_path(&path));
+
+        Self {
+            file: PagedFile::new(path),
+            spec: Arc::new(spec),
+            format,
+            page_size,
+        }
+    }
+}
+
+impl FoodSource for MappedFile {
+    type Data = MappedPage;
+
+    fn name(&self) -> &str {
+        &self.spec.source_name
+    }
+
+    fn is_finished(&self, current_page: usize) -> bool {
+        self.file.is_finished(current_page)
+    }
+
+    fn fetch(&self, current_page: usize) -> impl Future<Output = Result<Self::Data, SourceError>> {
+        let index_spec = self.spec.clone();
+        let spec = self.spec.clone();
+        let format = self.format;
+        let page_size = self.page_size;
+
+        Box::pin(async move {
+            let (total_pages, foods) = self
+                .file
+                .read(
+                    current_page,
+                    move |path| match format {
+                        MappedFormat::Csv => index_csv(path, &index_spec, page_size),
+                        MappedFormat::Jsonl => index_jsonl(path, &index_spec, page_size),
+                    },
+                    move |_, headers, bytes| {
+                        let rows = match format {
+                            MappedFormat::Csv => csv_rows(&bytes, &spec, headers)?,
+                            MappedFormat::Jsonl => jsonl_rows(&bytes, &spec.columns),
+                        };
+                        Ok(group_rows(
+                            &spec.source_name,
+                            &spec.tagnames,
+                            rows.into_iter(),
+                        ))
+                    },
+                )
+                .await?;
+
+            Ok(MappedPage { total_pages, foods })
+        })
+    }
+}
+
+/// Splits the rows into pages of `page_size` foods, the rows of a food always sharing a page
+fn index_csv(
+    path: &Path,
+    spec: &MappingSpec,
+    page_size: usize,
+) -> Result<FileIndex<Vec<String>>, SourceError> {
+    let (header, pages) =
+        file_source::index_csv(path, &csv_reader(spec, true), page_size, |headers| {
+            column_position(headers, &spec.columns.id).map(Some)
+        })?;
+
+    Ok(FileIndex { header, pages })
+}
+
+fn index_jsonl(
+    path: &Path,
+    spec: &MappingSpec,
+    page_size: usize,
+) -> Result<FileIndex<Vec<String>>, SourceError> {
+    let mut reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+    let mut splitter = PageSplitter::new(page_size);
+    let mut last_id = None;
+    let mut offset = 0u64;
+    let mut line = vec![];
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+
+        let start = offset;
+        offset += read as u64;
+
+        // Malformed lines are left for the page parsing to skip
+        let Ok(value) = serde_json::from_slice::<serde_json::Value>(&line) else {
+            continue;
+        };
+
+        let Some(id) = json_field(&value, &spec.columns.id) else {
+            continue;
+        };
+        if last_id.as_ref() == Some(&id) {
+            splitter.extend(offset);
+        } else {
+            splitter.push(start..offset);
+            last_id = Some(id);
+        }
+    }
+
+    Ok(FileIndex {
+        header: vec![],
+        pages: splitter.finish(),
+    })
+}
+
+fn csv_rows(
+    bytes: &[u8],
+    spec: &MappingSpec,
+    headers: &[String],
+) -> Result<Vec<MappedRow>, SourceError> {
+    let columns = &spec.columns;
+    let id = column_position(headers, &columns.id)?;
+    let name = column_position(headers, &columns.name)?;
+    let nutrient = column_position(headers, &columns.nutrient)?;
+    let unit = column_position(headers, &columns.unit)?;
+    let value = column_position(headers, &columns.value)?;
+    let optional = |column: &Option<String>| {
+        column
+            .as_ref()
+            .map(|column| column_position(headers, column))
+            .transpose()
+    };
+    let number = optional(&columns.number)?;
+    let tagname = optional(&columns.tagname)?;
+    let category = match &columns.category {
+        Some(category) => Some((
+            column_position(headers, &category.code)?,
+            column_position(headers, &category.name)?,
+        )),
+        None => None,
+    };
+
+    let mut reader = csv_reader(spec, false).from_reader(bytes);
+    let mut rows = vec![];
+    for record in reader.byte_records() {
+        let record = record.map_err(csv_error)?;
+        let field = |position: usize| {
+            record
+                .get(position)
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+        };
+
+        rows.push(MappedRow {
+            id: field(id),
+            name: field(name),
+            nutrient: field(nutrient),
+            number: number.and_then(field),
+            tagname: tagname.and_then(field),
+            unit: field(unit),
+            value: field(value),
+            category_code: category.and_then(|(code, _)| field(code)),
+            category: category.and_then(|(_, name)| field(name)),
+        });
+    }
+
+    Ok(rows)
+}
+
+fn jsonl_rows(bytes: &[u8], columns: &ColumnMapping) -> Vec<MappedRow> {
+    bytes
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .filter_map(
+            |line| match serde_json::from_slice::<serde_json::Value>(line) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Skipping malformed mapped table row");
+                    None
+                }
+            },
+        )
+        .map(|value| MappedRow {
+            id: json_field(&value, &columns.id),
+            name: json_field(&value, &columns.name),
+            nutrient: json_field(&value, &columns.nutrient),
+            number: columns
+                .number
+                .as_ref()
+                .and_then(|number| json_field(&value, number)),
+            tagname: columns
+                .tagname
+                .as_ref()
+                .and_then(|tagname| json_field(&value, tagname)),
+            unit: json_field(&value, &columns.unit),
+            value: json_field(&value, &columns.value),
+            category_code: columns
+                .category
+                .as_ref()
+                .and_then(|category| json_field(&value, &category.code)),
+            category: columns
+                .category
+                .as_ref()
+                .and_then(|category| json_field(&value, &category.name)),
+        })
+        .collect()
+}
+
+/// Follows a dot separated path into a json object, stringifying scalar values
+fn json_field(value: &serde_json::Value, path: &str) -> Option<String> {
+    let field = path
+        .split('.')
+        .try_fold(value, |value, key| value.get(key))?;
+
+    match field {
+        serde_json::Value::String(field) => Some(field.clone()),
+        serde_json::Value::Number(field) => Some(field.to_string()),
+        _ => None,
+    }
+}
+
+fn csv_reader(spec: &MappingSpec, has_headers: bool) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(spec.delimiter)
+        .has_headers(has_headers)
+        .flexible(true);
+    builder
+}
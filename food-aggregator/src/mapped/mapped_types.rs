@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use crate::supervisor::{FoodData, FoodEntry, FoodEntryNutrient};
+
+#[derive(Debug)]
+pub struct MappedPage {
+    pub total_pages: usize,
+    pub foods: Vec<MappedFood>,
+}
+
+impl FoodData for MappedPage {
+    type Entry = MappedFood;
+    type EntryIter<'a> = std::slice::Iter<'a, Self::Entry>;
+
+    fn entries(&self) -> Self::EntryIter<'_> {
+        self.foods.iter()
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+}
+
+#[derive(Debug)]
+pub struct MappedFood {
+    pub source: String,
+    pub id: String,
+    pub name: String,
+    pub category: Option<(i32, String)>,
+    pub nutrients: Vec<MappedNutrient>,
+}
+
+impl FoodEntry for MappedFood {
+    type Nutrient = MappedNutrient;
+    type NutrientIter<'a> = std::slice::Iter<'a, Self::Nutrient>;
+
+    fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    fn wweia_data(&self) -> Option<(i32, &String)> {
+        self.category.as_ref().map(|(code, name)| (*code, name))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fndds_code(&self) -> Option<i32> {
+        None
+    }
+
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn nutrients(&self) -> Self::NutrientIter<'_> {
+        self.nutrients.iter()
+    }
+}
+
+#[derive(Debug)]
+pub struct MappedNutrient {
+    pub name: String,
+    pub number: Option<String>,
+    pub tagname: Option<String>,
+    pub unit_name: String,
+    pub value: Option<f32>,
+}
+
+impl FoodEntryNutrient for MappedNutrient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    fn tagname(&self) -> Option<&str> {
+        self.tagname.as_deref()
+    }
+
+    fn unit_name(&self) -> &str {
+        &self.unit_name
+    }
+
+    fn value(&self) -> Option<f32> {
+        self.value
+    }
+}
+
+/// Fields of a single row of the table, already extracted through the column mapping
+#[derive(Debug, Default)]
+pub struct MappedRow {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub nutrient: Option<String>,
+    pub number: Option<String>,
+    pub tagname: Option<String>,
+    pub unit: Option<String>,
+    pub value: Option<String>,
+    pub category_code: Option<String>,
+    pub category: Option<String>,
+}
+
+/// Groups the rows of a page into foods, keeping the order foods first appear in. Rows without
+/// an id or a name are skipped, while nutrient values that are missing or not numeric, like the
+/// `Tr` used by some tables for trace amounts, are kept as unknown. Nutrients without a tagname
+/// column take theirs from `tagnames`, keyed by nutrient name
+pub fn group_rows(
+    source: &str,
+    tagnames: &HashMap<String, String>,
+    rows: impl Iterator<Item = MappedRow>,
+) -> Vec<MappedFood> {
+    let mut foods = Vec::<MappedFood>::new();
+    let mut positions = HashMap::<String, usize>::new();
+
+    for row in rows {
+        let (Some(id), Some(name)) = (non_empty(row.id), non_empty(row.name)) else {
+            continue;
+        };
+
+        let position = *positions.entry(id.clone()).or_insert_with(|| {
+            let category = non_empty(row.category_code)
+                .and_then(|code| code.parse().ok())
+                .zip(non_empty(row.category));
+
+            foods.push(MappedFood {
+                source: source.to_string(),
+                id,
+                name,
+                category,
+                nutrients: vec![],
+            });
+            foods.len() - 1
+        });
+
+        let nutrient = non_empty(row.nutrient);
+        let unit = non_empty(row.unit);
+        let value = non_empty(row.value).and_then(|value| value.parse::<f32>().ok());
+        if let (Some(name), Some(unit_name)) = (nutrient, unit) {
+            let tagname = non_empty(row.tagname).or_else(|| tagnames.get(&name).cloned());
+            foods[position].nutrients.push(MappedNutrient {
+                number: non_empty(row.number),
+                tagname,
+                name,
+                unit_name,
+                value,
+            });
+        }
+    }
+
+    foods
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
@@ -0,0 +1,181 @@
+mod mapped_file;
+mod mapped_types;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub use mapped_file::MappedFile;
+use serde::{Deserialize, Deserializer};
+
+use crate::aggregator::PagedAggregator;
+use crate::config::{ConfigError, SourceConfig};
+use crate::registry::{AggregatorContext, BoxedAggregator};
+
+/// Describes how a national food composition table maps onto foods. Tables are expected in long
+/// form, each row holding one nutrient value of a food, with the rows of a food next to each
+/// other.
+///
+/// ```toml
+/// [sources.cnf]
+/// kind = "mapped"
+/// path = "data/cnf.csv"
+/// source_name = "Canadian Nutrient File"
+///
+/// [sources.cnf.columns]
+/// id = "FoodID"
+/// name = "FoodDescription"
+/// nutrient = "NutrientName"
+/// unit = "NutrientUnit"
+/// value = "NutrientValue"
+/// category = { code = "FoodGroupID", name = "FoodGroupName" }
+/// ```
+///
+/// Nutrients are mapped onto the nutrient catalog by their INFOODS tagname or USDA nutrient
+/// number, read from the `tagname` and `number` columns when the table has them. Tables that only
+/// name their nutrients can give the tagname of each name instead.
+///
+/// ```toml
+/// [sources.cnf.tagnames]
+/// "PROTEIN" = "PROCNT"
+/// "ENERGY (KILOCALORIES)" = "ENERC_KCAL"
+/// ```
+///
+/// The spec can also live in its own TOML or YAML file, pointed by the `spec` option of the
+/// source.
+///
+/// ```yaml
+/// source_name: Canadian Nutrient File
+/// columns:
+///   id: FoodID
+///   name: FoodDescription
+///   nutrient: NutrientName
+///   unit: NutrientUnit
+///   value: NutrientValue
+///   category: { code: FoodGroupID, name: FoodGroupName }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MappingSpec {
+    /// Name foods of the table are stored under
+    pub source_name: String,
+    /// Inferred from the file extension when not set
+    #[serde(default)]
+    pub format: Option<MappedFormat>,
+    #[serde(default = "default_delimiter", deserialize_with = "ascii_delimiter")]
+    pub delimiter: u8,
+    pub columns: ColumnMapping,
+    /// INFOODS tagnames by nutrient name, for tables without a tagname column
+    #[serde(default)]
+    pub tagnames: HashMap<String, String>,
+}
+
+/// Columns of a CSV table, or dot separated paths into the objects of a JSONL table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnMapping {
+    pub id: String,
+    pub name: String,
+    pub nutrient: String,
+    /// USDA nutrient number of the nutrient
+    #[serde(default)]
+    pub number: Option<String>,
+    /// INFOODS tagname of the nutrient
+    #[serde(default)]
+    pub tagname: Option<String>,
+    pub unit: String,
+    pub value: String,
+    #[serde(default)]
+    pub category: Option<CategoryMapping>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryMapping {
+    pub code: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappedFormat {
+    Csv,
+    Jsonl,
+}
+
+impl MappedFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl" | "ndjson" | "json") => MappedFormat::Jsonl,
+            _ => MappedFormat::Csv,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MappedOptions {
+    path: PathBuf,
+    /// TOML or YAML file holding the [`MappingSpec`], told apart by its extension, otherwise it
+    /// is read from the source options. Every other option belongs to the inline spec, which is
+    /// rejected when a spec file is given
+    #[serde(default)]
+    spec: Option<PathBuf>,
+    #[serde(default = "default_page_size")]
+    page_size: usize,
+    #[serde(flatten)]
+    inline_spec: toml::Table,
+}
+
+fn default_page_size() -> usize {
+    1000
+}
+
+fn default_delimiter() -> u8 {
+    b','
+}
+
+fn ascii_delimiter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let delimiter = char::deserialize(deserializer)?;
+    u8::try_from(delimiter)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| serde::de::Error::custom("delimiter must be a single ascii character"))
+}
+
+pub fn build_aggregator(
+    name: &str,
+    config: &SourceConfig,
+    context: &AggregatorContext,
+) -> Result<BoxedAggregator, ConfigError> {
+    let options = config.options::<MappedOptions>(name)?;
+    let invalid = |error| ConfigError::InvalidOptions {
+        source: name.to_string(),
+        error,
+    };
+    let spec = match &options.spec {
+        Some(path) => match options.inline_spec.keys().next() {
+            Some(key) => {
+                return Err(invalid(serde::de::Error::unknown_field(
+                    key,
+                    &["path", "spec", "page_size"],
+                )));
+            }
+            None => read_spec(path)?,
+        },
+        None => toml::Value::Table(options.inline_spec)
+            .try_into()
+            .map_err(invalid)?,
+    };
+
+    let client = MappedFile::new(options.path, spec, options.page_size);
+    Ok(Box::new(PagedAggregator::from_file(
+        name, client, config, context,
+    )))
+}
+
+fn read_spec(path: &Path) -> Result<MappingSpec, ConfigError> {
+    let spec = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => Ok(serde_yaml_ng::from_str(&spec)?),
+        _ => Ok(toml::from_str(&spec)?),
+    }
+}
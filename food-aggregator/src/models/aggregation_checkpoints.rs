@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use sqlx::prelude::FromRow;
+use sqlx::types::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "page_status_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PageStatus {
+    InFlight,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AggregationCheckpoint {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub page: i32,
+    pub status: PageStatus,
+    pub attempts: i32,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct CreateCheckpointPayload<'data> {
+    run_id: Uuid,
+    page: usize,
+    status: PageStatus,
+    attempts: usize,
+    error: Option<&'data str>,
+}
+
+impl<'data> CreateCheckpointPayload<'data> {
+    pub fn new(run_id: Uuid, page: usize, status: PageStatus, attempts: usize) -> Self {
+        Self {
+            run_id,
+            page,
+            status,
+            attempts,
+            error: None,
+        }
+    }
+
+    pub fn with_error(mut self, error: &'data str) -> Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+impl AggregationCheckpoint {
+    pub async fn get_for_run(
+        executor: &mut PgConnection,
+        run_id: Uuid,
+    ) -> sqlx::Result<Vec<AggregationCheckpoint>> {
+        let checkpoints = sqlx::query_as!(
+            AggregationCheckpoint,
+            r#"
+            SELECT
+                id,
+                run_id,
+                page,
+                status AS "status: PageStatus",
+                attempts,
+                error,
+                created_at,
+                updated_at
+            FROM
+                aggregation_checkpoints
+            WHERE
+                run_id = $1
+            ORDER BY
+                page;
+            "#,
+            run_id
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(checkpoints)
+    }
+
+    pub async fn create_or_update(
+        executor: &mut PgConnection,
+        payload: CreateCheckpointPayload<'_>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO aggregation_checkpoints (run_id, page, status, attempts, error)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (run_id, page) DO UPDATE SET
+                status = EXCLUDED.status,
+                attempts = EXCLUDED.attempts,
+                error = EXCLUDED.error;
+            "#,
+            payload.run_id,
+            payload.page as i32,
+            payload.status as PageStatus,
+            payload.attempts as i32,
+            payload.error,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use sqlx::prelude::FromRow;
+use sqlx::types::Uuid;
+
+use crate::lease::AggregationLease;
+
+/// How many distinct errors are kept per attempt, a source that is down fails every page the
+/// same way and the history only needs a summary of it
+const MAX_ERRORS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "aggregation_outcome_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AggregationOutcome {
+    Running,
+    Finished,
+    /// Stopped by the rate limit, the run resumes in a later attempt
+    Postponed,
+    Failed,
+    Cancelled,
+    /// The process stopped before the attempt could record how it ended
+    Interrupted,
+}
+
+/// A single attempt at aggregating a run, a run that gets rate limited spans many attempts
+#[derive(Debug, Serialize, FromRow)]
+pub struct AggregationHistory {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub source: String,
+    pub dry_run: bool,
+    pub outcome: AggregationOutcome,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub pages_fetched: i64,
+    pub pages_retried: i64,
+    pub pages_failed: i64,
+    pub foods_inserted: i64,
+    pub foods_updated: i64,
+    pub nutrients_written: i64,
+    pub errors: Vec<String>,
+}
+
+/// Counters of an attempt, accumulated while it runs
+#[derive(Debug, Default, Clone)]
+pub struct AggregationStats {
+    pub pages_fetched: i64,
+    pub pages_retried: i64,
+    pub pages_failed: i64,
+    pub foods_inserted: i64,
+    pub foods_updated: i64,
+    pub nutrients_written: i64,
+    pub errors: Vec<String>,
+}
+
+impl AggregationStats {
+    pub fn merge(&mut self, other: AggregationStats) {
+        self.pages_fetched += other.pages_fetched;
+        self.pages_retried += other.pages_retried;
+        self.pages_failed += other.pages_failed;
+        self.foods_inserted += other.foods_inserted;
+        self.foods_updated += other.foods_updated;
+        self.nutrients_written += other.nutrients_written;
+        for error in other.errors {
+            self.record_error(error);
+        }
+    }
+
+    pub fn record_error(&mut self, error: impl Into<String>) {
+        let error = error.into();
+        if self.errors.len() < MAX_ERRORS && !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+}
+
+impl AggregationHistory {
+    /// Records a new attempt of `run_id`, along with the session holding the lease over the
+    /// source. Attempts of the same source still marked as running whose session no longer holds
+    /// the lease were cut short without recording their outcome, so they are marked as
+    /// interrupted, while attempts of a live session are left running.
+    pub async fn start(
+        executor: &mut PgConnection,
+        run_id: Uuid,
+        source: &str,
+    ) -> sqlx::Result<Uuid> {
+        let holder = AggregationLease::source_holder(executor, source).await?;
+        let (lease_pid, lease_backend_start) = holder
+            .map(|holder| (holder.pid, holder.backend_start))
+            .unzip();
+
+        sqlx::query!(
+            r#"
+            UPDATE aggregation_run_history
+            SET
+                outcome = 'interrupted',
+                ended_at = updated_at
+            WHERE
+                source = $1
+                AND outcome = 'running'
+                AND (
+                    $2::integer IS NULL
+                    OR lease_pid IS DISTINCT FROM $2
+                    OR lease_backend_start IS DISTINCT FROM $3
+                );
+            "#,
+            source,
+            lease_pid,
+            lease_backend_start
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO aggregation_run_history (run_id, source, lease_pid, lease_backend_start)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id;
+            "#,
+            run_id,
+            source,
+            lease_pid,
+            lease_backend_start
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Ends every attempt of `source` that is still running with `outcome`, adding `error` to
+    /// the errors of the attempt when given
+    pub async fn end_running(
+        executor: &mut PgConnection,
+        source: &str,
+        outcome: AggregationOutcome,
+        error: Option<&str>,
+    ) -> sqlx::Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE aggregation_run_history
+            SET
+                outcome = $2,
+                ended_at = updated_at,
+                errors = CASE
+                    WHEN $3::text IS NULL THEN errors
+                    ELSE array_append(errors, $3)
+                END
+            WHERE
+                source = $1
+                AND outcome = 'running';
+            "#,
+            source,
+            outcome as AggregationOutcome,
+            error
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn update_stats(
+        executor: &mut PgConnection,
+        id: Uuid,
+        stats: &AggregationStats,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE aggregation_run_history
+            SET
+                pages_fetched = $2,
+                pages_retried = $3,
+                pages_failed = $4,
+                foods_inserted = $5,
+                foods_updated = $6,
+                nutrients_written = $7,
+                errors = $8
+            WHERE
+                id = $1;
+            "#,
+            id,
+            stats.pages_fetched,
+            stats.pages_retried,
+            stats.pages_failed,
+            stats.foods_inserted,
+            stats.foods_updated,
+            stats.nutrients_written,
+            &stats.errors
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn finish(
+        executor: &mut PgConnection,
+        id: Uuid,
+        outcome: AggregationOutcome,
+        stats: &AggregationStats,
+    ) -> sqlx::Result<()> {
+        Self::update_stats(executor, id, stats).await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE aggregation_run_history
+            SET
+                outcome = $2,
+                ended_at = NOW()
+            WHERE
+                id = $1;
+            "#,
+            id,
+            outcome as AggregationOutcome
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Past attempts, newest first, optionally restricted to a single source
+    pub async fn list(
+        executor: &mut PgConnection,
+        source: Option<&str>,
+        limit: i64,
+    ) -> sqlx::Result<Vec<AggregationHistory>> {
+        let history = sqlx::query_as!(
+            AggregationHistory,
+            r#"
+            SELECT
+                h.id,
+                h.run_id,
+                h.source,
+                r.dry_run,
+                h.outcome AS "outcome: AggregationOutcome",
+                h.started_at,
+                h.ended_at,
+                h.pages_fetched,
+                h.pages_retried,
+                h.pages_failed,
+                h.foods_inserted,
+                h.foods_updated,
+                h.nutrients_written,
+                h.errors
+            FROM
+                aggregation_run_history h
+                JOIN aggregation_runs r ON r.id = h.run_id
+            WHERE
+                $1::varchar IS NULL
+                OR h.source = $1
+            ORDER BY
+                h.started_at DESC
+            LIMIT $2;
+            "#,
+            source,
+            limit
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(history)
+    }
+}
@@ -6,8 +6,8 @@ use sqlx::types::chrono::{DateTime, Utc};
 pub struct AggregateMetadataModel {
     pub id: sqlx::types::Uuid,
     pub last_run: DateTime<Utc>,
-    created_at: DateTime<Utc>,
-    updated_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl AggregateMetadataModel {
@@ -20,7 +20,10 @@ impl AggregateMetadataModel {
             SELECT
                 *
             FROM
-                aggregation_metadata;
+                aggregation_metadata
+            ORDER BY
+                last_run DESC
+            LIMIT 1;
             "#
         )
         .fetch_optional(executor)
@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use sqlx::prelude::FromRow;
+use sqlx::types::Uuid;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AggregationRun {
+    pub id: Uuid,
+    pub source: String,
+    pub total_pages: Option<i32>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Dry runs only diff the fetched pages against the live tables, they never resume or get
+    /// resumed by a regular run
+    pub dry_run: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Latest run of a source along with how many of its pages reached each checkpoint status
+#[derive(Debug, Serialize, FromRow)]
+pub struct AggregationRunProgress {
+    pub id: Uuid,
+    pub source: String,
+    pub total_pages: Option<i32>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub dry_run: bool,
+    pub created_at: DateTime<Utc>,
+    pub completed_pages: i64,
+    pub failed_pages: i64,
+    pub in_flight_pages: i64,
+}
+
+impl AggregationRun {
+    pub async fn get_latest_progress(
+        executor: &mut PgConnection,
+    ) -> sqlx::Result<Vec<AggregationRunProgress>> {
+        let progress = sqlx::query_as!(
+            AggregationRunProgress,
+            r#"
+            SELECT DISTINCT ON (r.source)
+                r.id,
+                r.source,
+                r.total_pages,
+                r.finished_at,
+                r.dry_run,
+                r.created_at,
+                COUNT(c.id) FILTER (WHERE c.status = 'completed') AS "completed_pages!",
+                COUNT(c.id) FILTER (WHERE c.status = 'failed') AS "failed_pages!",
+                COUNT(c.id) FILTER (WHERE c.status = 'in_flight') AS "in_flight_pages!"
+            FROM
+                aggregation_runs r
+                LEFT JOIN aggregation_checkpoints c ON c.run_id = r.id
+            GROUP BY
+                r.id
+            ORDER BY
+                r.source,
+                r.created_at DESC;
+            "#
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(progress)
+    }
+
+    /// Past runs with their progress, newest first, optionally restricted to a single source
+    pub async fn list_progress(
+        executor: &mut PgConnection,
+        source: Option<&str>,
+        limit: i64,
+    ) -> sqlx::Result<Vec<AggregationRunProgress>> {
+        let progress = sqlx::query_as!(
+            AggregationRunProgress,
+            r#"
+            SELECT
+                r.id,
+                r.source,
+                r.total_pages,
+                r.finished_at,
+                r.dry_run,
+                r.created_at,
+                COUNT(c.id) FILTER (WHERE c.status = 'completed') AS "completed_pages!",
+                COUNT(c.id) FILTER (WHERE c.status = 'failed') AS "failed_pages!",
+                COUNT(c.id) FILTER (WHERE c.status = 'in_flight') AS "in_flight_pages!"
+            FROM
+                aggregation_runs r
+                LEFT JOIN aggregation_checkpoints c ON c.run_id = r.id
+            WHERE
+                $1::varchar IS NULL
+                OR r.source = $1
+            GROUP BY
+                r.id
+            ORDER BY
+                r.created_at DESC
+            LIMIT $2;
+            "#,
+            source,
+            limit
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(progress)
+    }
+
+    pub async fn get_progress_for_runs(
+        executor: &mut PgConnection,
+        run_ids: &[Uuid],
+    ) -> sqlx::Result<Vec<AggregationRunProgress>> {
+        let progress = sqlx::query_as!(
+            AggregationRunProgress,
+            r#"
+            SELECT
+                r.id,
+                r.source,
+                r.total_pages,
+                r.finished_at,
+                r.dry_run,
+                r.created_at,
+                COUNT(c.id) FILTER (WHERE c.status = 'completed') AS "completed_pages!",
+                COUNT(c.id) FILTER (WHERE c.status = 'failed') AS "failed_pages!",
+                COUNT(c.id) FILTER (WHERE c.status = 'in_flight') AS "in_flight_pages!"
+            FROM
+                aggregation_runs r
+                LEFT JOIN aggregation_checkpoints c ON c.run_id = r.id
+            WHERE
+                r.id = ANY($1)
+            GROUP BY
+                r.id;
+            "#,
+            run_ids
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(progress)
+    }
+
+    /// Returns the most recent run of `source` that didn't finish yet, creating a new one when
+    /// every previous run is finished. Callers hold the lease over the source, otherwise two
+    /// aggregations could pick up the same run.
+    pub async fn get_or_create_unfinished(
+        executor: &mut PgConnection,
+        source: &str,
+        dry_run: bool,
+    ) -> sqlx::Result<AggregationRun> {
+        let run = sqlx::query_as!(
+            AggregationRun,
+            r#"
+            SELECT
+                *
+            FROM
+                aggregation_runs
+            WHERE
+                source = $1
+                AND dry_run = $2
+                AND finished_at IS NULL
+            ORDER BY
+                created_at DESC
+            LIMIT 1;
+            "#,
+            source,
+            dry_run
+        )
+        .fetch_optional(executor.as_mut())
+        .await?;
+
+        match run {
+            Some(run) => Ok(run),
+            None => AggregationRun::create(executor, source, dry_run).await,
+        }
+    }
+
+    pub async fn get_latest_dry_run(
+        executor: &mut PgConnection,
+        source: &str,
+    ) -> sqlx::Result<Option<AggregationRun>> {
+        let run = sqlx::query_as!(
+            AggregationRun,
+            r#"
+            SELECT
+                *
+            FROM
+                aggregation_runs
+            WHERE
+                source = $1
+                AND dry_run
+            ORDER BY
+                created_at DESC
+            LIMIT 1;
+            "#,
+            source
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(run)
+    }
+
+    pub async fn create(
+        executor: &mut PgConnection,
+        source: &str,
+        dry_run: bool,
+    ) -> sqlx::Result<AggregationRun> {
+        let run = sqlx::query_as!(
+            AggregationRun,
+            "INSERT INTO aggregation_runs (source, dry_run) VALUES ($1, $2) RETURNING *;",
+            source,
+            dry_run
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(run)
+    }
+
+    pub async fn set_total_pages(
+        executor: &mut PgConnection,
+        id: Uuid,
+        total_pages: usize,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE aggregation_runs SET total_pages = $2 WHERE id = $1;",
+            id,
+            total_pages as i32
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn finish(executor: &mut PgConnection, id: Uuid) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE aggregation_runs SET finished_at = NOW() WHERE id = $1;",
+            id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+}
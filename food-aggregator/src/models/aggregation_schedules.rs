@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use sqlx::prelude::FromRow;
+
+/// When a source last ran and is due to run again according to its schedule
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AggregationSchedule {
+    pub source: String,
+    /// Declaration the next run was computed from, so it is recomputed when the schedule of the
+    /// source is changed
+    pub schedule: String,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Empty when the schedule never matches again
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AggregationSchedule {
+    pub async fn get_all(executor: &mut PgConnection) -> sqlx::Result<Vec<AggregationSchedule>> {
+        let schedules = sqlx::query_as!(
+            AggregationSchedule,
+            r#"
+            SELECT
+                *
+            FROM
+                aggregation_schedules
+            ORDER BY
+                source;
+            "#
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(schedules)
+    }
+
+    pub async fn upsert(
+        executor: &mut PgConnection,
+        source: &str,
+        schedule: &str,
+        last_run_at: Option<DateTime<Utc>>,
+        next_run_at: Option<DateTime<Utc>>,
+    ) -> sqlx::Result<AggregationSchedule> {
+        let schedule = sqlx::query_as!(
+            AggregationSchedule,
+            r#"
+            INSERT INTO aggregation_schedules (source, schedule, last_run_at, next_run_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (source) DO UPDATE SET
+                schedule = EXCLUDED.schedule,
+                last_run_at = EXCLUDED.last_run_at,
+                next_run_at = EXCLUDED.next_run_at
+            RETURNING *;
+            "#,
+            source,
+            schedule,
+            last_run_at,
+            next_run_at
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(schedule)
+    }
+}
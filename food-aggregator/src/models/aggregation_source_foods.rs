@@ -0,0 +1,40 @@
+use sqlx::PgConnection;
+
+/// Which configured source wrote each food, keyed by the food source and external id of the food
+/// rather than its id, so foods staged under their own ids are linked to the live ones as well
+pub struct AggregationSourceFoods;
+
+impl AggregationSourceFoods {
+    /// Links the given foods, as pairs of food source name and external id, to `source`
+    pub async fn record<'a>(
+        executor: &mut PgConnection,
+        source: &str,
+        foods: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> sqlx::Result<()> {
+        let (food_sources, external_ids): (Vec<_>, Vec<_>) = foods.unzip();
+        if external_ids.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO aggregation_source_foods (source, food_source_id, external_id)
+            SELECT DISTINCT
+                $1,
+                fs.id,
+                input.external_id
+            FROM
+                UNNEST($2::text[], $3::text[]) AS input (food_source, external_id)
+                JOIN food_sources fs ON fs.name = input.food_source
+            ON CONFLICT DO NOTHING;
+            "#,
+            source,
+            &food_sources as &[&str],
+            &external_ids as &[&str]
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use sqlx::prelude::FromRow;
+use sqlx::types::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "dead_letter_kind_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterKind {
+    Fetch,
+    Persist,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub source: String,
+    pub page: i32,
+    pub kind: DeadLetterKind,
+    pub error: String,
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct CreateDeadLetterPayload<'data> {
+    run_id: Uuid,
+    page: usize,
+    kind: DeadLetterKind,
+    error: &'data str,
+    attempts: usize,
+}
+
+impl<'data> CreateDeadLetterPayload<'data> {
+    pub fn new(
+        run_id: Uuid,
+        page: usize,
+        kind: DeadLetterKind,
+        error: &'data str,
+        attempts: usize,
+    ) -> Self {
+        Self {
+            run_id,
+            page,
+            kind,
+            error,
+            attempts,
+        }
+    }
+}
+
+impl DeadLetter {
+    pub async fn create_or_update(
+        executor: &mut PgConnection,
+        payload: CreateDeadLetterPayload<'_>,
+    ) -> sqlx::Result<DeadLetter> {
+        // The source is taken from the run, so the supervisor doesn't need to know the name the
+        // source was registered with
+        let dead_letter = sqlx::query_as!(
+            DeadLetter,
+            r#"
+            INSERT INTO aggregation_dead_letters (run_id, source, page, kind, error, attempts)
+            SELECT
+                r.id,
+                r.source,
+                $2,
+                $3,
+                $4,
+                $5
+            FROM
+                aggregation_runs r
+            WHERE
+                r.id = $1
+            ON CONFLICT (run_id, page) DO UPDATE SET
+                kind = EXCLUDED.kind,
+                error = EXCLUDED.error,
+                attempts = aggregation_dead_letters.attempts + EXCLUDED.attempts
+            RETURNING
+                id,
+                run_id,
+                source,
+                page,
+                kind AS "kind: DeadLetterKind",
+                error,
+                attempts,
+                created_at,
+                updated_at;
+            "#,
+            payload.run_id,
+            payload.page as i32,
+            payload.kind as DeadLetterKind,
+            payload.error,
+            payload.attempts as i32,
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(dead_letter)
+    }
+
+    pub async fn get(executor: &mut PgConnection, id: Uuid) -> sqlx::Result<Option<DeadLetter>> {
+        let dead_letter = sqlx::query_as!(
+            DeadLetter,
+            r#"
+            SELECT
+                id,
+                run_id,
+                source,
+                page,
+                kind AS "kind: DeadLetterKind",
+                error,
+                attempts,
+                created_at,
+                updated_at
+            FROM
+                aggregation_dead_letters
+            WHERE
+                id = $1;
+            "#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(dead_letter)
+    }
+
+    pub async fn get_all(
+        executor: &mut PgConnection,
+        source: Option<&str>,
+    ) -> sqlx::Result<Vec<DeadLetter>> {
+        let dead_letters = sqlx::query_as!(
+            DeadLetter,
+            r#"
+            SELECT
+                id,
+                run_id,
+                source,
+                page,
+                kind AS "kind: DeadLetterKind",
+                error,
+                attempts,
+                created_at,
+                updated_at
+            FROM
+                aggregation_dead_letters
+            WHERE
+                $1::varchar IS NULL
+                OR source = $1
+            ORDER BY
+                created_at;
+            "#,
+            source
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(dead_letters)
+    }
+
+    pub async fn record_failed_replay(
+        executor: &mut PgConnection,
+        id: Uuid,
+        error: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE aggregation_dead_letters
+            SET
+                error = $2,
+                attempts = attempts + 1
+            WHERE
+                id = $1;
+            "#,
+            id,
+            error
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(executor: &mut PgConnection, id: Uuid) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM aggregation_dead_letters WHERE id = $1;", id)
+            .execute(executor)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn purge(executor: &mut PgConnection, source: Option<&str>) -> sqlx::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM aggregation_dead_letters WHERE $1::varchar IS NULL OR source = $1;",
+            source
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::prelude::FromRow;
+use sqlx::types::Uuid;
+use sqlx::{PgConnection, QueryBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "food_diff_kind_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FoodDiffKind {
+    Added,
+    Renamed,
+    NutrientChanged,
+    Removed,
+    /// Marks a food as seen by the run, so it is not reported as removed
+    Unchanged,
+}
+
+/// A change a dry run found between a fetched food and what is stored in the live tables
+#[derive(Debug, Serialize, FromRow)]
+pub struct FoodDiff {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub external_id: String,
+    pub kind: FoodDiffKind,
+    pub name: String,
+    pub previous_name: Option<String>,
+    pub nutrient: Option<String>,
+    pub unit: Option<String>,
+    pub previous_value: Option<f32>,
+    pub value: Option<f32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct CreateFoodDiffPayload<'data> {
+    external_id: String,
+    kind: FoodDiffKind,
+    name: &'data str,
+    previous_name: Option<&'data str>,
+    nutrient: Option<&'data str>,
+    unit: Option<&'data str>,
+    previous_value: Option<f32>,
+    value: Option<f32>,
+}
+
+impl<'data> CreateFoodDiffPayload<'data> {
+    pub fn new(external_id: String, kind: FoodDiffKind, name: &'data str) -> Self {
+        Self {
+            external_id,
+            kind,
+            name,
+            previous_name: None,
+            nutrient: None,
+            unit: None,
+            previous_value: None,
+            value: None,
+        }
+    }
+
+    pub fn with_previous_name(mut self, previous_name: &'data str) -> Self {
+        self.previous_name = Some(previous_name);
+        self
+    }
+
+    /// Values are `None` when they are not known, `previous_value` is also `None` when the food
+    /// didn't have the nutrient before
+    pub fn with_nutrient(
+        mut self,
+        nutrient: &'data str,
+        unit: &'data str,
+        previous_value: Option<f32>,
+        value: Option<f32>,
+    ) -> Self {
+        self.nutrient = Some(nutrient);
+        self.unit = Some(unit);
+        self.previous_value = previous_value;
+        self.value = value;
+        self
+    }
+}
+
+/// Changes of a dry run grouped the way they are reported
+#[derive(Debug, Default, Serialize)]
+pub struct FoodDiffReport {
+    pub added: Vec<FoodDiff>,
+    pub renamed: Vec<FoodDiff>,
+    pub nutrients_changed: Vec<FoodDiff>,
+    pub removed: Vec<FoodDiff>,
+}
+
+impl FoodDiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.renamed.is_empty()
+            && self.nutrients_changed.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+impl FromIterator<FoodDiff> for FoodDiffReport {
+    fn from_iter<I: IntoIterator<Item = FoodDiff>>(diffs: I) -> Self {
+        let mut report = FoodDiffReport::default();
+
+        for diff in diffs {
+            match diff.kind {
+                FoodDiffKind::Added => report.added.push(diff),
+                FoodDiffKind::Renamed => report.renamed.push(diff),
+                FoodDiffKind::NutrientChanged => report.nutrients_changed.push(diff),
+                FoodDiffKind::Removed => report.removed.push(diff),
+                FoodDiffKind::Unchanged => {}
+            }
+        }
+
+        report
+    }
+}
+
+impl FoodDiff {
+    /// Replaces the diffs previously recorded for the given foods, so a page diffed twice doesn't
+    /// report its changes twice
+    pub async fn replace_for_foods(
+        executor: &mut PgConnection,
+        run_id: Uuid,
+        external_ids: &[String],
+        payloads: Vec<CreateFoodDiffPayload<'_>>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "DELETE FROM aggregation_food_diffs WHERE run_id = $1 AND external_id = ANY ($2);",
+            run_id,
+            external_ids
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        for chunk in payloads.chunks(1000) {
+            let mut query_builder = QueryBuilder::new(
+                r#"INSERT INTO aggregation_food_diffs (
+                    run_id, external_id, kind, name, previous_name, nutrient, unit, previous_value, value
+                ) "#,
+            );
+
+            query_builder.push_values(chunk, |mut b, payload| {
+                b.push_bind(run_id)
+                    .push_bind(&payload.external_id)
+                    .push_bind(payload.kind)
+                    .push_bind(payload.name)
+                    .push_bind(payload.previous_name)
+                    .push_bind(payload.nutrient)
+                    .push_bind(payload.unit)
+                    .push_bind(payload.previous_value)
+                    .push_bind(payload.value);
+            });
+
+            query_builder.build().execute(executor.as_mut()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Records every stored food written by the configured `source` the run never saw as
+    /// removed, then drops the markers of unchanged foods as they are not part of the report
+    pub async fn record_removed(
+        executor: &mut PgConnection,
+        run_id: Uuid,
+        source: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO aggregation_food_diffs (run_id, external_id, kind, name)
+            SELECT
+                $1,
+                f.external_id,
+                'removed',
+                f.name
+            FROM
+                foods f
+                JOIN aggregation_source_foods a ON f.source_id = a.food_source_id
+                    AND f.external_id = a.external_id
+            WHERE
+                a.source = $2
+                AND NOT EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        aggregation_food_diffs d
+                    WHERE
+                        d.run_id = $1
+                        AND d.external_id = f.external_id);
+            "#,
+            run_id,
+            source
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM aggregation_food_diffs WHERE run_id = $1 AND kind = 'unchanged';",
+            run_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_report(
+        executor: &mut PgConnection,
+        run_id: Uuid,
+    ) -> sqlx::Result<FoodDiffReport> {
+        let diffs = sqlx::query_as!(
+            FoodDiff,
+            r#"
+            SELECT
+                id,
+                run_id,
+                external_id,
+                kind AS "kind: FoodDiffKind",
+                name,
+                previous_name,
+                nutrient,
+                unit,
+                previous_value,
+                value,
+                created_at,
+                updated_at
+            FROM
+                aggregation_food_diffs
+            WHERE
+                run_id = $1
+            ORDER BY
+                external_id,
+                nutrient;
+            "#,
+            run_id
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(diffs.into_iter().collect())
+    }
+}
@@ -0,0 +1,228 @@
+use sqlx::PgConnection;
+use sqlx::types::Uuid;
+
+use super::aggregation_history::AggregationStats;
+use super::foods::CreateFoodPayload;
+
+/// Rows are sent to `COPY` in chunks of this many bytes, so a page never has to be encoded whole
+const COPY_CHUNK_BYTES: usize = 1 << 20;
+
+/// Nutrient value of a food that is only known by its external id until it is merged
+#[derive(Debug)]
+pub struct IngestFoodNutrientPayload<'data> {
+    pub source_id: Uuid,
+    pub external_id: &'data str,
+    pub nutrient_id: Uuid,
+    pub unit_id: Uuid,
+    pub value: Option<f32>,
+}
+
+/// Bulk ingest path for `foods` and `food_nutrients`. Pages are streamed with `COPY` into
+/// temporary tables, and merged into the target tables with a single upsert for each of them,
+/// instead of one round trip per food.
+///
+/// The temporary tables are dropped when the transaction commits, so staging and merging have
+/// to happen within the same transaction. Merging targets the unqualified tables, so a
+/// transaction routed through [`Staging`](super::staging::Staging) merges into staging.
+pub struct FoodIngest;
+
+impl FoodIngest {
+    async fn create_tables(executor: &mut PgConnection) -> sqlx::Result<()> {
+        // The position keeps the order rows were copied in, so the last copy of a row wins
+        sqlx::query(
+            r#"
+            CREATE TEMP TABLE IF NOT EXISTS ingest_foods (
+                position bigserial,
+                name text NOT NULL,
+                source_id uuid NOT NULL,
+                external_id text NOT NULL,
+                fndds_code int4,
+                wweia_category uuid
+            ) ON COMMIT DROP;
+            "#,
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TEMP TABLE IF NOT EXISTS ingest_food_nutrients (
+                position bigserial,
+                source_id uuid NOT NULL,
+                external_id text NOT NULL,
+                nutrient_id uuid NOT NULL,
+                unit_id uuid NOT NULL,
+                value float4
+            ) ON COMMIT DROP;
+            "#,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Copies foods and their nutrient values into the temporary tables, to be written by
+    /// [`FoodIngest::merge`] before the transaction commits.
+    pub async fn stage(
+        executor: &mut PgConnection,
+        foods: &[CreateFoodPayload<'_>],
+        food_nutrients: &[IngestFoodNutrientPayload<'_>],
+    ) -> sqlx::Result<()> {
+        Self::create_tables(executor).await?;
+
+        let rows = foods.iter().map(|food| {
+            [
+                Some(food.name.to_string()),
+                Some(food.source_id.to_string()),
+                Some(food.external_id.clone()),
+                food.fndds_code.map(|code| code.to_string()),
+                food.wweia_category.map(|id| id.to_string()),
+            ]
+        });
+        copy_rows(
+            executor,
+            "COPY ingest_foods (name, source_id, external_id, fndds_code, wweia_category) \
+             FROM STDIN",
+            rows,
+        )
+        .await?;
+
+        let rows = food_nutrients.iter().map(|nutrient| {
+            [
+                Some(nutrient.source_id.to_string()),
+                Some(nutrient.external_id.to_string()),
+                Some(nutrient.nutrient_id.to_string()),
+                Some(nutrient.unit_id.to_string()),
+                nutrient.value.map(|value| value.to_string()),
+            ]
+        });
+        copy_rows(
+            executor,
+            "COPY ingest_food_nutrients (source_id, external_id, nutrient_id, unit_id, value) \
+             FROM STDIN",
+            rows,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Upserts everything staged by the transaction, returning how many foods and nutrients were
+    /// written, and empties the temporary tables.
+    pub async fn merge(executor: &mut PgConnection) -> sqlx::Result<AggregationStats> {
+        Self::create_tables(executor).await?;
+
+        // A food can only be upserted once per statement, and xmax is only zero for rows that
+        // didn't exist before the statement
+        let upserted = sqlx::query_scalar::<_, bool>(
+            r#"
+            INSERT INTO foods (name, source_id, external_id, fndds_code, wweia_category)
+            SELECT DISTINCT ON (source_id, external_id)
+                name,
+                source_id,
+                external_id,
+                fndds_code,
+                wweia_category
+            FROM
+                ingest_foods
+            ORDER BY
+                source_id,
+                external_id,
+                position DESC
+            ON CONFLICT (source_id, external_id) DO UPDATE SET
+                name = EXCLUDED.name,
+                fndds_code = EXCLUDED.fndds_code,
+                wweia_category = EXCLUDED.wweia_category
+            RETURNING (xmax = 0) AS inserted;
+            "#,
+        )
+        .fetch_all(executor.as_mut())
+        .await?;
+        let foods_inserted = upserted.iter().filter(|inserted| **inserted).count() as i64;
+
+        let nutrients = sqlx::query(
+            r#"
+            INSERT INTO food_nutrients (food_id, nutrient_id, unit_id, source_id, value)
+            SELECT DISTINCT ON (f.id, ifn.nutrient_id, ifn.source_id)
+                f.id,
+                ifn.nutrient_id,
+                ifn.unit_id,
+                ifn.source_id,
+                ifn.value
+            FROM
+                ingest_food_nutrients ifn
+                JOIN foods f ON f.source_id = ifn.source_id
+                    AND f.external_id = ifn.external_id
+            ORDER BY
+                f.id,
+                ifn.nutrient_id,
+                ifn.source_id,
+                ifn.position DESC
+            ON CONFLICT (food_id, nutrient_id, source_id) DO UPDATE SET
+                value = EXCLUDED.value,
+                unit_id = EXCLUDED.unit_id
+            WHERE (food_nutrients.value, food_nutrients.unit_id)
+                IS DISTINCT FROM (EXCLUDED.value, EXCLUDED.unit_id);
+            "#,
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        sqlx::query("TRUNCATE ingest_foods, ingest_food_nutrients;")
+            .execute(executor)
+            .await?;
+
+        Ok(AggregationStats {
+            foods_inserted,
+            foods_updated: upserted.len() as i64 - foods_inserted,
+            nutrients_written: nutrients.rows_affected() as i64,
+            ..AggregationStats::default()
+        })
+    }
+}
+
+/// Streams `rows` to a `COPY ... FROM STDIN` statement using the text format
+async fn copy_rows<const N: usize>(
+    executor: &mut PgConnection,
+    statement: &str,
+    rows: impl Iterator<Item = [Option<String>; N]>,
+) -> sqlx::Result<u64> {
+    let mut copy = executor.copy_in_raw(statement).await?;
+    let mut buffer = String::new();
+
+    for row in rows {
+        for (index, field) in row.iter().enumerate() {
+            if index > 0 {
+                buffer.push('\t');
+            }
+            match field {
+                Some(field) => escape_copy_text(&mut buffer, field),
+                None => buffer.push_str("\\N"),
+            }
+        }
+        buffer.push('\n');
+
+        if buffer.len() >= COPY_CHUNK_BYTES {
+            copy.send(std::mem::take(&mut buffer).into_bytes()).await?;
+        }
+    }
+
+    if !buffer.is_empty() {
+        copy.send(buffer.into_bytes()).await?;
+    }
+
+    copy.finish().await
+}
+
+fn escape_copy_text(buffer: &mut String, field: &str) {
+    for char in field.chars() {
+        match char {
+            '\\' => buffer.push_str("\\\\"),
+            '\t' => buffer.push_str("\\t"),
+            '\n' => buffer.push_str("\\n"),
+            '\r' => buffer.push_str("\\r"),
+            char => buffer.push(char),
+        }
+    }
+}
@@ -11,18 +11,27 @@ pub struct FoodNutrients {
     nutrient_id: Uuid,
     unit_id: Uuid,
     source_id: Uuid,
-    value: f32,
+    value: Option<f32>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
 
+/// Nutrient value as currently stored for a food, with its nutrient and unit names resolved
+#[derive(Debug, FromRow)]
+pub struct StoredFoodNutrient {
+    pub food_id: Uuid,
+    pub nutrient: String,
+    pub unit: String,
+    pub value: Option<f32>,
+}
+
 #[derive(Debug)]
 pub struct CreateFoodNutrientPayload {
     food_id: Uuid,
     nutrient_id: Uuid,
     unit_id: Uuid,
     source_id: Uuid,
-    value: f32,
+    value: Option<f32>,
 }
 
 impl CreateFoodNutrientPayload {
@@ -31,7 +40,7 @@ impl CreateFoodNutrientPayload {
         nutrient_id: Uuid,
         unit_id: Uuid,
         source_id: Uuid,
-        value: f32,
+        value: Option<f32>,
     ) -> Self {
         Self {
             food_id,
@@ -44,6 +53,33 @@ impl CreateFoodNutrientPayload {
 }
 
 impl FoodNutrients {
+    pub async fn get_for_foods(
+        executor: &mut PgConnection,
+        food_ids: &[Uuid],
+    ) -> sqlx::Result<Vec<StoredFoodNutrient>> {
+        let nutrients = sqlx::query_as!(
+            StoredFoodNutrient,
+            r#"
+            SELECT
+                fn.food_id,
+                n.name AS nutrient,
+                u.name AS unit,
+                fn.value
+            FROM
+                food_nutrients fn
+                JOIN nutrients n ON fn.nutrient_id = n.id
+                JOIN units u ON fn.unit_id = u.id
+            WHERE
+                fn.food_id = ANY ($1);
+            "#,
+            food_ids
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(nutrients)
+    }
+
     pub async fn create_or_update(
         executor: &mut PgConnection,
         create_nutrient_payload: CreateFoodNutrientPayload,
@@ -54,7 +90,7 @@ impl FoodNutrients {
             INSERT INTO food_nutrients (food_id, nutrient_id, unit_id, source_id, value)
             VALUES ($1, $2, $3, $4, $5)
             ON CONFLICT (food_id, nutrient_id, source_id)
-            DO UPDATE SET value = EXCLUDED.value
+            DO UPDATE SET value = EXCLUDED.value, unit_id = EXCLUDED.unit_id
             RETURNING *;
             "#,
             create_nutrient_payload.food_id,
@@ -72,11 +108,12 @@ impl FoodNutrients {
     pub async fn create_or_update_bulk(
         executor: &mut PgConnection,
         bulk_create_payload: Vec<CreateFoodNutrientPayload>,
-    ) -> sqlx::Result<()> {
+    ) -> sqlx::Result<u64> {
         if bulk_create_payload.is_empty() {
-            return Ok(());
+            return Ok(0);
         }
 
+        let mut written = 0;
         for chunk in bulk_create_payload.chunks(1000) {
             let mut query_builder = QueryBuilder::new(
                 "INSERT INTO food_nutrients (food_id, nutrient_id, unit_id, source_id, value) ",
@@ -90,10 +127,20 @@ impl FoodNutrients {
                     .push_bind(payload.value);
             });
 
-            query_builder.push(" ON CONFLICT (food_id, nutrient_id, source_id) DO NOTHING");
-            query_builder.build().execute(executor.as_mut()).await?;
+            // Values stored as zero before unknown values could be told apart get corrected, and so
+            // do units stored before the nutrient catalog converted values to its default units
+            query_builder.push(
+                r#" ON CONFLICT (food_id, nutrient_id, source_id) DO UPDATE SET
+                    value = EXCLUDED.value,
+                    unit_id = EXCLUDED.unit_id
+                WHERE (food_nutrients.value, food_nutrients.unit_id)
+                    IS DISTINCT FROM (EXCLUDED.value, EXCLUDED.unit_id)
+                "#,
+            );
+            let result = query_builder.build().execute(executor.as_mut()).await?;
+            written += result.rows_affected();
         }
 
-        Ok(())
+        Ok(written)
     }
 }
@@ -6,28 +6,33 @@ use sqlx::prelude::FromRow;
 use sqlx::types::Uuid;
 use sqlx::{PgConnection, QueryBuilder};
 
-use super::food_nutrients::FoodNutrients;
-use super::food_sources::FoodSources;
-use super::wweia_categories::WWEIACategories;
-
 #[derive(Debug, FromRow)]
 pub struct Foods {
     pub id: Uuid,
     pub name: String,
     pub source_id: Uuid,
-    pub external_id: i32,
+    pub external_id: String,
     pub fndds_code: Option<i32>,
     pub wweia_category: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Ids of the upserted foods keyed by their source id and external id, along with how many of
+/// them were inserted or updated
+#[derive(Debug)]
+pub struct UpsertedFoods {
+    pub ids: HashMap<(Uuid, String), Uuid>,
+    pub inserted: usize,
+    pub updated: usize,
+}
+
 #[derive(Debug)]
 pub struct CreateFoodPayload<'data> {
     pub name: &'data str,
     pub fndds_code: Option<i32>,
     pub source_id: Uuid,
-    pub external_id: i32,
+    pub external_id: String,
     pub wweia_category: Option<Uuid>,
 }
 
@@ -36,7 +41,7 @@ impl<'data> CreateFoodPayload<'data> {
         name: &'data str,
         fndds_code: Option<i32>,
         source_id: Uuid,
-        external_id: i32,
+        external_id: String,
         wweia_category: Option<Uuid>,
     ) -> Self {
         Self {
@@ -49,6 +54,14 @@ impl<'data> CreateFoodPayload<'data> {
     }
 }
 
+/// Food as currently stored, used to diff incoming entries without touching the live tables
+#[derive(Debug, FromRow)]
+pub struct StoredFood {
+    pub id: Uuid,
+    pub external_id: String,
+    pub name: String,
+}
+
 #[derive(Debug, Serialize, FromRow)]
 pub struct SearchSchemaFood {
     id: Uuid,
@@ -92,6 +105,34 @@ impl Foods {
         Ok(search_schema)
     }
 
+    pub async fn get_by_external_ids(
+        executor: &mut PgConnection,
+        source: &str,
+        external_ids: &[String],
+    ) -> sqlx::Result<Vec<StoredFood>> {
+        let foods = sqlx::query_as!(
+            StoredFood,
+            r#"
+            SELECT
+                f.id,
+                f.external_id,
+                f.name
+            FROM
+                foods f
+                JOIN food_sources fs ON f.source_id = fs.id
+            WHERE
+                fs.name = $1
+                AND f.external_id = ANY ($2);
+            "#,
+            source,
+            external_ids
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(foods)
+    }
+
     pub async fn create_or_update(
         executor: &mut PgConnection,
         create_food_payload: CreateFoodPayload<'_>,
@@ -122,7 +163,7 @@ impl Foods {
     pub async fn create_or_update_bulk(
         executor: &mut PgConnection,
         mut bulk_create_payload: impl Iterator<Item = CreateFoodPayload<'_>>,
-    ) -> sqlx::Result<HashMap<(String, i32), Uuid>> {
+    ) -> sqlx::Result<UpsertedFoods> {
         let mut query_builder = QueryBuilder::new(
             "INSERT INTO foods (name, source_id, external_id, fndds_code, wweia_category) ",
         );
@@ -138,26 +179,26 @@ impl Foods {
                 name = EXCLUDED.name,
                 fndds_code = EXCLUDED.fndds_code,
                 wweia_category = EXCLUDED.wweia_category
+            RETURNING id, source_id, external_id, (xmax = 0) AS inserted
             "#,
         );
-        query_builder.build().execute(executor.as_mut()).await?;
-
-        let mut query_builder = QueryBuilder::new(
-            r#"
-            SELECT f.id, f.external_id, s.name as source_name
-            FROM foods f
-            JOIN food_sources s ON f.source_id = s.id
-            "#,
-        );
-
-        let map = query_builder
-            .build_query_as::<(Uuid, i32, String)>()
-            .fetch_all(executor.as_mut())
-            .await?
+        // xmax is only zero for rows that didn't exist before the statement
+        let upserted = query_builder
+            .build_query_as::<(Uuid, Uuid, String, bool)>()
+            .fetch_all(executor)
+            .await?;
+        let inserted = upserted.iter().filter(|(.., inserted)| *inserted).count();
+        let updated = upserted.len() - inserted;
+
+        let ids = upserted
             .into_iter()
-            .map(|(id, external_id, source_name)| ((source_name, external_id), id))
+            .map(|(id, source_id, external_id, _)| ((source_id, external_id), id))
             .collect();
 
-        Ok(map)
+        Ok(UpsertedFoods {
+            ids,
+            inserted,
+            updated,
+        })
     }
 }
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use sqlx::PgPool;
+use sqlx::types::Uuid;
+
+use super::named_rows::{self, NamedTable};
+
+/// Ids of a small lookup table keyed by the unique name of each row, kept for the lifetime of the
+/// process. Rows of those tables are never removed, but only ids of committed rows may be cached,
+/// as a row whose transaction rolls back would leave its id dangling.
+#[derive(Debug, Default)]
+pub struct IdCache {
+    ids: Mutex<HashMap<String, Uuid>>,
+}
+
+impl IdCache {
+    /// Splits `names` into the ids that are cached and the names that have to be looked up
+    fn lookup<'a>(
+        &self,
+        names: impl Iterator<Item = &'a str>,
+    ) -> (HashMap<String, Uuid>, Vec<&'a str>) {
+        let ids = self.ids.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut found = HashMap::new();
+        let mut missing = vec![];
+
+        for name in names {
+            match ids.get(name) {
+                Some(id) => {
+                    found.insert(name.to_string(), *id);
+                }
+                None => missing.push(name),
+            }
+        }
+
+        (found, missing)
+    }
+
+    /// Ids of the given names, creating the missing rows of `table`. New rows are committed right
+    /// away rather than with the page that brought them, so their ids can be cached for the next
+    /// pages
+    pub async fn get_or_create<'a>(
+        &self,
+        pool: &PgPool,
+        table: NamedTable,
+        names: impl Iterator<Item = &'a str>,
+    ) -> sqlx::Result<HashMap<String, Uuid>> {
+        let (mut ids, missing) = self.lookup(names);
+        if missing.is_empty() {
+            return Ok(ids);
+        }
+
+        let mut conn = pool.acquire().await?;
+        let missing = missing.into_iter().map(|name| (name.to_string(), None));
+        let created = named_rows::maybe_create_bulk(conn.as_mut(), table, missing).await?;
+        self.extend(&created);
+        ids.extend(created);
+        Ok(ids)
+    }
+
+    pub fn extend(&self, committed: &HashMap<String, Uuid>) {
+        let mut ids = self.ids.lock().unwrap_or_else(PoisonError::into_inner);
+        ids.extend(committed.iter().map(|(name, id)| (name.clone(), *id)));
+    }
+}
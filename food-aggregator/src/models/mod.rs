@@ -1,7 +1,19 @@
+pub mod aggregation_checkpoints;
+pub mod aggregation_history;
 pub mod aggregation_metadata;
+pub mod aggregation_runs;
+pub mod aggregation_schedules;
+pub mod aggregation_source_foods;
+pub mod dead_letters;
+pub mod food_diffs;
+pub mod food_ingest;
 pub mod food_nutrients;
 pub mod food_sources;
 pub mod foods;
+pub mod id_cache;
+pub mod named_rows;
 pub mod nutrients;
+pub mod rate_limit_buckets;
+pub mod staging;
 pub mod units;
 pub mod wweia_categories;
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use sqlx::PgConnection;
+use sqlx::types::Uuid;
+
+/// Small lookup tables whose rows are identified by a unique name, created as the foods of a
+/// source refer to them
+#[derive(Debug, Clone, Copy)]
+pub enum NamedTable {
+    FoodSources,
+    Nutrients,
+    Units,
+    WweiaCategories,
+}
+
+impl NamedTable {
+    fn table(self) -> &'static str {
+        match self {
+            NamedTable::FoodSources => "food_sources",
+            NamedTable::Nutrients => "nutrients",
+            NamedTable::Units => "units",
+            NamedTable::WweiaCategories => "wweia_categories",
+        }
+    }
+
+    /// Columns written when creating a row, WWEIA categories also carry the code of the category
+    fn columns(self) -> &'static str {
+        match self {
+            NamedTable::WweiaCategories => "name, code",
+            _ => "name",
+        }
+    }
+}
+
+/// Creates the rows of `table` that don't exist yet, returning the ids of every given name. Rows
+/// are given as their name and code, the code is only stored by tables that have one.
+///
+/// The table name comes from [`NamedTable`] rather than the caller, which is why the statements
+/// are built at runtime instead of being checked by `query!`.
+pub async fn maybe_create_bulk(
+    executor: &mut PgConnection,
+    table: NamedTable,
+    rows: impl Iterator<Item = (String, Option<i32>)>,
+) -> sqlx::Result<HashMap<String, Uuid>> {
+    let (names, codes): (Vec<_>, Vec<_>) = rows.collect::<HashMap<_, _>>().into_iter().unzip();
+    if names.is_empty() {
+        return Ok(HashMap::default());
+    }
+
+    let (name, columns) = (table.table(), table.columns());
+
+    // The outer select reads the table as it was before the insert, so each name is returned
+    // once, either as an existing row or as a new one
+    let upsert = format!(
+        r#"
+        WITH input AS (
+            SELECT name, code FROM UNNEST($1::text[], $2::int4[]) AS input (name, code)
+        ),
+        inserted AS (
+            INSERT INTO {name} ({columns})
+            SELECT {columns} FROM input
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id, name
+        )
+        SELECT id, name FROM inserted
+        UNION ALL
+        SELECT t.id, t.name FROM {name} t JOIN input i ON t.name = i.name;
+        "#
+    );
+    let mut ids = sqlx::query_as::<_, (Uuid, String)>(&upsert)
+        .bind(&names)
+        .bind(&codes)
+        .fetch_all(executor.as_mut())
+        .await?
+        .into_iter()
+        .map(|(id, name)| (name, id))
+        .collect::<HashMap<_, _>>();
+    if ids.len() == names.len() {
+        return Ok(ids);
+    }
+
+    // Rows inserted by a transaction that committed while the upsert ran are skipped by it
+    // without being visible to it, so they are read again
+    let missing = names
+        .into_iter()
+        .filter(|name| !ids.contains_key(name))
+        .collect::<Vec<_>>();
+    let select = format!("SELECT id, name FROM {name} WHERE name = ANY ($1);");
+    let rows = sqlx::query_as::<_, (Uuid, String)>(&select)
+        .bind(&missing)
+        .fetch_all(executor)
+        .await?;
+    ids.extend(rows.into_iter().map(|(id, name)| (name, id)));
+
+    Ok(ids)
+}
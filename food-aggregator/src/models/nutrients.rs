@@ -1,14 +1,39 @@
 use std::collections::HashMap;
+use std::sync::LazyLock;
 
 use chrono::{DateTime, Utc};
 use sqlx::prelude::FromRow;
 use sqlx::types::Uuid;
-use sqlx::{PgConnection, QueryBuilder, Row};
+use sqlx::{PgConnection, PgPool};
+
+use super::id_cache::IdCache;
+use super::named_rows::NamedTable;
+use super::units::Units;
+use crate::nutrient_catalog::CatalogNutrient;
+
+static NUTRIENT_IDS: LazyLock<IdCache> = LazyLock::new(IdCache::default);
+
+/// Broad kind of a nutrient of the catalog, used to group nutrients when displaying them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "nutrient_group_type", rename_all = "snake_case")]
+pub enum NutrientGroup {
+    Macro,
+    Lipid,
+    Mineral,
+    Vitamin,
+}
 
 #[derive(Debug, FromRow)]
 pub struct Nutrients {
     pub id: Uuid,
     pub name: String,
+    /// USDA nutrient number, only set for nutrients of the catalog like the columns below
+    pub number: Option<String>,
+    /// INFOODS tagname
+    pub tagname: Option<String>,
+    pub default_unit_id: Option<Uuid>,
+    pub display_order: Option<i32>,
+    pub nutrient_group: Option<NutrientGroup>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -21,7 +46,16 @@ impl Nutrients {
             INSERT INTO nutrients (name)
             VALUES ($1)
             ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
-            RETURNING *;
+            RETURNING
+                id,
+                name,
+                number,
+                tagname,
+                default_unit_id,
+                display_order,
+                nutrient_group AS "nutrient_group: NutrientGroup",
+                created_at,
+                updated_at;
             "#,
             name
         )
@@ -31,31 +65,70 @@ impl Nutrients {
         Ok(nutrients)
     }
 
-    pub async fn maybe_create_bulk(
-        executor: &mut PgConnection,
-        bulk_payload: impl Iterator<Item = &str>,
+    /// Ids of the given nutrients, creating the missing ones, see [`IdCache::get_or_create`]
+    pub async fn get_or_create_ids<'a>(
+        pool: &PgPool,
+        names: impl Iterator<Item = &'a str>,
     ) -> sqlx::Result<HashMap<String, Uuid>> {
-        let bulk_payload = bulk_payload.collect::<Vec<_>>();
-        if bulk_payload.is_empty() {
-            return Ok(HashMap::default());
-        }
-
-        let mut query_builder = QueryBuilder::new("INSERT INTO nutrients (name) ");
-        query_builder.push_values(bulk_payload, |mut b, name| {
-            b.push_bind(name);
-        });
-        query_builder.push(" ON CONFLICT (name) DO NOTHING");
-        query_builder.build().execute(executor.as_mut()).await?;
-
-        let rows = sqlx::query("SELECT id, name FROM nutrients")
-            .fetch_all(executor)
-            .await?;
-
-        let map = rows
-            .into_iter()
-            .map(|row| (row.get("name"), row.get("id")))
-            .collect();
+        NUTRIENT_IDS
+            .get_or_create(pool, NamedTable::Nutrients, names)
+            .await
+    }
+
+    /// Upserts the nutrients of the catalog with their catalog data, claiming the rows created
+    /// under the same name before the catalog existed, and caches their ids
+    pub async fn sync_catalog(pool: &PgPool, catalog: &[CatalogNutrient]) -> sqlx::Result<()> {
+        let unit_ids = Units::get_or_create_ids(pool, catalog.iter().map(|n| n.unit)).await?;
 
-        Ok(map)
+        let names = catalog.iter().map(|n| n.name).collect::<Vec<_>>();
+        let numbers = catalog.iter().map(|n| n.number).collect::<Vec<_>>();
+        let tagnames = catalog.iter().map(|n| n.tagname).collect::<Vec<_>>();
+        let units = catalog.iter().map(|n| unit_ids[n.unit]).collect::<Vec<_>>();
+        let orders = (1..=catalog.len() as i32).collect::<Vec<_>>();
+        let groups = catalog.iter().map(|n| n.group).collect::<Vec<_>>();
+
+        let mut conn = pool.acquire().await?;
+        let rows = sqlx::query!(
+            r#"
+            INSERT INTO nutrients (
+                name,
+                number,
+                tagname,
+                default_unit_id,
+                display_order,
+                nutrient_group
+            )
+            SELECT * FROM UNNEST(
+                $1::text[],
+                $2::text[],
+                $3::text[],
+                $4::uuid[],
+                $5::int4[],
+                $6::nutrient_group_type[]
+            )
+            ON CONFLICT (name) DO UPDATE SET
+                number = EXCLUDED.number,
+                tagname = EXCLUDED.tagname,
+                default_unit_id = EXCLUDED.default_unit_id,
+                display_order = EXCLUDED.display_order,
+                nutrient_group = EXCLUDED.nutrient_group
+            RETURNING id, name;
+            "#,
+            &names as &[&str],
+            &numbers as &[&str],
+            &tagnames as &[&str],
+            &units,
+            &orders,
+            &groups as &[NutrientGroup]
+        )
+        .fetch_all(conn.as_mut())
+        .await?;
+
+        let ids = rows
+            .into_iter()
+            .map(|row| (row.name, row.id))
+            .collect::<HashMap<_, _>>();
+        NUTRIENT_IDS.extend(&ids);
+        Ok(())
     }
 }
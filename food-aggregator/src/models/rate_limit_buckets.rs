@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use sqlx::prelude::FromRow;
+
+/// Request budget of a source, shared by every process fetching from it
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RateLimitBucket {
+    pub source: String,
+    /// Requests left as of `refilled_at`, fractional as the budget refills continuously
+    pub tokens: f64,
+    pub refilled_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl RateLimitBucket {
+    /// Locks the bucket of `source` until the end of the transaction, creating it with
+    /// `capacity` tokens when the source never made a request. Also returns the database clock,
+    /// so every process refills the bucket against the same time.
+    pub async fn lock(
+        executor: &mut PgConnection,
+        source: &str,
+        capacity: f64,
+    ) -> sqlx::Result<(RateLimitBucket, DateTime<Utc>)> {
+        sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_buckets (source, tokens)
+            VALUES ($1, $2)
+            ON CONFLICT (source) DO NOTHING;
+            "#,
+            source,
+            capacity
+        )
+        .execute(&mut *executor)
+        .await?;
+
+        let bucket = sqlx::query_as!(
+            RateLimitBucket,
+            r#"
+            SELECT
+                *
+            FROM
+                rate_limit_buckets
+            WHERE
+                source = $1
+            FOR UPDATE;
+            "#,
+            source
+        )
+        .fetch_one(&mut *executor)
+        .await?;
+
+        // NOW() is when the transaction started, which can be long before the lock was taken
+        let now = sqlx::query_scalar!(r#"SELECT clock_timestamp() AS "now!";"#)
+            .fetch_one(executor)
+            .await?;
+
+        Ok((bucket, now))
+    }
+
+    pub async fn update(
+        executor: &mut PgConnection,
+        source: &str,
+        tokens: f64,
+        refilled_at: DateTime<Utc>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE rate_limit_buckets
+            SET
+                tokens = $2,
+                refilled_at = $3
+            WHERE
+                source = $1;
+            "#,
+            source,
+            tokens,
+            refilled_at
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all(executor: &mut PgConnection) -> sqlx::Result<Vec<RateLimitBucket>> {
+        let buckets = sqlx::query_as!(
+            RateLimitBucket,
+            r#"
+            SELECT
+                *
+            FROM
+                rate_limit_buckets
+            ORDER BY
+                source;
+            "#
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(buckets)
+    }
+}
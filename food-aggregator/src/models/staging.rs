@@ -0,0 +1,118 @@
+use sqlx::PgConnection;
+
+use super::aggregation_history::AggregationStats;
+
+/// Staging copies of `foods` and `food_nutrients`, living in the `aggregation_staging` schema.
+pub struct Staging;
+
+impl Staging {
+    /// Makes every unqualified `foods` and `food_nutrients` statement of the current transaction
+    /// target the staging tables, while lookup tables keep resolving to the public schema.
+    pub async fn route_transaction(executor: &mut PgConnection) -> sqlx::Result<()> {
+        sqlx::query("SET LOCAL search_path TO aggregation_staging, public;")
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Merges everything staged by the configured `source` into the live tables and clears it from staging,
+    /// returning how many foods and nutrients were written to the live tables.
+    pub async fn swap_source(
+        executor: &mut PgConnection,
+        source: &str,
+    ) -> sqlx::Result<AggregationStats> {
+        // xmax is only zero for rows that didn't exist before the statement
+        let upserted = sqlx::query_scalar!(
+            r#"
+            INSERT INTO public.foods (name, source_id, external_id, fndds_code, wweia_category)
+            SELECT
+                sf.name,
+                sf.source_id,
+                sf.external_id,
+                sf.fndds_code,
+                sf.wweia_category
+            FROM
+                aggregation_staging.foods sf
+                JOIN public.aggregation_source_foods a ON sf.source_id = a.food_source_id
+                    AND sf.external_id = a.external_id
+            WHERE
+                a.source = $1
+            ON CONFLICT (source_id, external_id) DO UPDATE SET
+                name = EXCLUDED.name,
+                fndds_code = EXCLUDED.fndds_code,
+                wweia_category = EXCLUDED.wweia_category
+            RETURNING (xmax = 0) AS "inserted!";
+            "#,
+            source
+        )
+        .fetch_all(executor.as_mut())
+        .await?;
+        let foods_inserted = upserted.iter().filter(|inserted| **inserted).count() as i64;
+
+        // Staged foods got their own ids, so nutrients are matched to the live foods through the
+        // (source_id, external_id) pair instead
+        let nutrients = sqlx::query!(
+            r#"
+            INSERT INTO public.food_nutrients (food_id, nutrient_id, unit_id, source_id, value)
+            SELECT
+                f.id,
+                sfn.nutrient_id,
+                sfn.unit_id,
+                sfn.source_id,
+                sfn.value
+            FROM
+                aggregation_staging.food_nutrients sfn
+                JOIN aggregation_staging.foods sf ON sfn.food_id = sf.id
+                JOIN public.foods f ON f.source_id = sf.source_id
+                    AND f.external_id = sf.external_id
+                JOIN public.aggregation_source_foods a ON sf.source_id = a.food_source_id
+                    AND sf.external_id = a.external_id
+            WHERE
+                a.source = $1
+            ON CONFLICT (food_id, nutrient_id, source_id) DO UPDATE SET
+                value = EXCLUDED.value,
+                unit_id = EXCLUDED.unit_id
+            WHERE (public.food_nutrients.value, public.food_nutrients.unit_id)
+                IS DISTINCT FROM (EXCLUDED.value, EXCLUDED.unit_id);
+            "#,
+            source
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM aggregation_staging.food_nutrients sfn
+            USING aggregation_staging.foods sf, public.aggregation_source_foods a
+            WHERE sfn.food_id = sf.id
+                AND sf.source_id = a.food_source_id
+                AND sf.external_id = a.external_id
+                AND a.source = $1;
+            "#,
+            source
+        )
+        .execute(executor.as_mut())
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM aggregation_staging.foods sf
+            USING public.aggregation_source_foods a
+            WHERE sf.source_id = a.food_source_id
+                AND sf.external_id = a.external_id
+                AND a.source = $1;
+            "#,
+            source
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(AggregationStats {
+            foods_inserted,
+            foods_updated: upserted.len() as i64 - foods_inserted,
+            nutrients_written: nutrients.rows_affected() as i64,
+            ..AggregationStats::default()
+        })
+    }
+}
@@ -1,9 +1,15 @@
 use std::collections::HashMap;
+use std::sync::LazyLock;
 
 use chrono::{DateTime, Utc};
 use sqlx::prelude::FromRow;
 use sqlx::types::Uuid;
-use sqlx::{PgConnection, QueryBuilder, Row};
+use sqlx::{PgConnection, PgPool};
+
+use super::id_cache::IdCache;
+use super::named_rows::NamedTable;
+
+static UNIT_IDS: LazyLock<IdCache> = LazyLock::new(IdCache::default);
 
 #[derive(Debug, FromRow)]
 pub struct Units {
@@ -31,31 +37,11 @@ impl Units {
         Ok(units)
     }
 
-    pub async fn maybe_create_bulk(
-        executor: &mut PgConnection,
-        bulk_payload: impl Iterator<Item = &str>,
+    /// Ids of the given units, creating the missing ones, see [`IdCache::get_or_create`]
+    pub async fn get_or_create_ids<'a>(
+        pool: &PgPool,
+        names: impl Iterator<Item = &'a str>,
     ) -> sqlx::Result<HashMap<String, Uuid>> {
-        let bulk_payload = bulk_payload.collect::<Vec<_>>();
-        if bulk_payload.is_empty() {
-            return Ok(HashMap::default());
-        }
-
-        let mut query_builder = QueryBuilder::new("INSERT INTO units (name) ");
-        query_builder.push_values(bulk_payload, |mut b, name| {
-            b.push_bind(name);
-        });
-        query_builder.push(" ON CONFLICT (name) DO NOTHING");
-        query_builder.build().execute(executor.as_mut()).await?;
-
-        let rows = sqlx::query("SELECT id, name FROM units")
-            .fetch_all(executor)
-            .await?;
-
-        let map = rows
-            .into_iter()
-            .map(|row| (row.get("name"), row.get("id")))
-            .collect();
-
-        Ok(map)
+        UNIT_IDS.get_or_create(pool, NamedTable::Units, names).await
     }
 }
@@ -2,9 +2,11 @@ use std::collections::HashMap;
 
 use chrono::{DateTime, Utc};
 use serde::Serialize;
+use sqlx::PgConnection;
 use sqlx::prelude::FromRow;
 use sqlx::types::Uuid;
-use sqlx::{PgConnection, QueryBuilder, Row};
+
+use super::named_rows::{self, NamedTable};
 
 #[derive(Debug, Serialize, FromRow)]
 pub struct WWEIACategories {
@@ -49,33 +51,13 @@ impl WWEIACategories {
         Ok(category)
     }
 
+    /// Creates the categories that don't exist yet, returning the ids of every given category
+    /// keyed by its name
     pub async fn maybe_create_bulk(
         executor: &mut PgConnection,
         bulk_payload: impl Iterator<Item = (i32, &String)>,
     ) -> sqlx::Result<HashMap<String, Uuid>> {
-        let bulk_payload = bulk_payload.collect::<Vec<_>>();
-        if bulk_payload.is_empty() {
-            return Ok(HashMap::default());
-        }
-
-        let mut query_builder = QueryBuilder::new("INSERT INTO wweia_categories (code, name) ");
-
-        query_builder.push_values(bulk_payload, |mut b, (code, name)| {
-            b.push_bind(code).push_bind(name);
-        });
-
-        query_builder.push(" ON CONFLICT (name) DO NOTHING");
-        query_builder.build().execute(executor.as_mut()).await?;
-
-        let rows = sqlx::query("SELECT id, name FROM wweia_categories")
-            .fetch_all(executor)
-            .await?;
-
-        let map = rows
-            .into_iter()
-            .map(|row| (row.get("name"), row.get("id")))
-            .collect();
-
-        Ok(map)
+        let rows = bulk_payload.map(|(code, name)| (name.clone(), Some(code)));
+        named_rows::maybe_create_bulk(executor, NamedTable::WweiaCategories, rows).await
     }
 }
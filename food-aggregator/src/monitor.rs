@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Uuid;
+use tokio::sync::{Notify, broadcast};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// How many events a slow subscriber can fall behind before it starts missing them
+const EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum SourceState {
+    /// Configured, but not aggregated since the process started
+    Idle,
+    /// Waiting for the scheduler to pick it up
+    Queued,
+    Running,
+    /// Rate limited, resumes once the wake time is reached
+    Waiting {
+        until: DateTime<Utc>,
+    },
+    Finished,
+    Failed {
+        error: String,
+    },
+    Cancelled,
+}
+
+impl SourceState {
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            SourceState::Queued | SourceState::Running | SourceState::Waiting { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatus {
+    pub source: String,
+    #[serde(flatten)]
+    pub state: SourceState,
+    pub run_id: Option<Uuid>,
+    /// Pages that failed to fetch and are backing off before being retried
+    pub pending_retries: usize,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SourceStatus {
+    pub fn idle(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            state: SourceState::Idle,
+            run_id: None,
+            pending_retries: 0,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+/// Something that happened while aggregating a source, published as it happens so it can be
+/// followed live
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+    pub source: String,
+    pub run_id: Option<Uuid>,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: ProgressEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEventKind {
+    State {
+        #[serde(flatten)]
+        state: SourceState,
+    },
+    /// The first page was fetched, so the size of the run is known
+    RunStarted {
+        total_pages: usize,
+        first_page: usize,
+    },
+    /// Pages fetched before the workers are spawned have no worker id
+    PageStarted {
+        worker_id: Option<usize>,
+        page: usize,
+        retries: usize,
+    },
+    PagePersisted {
+        worker_id: Option<usize>,
+        page: usize,
+        retries: usize,
+        persist_ms: u64,
+    },
+    PageRetrying {
+        page: usize,
+        retries: usize,
+        delay_ms: u64,
+        error: String,
+    },
+    PageFailed {
+        page: usize,
+        retries: usize,
+        error: String,
+    },
+    /// How many workers may fetch at once changed with the load on the source
+    WorkersAdjusted { workers: usize },
+    /// The aggregation failed or panicked, and runs again after `delay_ms`
+    RestartScheduled {
+        restarts: usize,
+        delay_ms: u64,
+        error: String,
+    },
+    /// The aggregation failed after every restart it was allowed, and waits to be triggered again
+    RestartsExhausted { restarts: usize, error: String },
+}
+
+impl ProgressEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProgressEventKind::State { .. } => "state",
+            ProgressEventKind::RunStarted { .. } => "run_started",
+            ProgressEventKind::PageStarted { .. } => "page_started",
+            ProgressEventKind::PagePersisted { .. } => "page_persisted",
+            ProgressEventKind::PageRetrying { .. } => "page_retrying",
+            ProgressEventKind::PageFailed { .. } => "page_failed",
+            ProgressEventKind::WorkersAdjusted { .. } => "workers_adjusted",
+            ProgressEventKind::RestartScheduled { .. } => "restart_scheduled",
+            ProgressEventKind::RestartsExhausted { .. } => "restarts_exhausted",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TrackedSource {
+    status: SourceStatus,
+    /// Stops the aggregation of the source, letting the pages in flight finish first
+    cancel: Option<CancellationToken>,
+    /// Wakes the scheduling loop the source was queued in, so it notices a cancellation
+    wake: Option<Arc<Notify>>,
+}
+
+/// Live state of every source aggregated by this process, shared between the scheduling loop,
+/// the aggregators and whoever wants to observe or cancel them.
+#[derive(Debug, Clone)]
+pub struct AggregationMonitor {
+    sources: Arc<Mutex<BTreeMap<String, TrackedSource>>>,
+    events: broadcast::Sender<ProgressEvent>,
+}
+
+impl Default for AggregationMonitor {
+    fn default() -> Self {
+        Self {
+            sources: Arc::default(),
+            events: broadcast::Sender::new(EVENT_CAPACITY),
+        }
+    }
+}
+
+impl AggregationMonitor {
+    /// Receives every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn status(&self) -> Vec<SourceStatus> {
+        self.sources()
+            .values()
+            .map(|tracked| tracked.status.clone())
+            .collect()
+    }
+
+    pub fn source_status(&self, source: &str) -> Option<SourceStatus> {
+        self.sources()
+            .get(source)
+            .map(|tracked| tracked.status.clone())
+    }
+
+    pub fn is_active(&self, source: &str) -> bool {
+        self.sources()
+            .get(source)
+            .is_some_and(|tracked| tracked.status.state.is_active())
+    }
+
+    pub fn active_sources(&self) -> Vec<String> {
+        self.sources()
+            .iter()
+            .filter(|(_, tracked)| tracked.status.state.is_active())
+            .map(|(source, _)| source.clone())
+            .collect()
+    }
+
+    /// Cancels the aggregation of `source`. A running aggregation stops fetching new pages and
+    /// winds down once the pages in flight are persisted, the run keeps its checkpoints so it
+    /// resumes from where it stopped the next time it is triggered.
+    ///
+    /// Returns whether the source was being aggregated.
+    pub fn cancel(&self, source: &str) -> bool {
+        let mut sources = self.sources();
+        let Some(tracked) = sources.get_mut(source) else { return false };
+
+        if !tracked.status.state.is_active() {
+            return false;
+        }
+
+        if let Some(cancel) = &tracked.cancel {
+            cancel.cancel();
+        }
+
+        if let Some(wake) = &tracked.wake {
+            wake.notify_one();
+        }
+
+        self.set_state(tracked, SourceState::Cancelled);
+        true
+    }
+
+    pub fn source(&self, source: impl Into<String>) -> SourceMonitor {
+        SourceMonitor {
+            monitor: self.clone(),
+            source: source.into(),
+        }
+    }
+
+    /// Marks `source` as queued in the scheduling loop woken by `wake`, unless it is already
+    /// being aggregated by another one. Cancelling the source cancels `cancel`.
+    pub(crate) fn queue(&self, source: &str, wake: Arc<Notify>, cancel: CancellationToken) -> bool {
+        let mut sources = self.sources();
+        if sources
+            .get(source)
+            .is_some_and(|tracked| tracked.status.state.is_active())
+        {
+            return false;
+        }
+
+        let tracked = sources
+            .entry(source.to_string())
+            .insert_entry(TrackedSource {
+                status: SourceStatus::idle(source),
+                cancel: Some(cancel),
+                wake: Some(wake),
+            })
+            .into_mut();
+        self.set_state(tracked, SourceState::Queued);
+
+        true
+    }
+
+    pub(crate) fn is_cancelled(&self, source: &str) -> bool {
+        self.sources()
+            .get(source)
+            .is_some_and(|tracked| tracked.status.state == SourceState::Cancelled)
+    }
+
+    pub(crate) fn start(&self, source: &str) {
+        self.update(source, |tracked| {
+            self.set_state(tracked, SourceState::Running)
+        });
+    }
+
+    pub(crate) fn wait(&self, source: &str, until: Instant) {
+        let until = Utc::now() + until.saturating_duration_since(Instant::now());
+        self.settle(source, SourceState::Waiting { until });
+    }
+
+    pub(crate) fn finish(&self, source: &str) {
+        self.settle(source, SourceState::Finished);
+    }
+
+    pub(crate) fn fail(&self, source: &str, error: String) {
+        self.settle(source, SourceState::Failed { error });
+    }
+
+    /// Marks a source that stopped because the whole aggregation was cancelled
+    pub(crate) fn cancelled(&self, source: &str) {
+        self.settle(source, SourceState::Cancelled);
+    }
+
+    /// Moves a running source out of the running state, a cancellation always takes precedence
+    fn settle(&self, source: &str, state: SourceState) {
+        self.update(source, |tracked| {
+            if tracked.status.state == SourceState::Cancelled {
+                return;
+            }
+
+            self.set_state(tracked, state);
+        });
+    }
+
+    fn set_state(&self, tracked: &mut TrackedSource, state: SourceState) {
+        tracked.status.state = state.clone();
+        tracked.status.updated_at = Utc::now();
+        self.publish(
+            &tracked.status.source,
+            tracked.status.run_id,
+            ProgressEventKind::State { state },
+        );
+    }
+
+    fn publish(&self, source: &str, run_id: Option<Uuid>, kind: ProgressEventKind) {
+        let event = ProgressEvent {
+            source: source.to_string(),
+            run_id,
+            at: Utc::now(),
+            kind,
+        };
+
+        // Sending only fails when nobody is following the aggregation
+        if self.events.send(event).is_err() {
+            tracing::trace!(%source, "No subscribers for progress event");
+        }
+    }
+
+    fn update(&self, source: &str, update: impl FnOnce(&mut TrackedSource)) {
+        if let Some(tracked) = self.sources().get_mut(source) {
+            update(tracked);
+        }
+    }
+
+    fn sources(&self) -> MutexGuard<'_, BTreeMap<String, TrackedSource>> {
+        // The map is only ever mutated through small infallible updates, so a poisoned lock
+        // doesn't leave it inconsistent
+        self.sources.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Handle aggregators use to report the progress of a single source
+#[derive(Debug, Clone)]
+pub struct SourceMonitor {
+    monitor: AggregationMonitor,
+    source: String,
+}
+
+impl SourceMonitor {
+    pub fn set_run(&self, run_id: Uuid) {
+        self.monitor.update(&self.source, |tracked| {
+            tracked.status.run_id = Some(run_id);
+            tracked.status.updated_at = Utc::now();
+        });
+    }
+
+    pub fn publish(&self, kind: ProgressEventKind) {
+        let run_id = self
+            .monitor
+            .source_status(&self.source)
+            .and_then(|status| status.run_id);
+        self.monitor.publish(&self.source, run_id, kind);
+    }
+
+    pub fn set_pending_retries(&self, pending_retries: usize) {
+        self.monitor.update(&self.source, |tracked| {
+            tracked.status.pending_retries = pending_retries;
+            tracked.status.updated_at = Utc::now();
+        });
+    }
+}
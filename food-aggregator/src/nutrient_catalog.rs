@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use sqlx::PgPool;
+use tokio::sync::OnceCell;
+
+use crate::models::nutrients::{NutrientGroup, Nutrients};
+use crate::supervisor::FoodEntryNutrient;
+
+/// Nutrient of the canonical catalog, every source reporting it under any of its aliases and in a
+/// unit convertible to its default unit is stored as this same nutrient
+#[derive(Debug)]
+pub struct CatalogNutrient {
+    /// USDA nutrient number
+    pub number: &'static str,
+    /// INFOODS tagname
+    pub tagname: &'static str,
+    /// Name the nutrient is stored as, the one used by USDA so existing rows are kept
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub group: NutrientGroup,
+    /// Names used by the sources for this nutrient
+    pub aliases: &'static [&'static str],
+}
+
+/// Nutrients of the catalog, in the order they are displayed in
+pub const CATALOG: &[CatalogNutrient] = &[
+    CatalogNutrient {
+        number: "208",
+        tagname: "ENERC_KCAL",
+        name: "Energy",
+        unit: "kcal",
+        group: NutrientGroup::Macro,
+        aliases: &["energy-kcal"],
+    },
+    CatalogNutrient {
+        number: "268",
+        tagname: "ENERC_KJ",
+        name: "Energy (kJ)",
+        unit: "kJ",
+        group: NutrientGroup::Macro,
+        aliases: &["Energy", "energy-kj"],
+    },
+    CatalogNutrient {
+        number: "203",
+        tagname: "PROCNT",
+        name: "Protein",
+        unit: "g",
+        group: NutrientGroup::Macro,
+        aliases: &["proteins"],
+    },
+    CatalogNutrient {
+        number: "205",
+        tagname: "CHOCDF",
+        name: "Carbohydrate, by difference",
+        unit: "g",
+        group: NutrientGroup::Macro,
+        aliases: &["carbohydrates"],
+    },
+    CatalogNutrient {
+        number: "269",
+        tagname: "SUGAR",
+        name: "Total Sugars",
+        unit: "g",
+        group: NutrientGroup::Macro,
+        aliases: &["Sugars, total including NLEA", "sugars"],
+    },
+    CatalogNutrient {
+        number: "291",
+        tagname: "FIBTG",
+        name: "Fiber, total dietary",
+        unit: "g",
+        group: NutrientGroup::Macro,
+        aliases: &["fiber"],
+    },
+    CatalogNutrient {
+        number: "255",
+        tagname: "WATER",
+        name: "Water",
+        unit: "g",
+        group: NutrientGroup::Macro,
+        aliases: &[],
+    },
+    CatalogNutrient {
+        number: "221",
+        tagname: "ALC",
+        name: "Alcohol, ethyl",
+        unit: "g",
+        group: NutrientGroup::Macro,
+        aliases: &[],
+    },
+    CatalogNutrient {
+        number: "204",
+        tagname: "FAT",
+        name: "Total lipid (fat)",
+        unit: "g",
+        group: NutrientGroup::Lipid,
+        aliases: &["fat"],
+    },
+    CatalogNutrient {
+        number: "606",
+        tagname: "FASAT",
+        name: "Fatty acids, total saturated",
+        unit: "g",
+        group: NutrientGroup::Lipid,
+        aliases: &["saturated-fat"],
+    },
+    CatalogNutrient {
+        number: "645",
+        tagname: "FAMS",
+        name: "Fatty acids, total monounsaturated",
+        unit: "g",
+        group: NutrientGroup::Lipid,
+        aliases: &["monounsaturated-fat"],
+    },
+    CatalogNutrient {
+        number: "646",
+        tagname: "FAPU",
+        name: "Fatty acids, total polyunsaturated",
+        unit: "g",
+        group: NutrientGroup::Lipid,
+        aliases: &["polyunsaturated-fat"],
+    },
+    CatalogNutrient {
+        number: "605",
+        tagname: "FATRN",
+        name: "Fatty acids, total trans",
+        unit: "g",
+        group: NutrientGroup::Lipid,
+        aliases: &["trans-fat"],
+    },
+    CatalogNutrient {
+        number: "601",
+        tagname: "CHOLE",
+        name: "Cholesterol",
+        unit: "mg",
+        group: NutrientGroup::Lipid,
+        aliases: &["cholesterol"],
+    },
+    CatalogNutrient {
+        number: "301",
+        tagname: "CA",
+        name: "Calcium, Ca",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["calcium"],
+    },
+    CatalogNutrient {
+        number: "303",
+        tagname: "FE",
+        name: "Iron, Fe",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["iron"],
+    },
+    CatalogNutrient {
+        number: "304",
+        tagname: "MG",
+        name: "Magnesium, Mg",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["magnesium"],
+    },
+    CatalogNutrient {
+        number: "305",
+        tagname: "P",
+        name: "Phosphorus, P",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["phosphorus"],
+    },
+    CatalogNutrient {
+        number: "306",
+        tagname: "K",
+        name: "Potassium, K",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["potassium"],
+    },
+    CatalogNutrient {
+        number: "307",
+        tagname: "NA",
+        name: "Sodium, Na",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["sodium"],
+    },
+    CatalogNutrient {
+        number: "309",
+        tagname: "ZN",
+        name: "Zinc, Zn",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["zinc"],
+    },
+    CatalogNutrient {
+        number: "312",
+        tagname: "CU",
+        name: "Copper, Cu",
+        unit: "mg",
+        group: NutrientGroup::Mineral,
+        aliases: &["copper"],
+    },
+    CatalogNutrient {
+        number: "317",
+        tagname: "SE",
+        name: "Selenium, Se",
+        unit: "µg",
+        group: NutrientGroup::Mineral,
+        aliases: &["selenium"],
+    },
+    CatalogNutrient {
+        number: "320",
+        tagname: "VITA_RAE",
+        name: "Vitamin A, RAE",
+        unit: "µg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-a"],
+    },
+    CatalogNutrient {
+        number: "401",
+        tagname: "VITC",
+        name: "Vitamin C, total ascorbic acid",
+        unit: "mg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-c"],
+    },
+    CatalogNutrient {
+        number: "328",
+        tagname: "VITD",
+        name: "Vitamin D (D2 + D3)",
+        unit: "µg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-d"],
+    },
+    CatalogNutrient {
+        number: "323",
+        tagname: "TOCPHA",
+        name: "Vitamin E (alpha-tocopherol)",
+        unit: "mg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-e"],
+    },
+    CatalogNutrient {
+        number: "430",
+        tagname: "VITK1",
+        name: "Vitamin K (phylloquinone)",
+        unit: "µg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-k"],
+    },
+    CatalogNutrient {
+        number: "404",
+        tagname: "THIA",
+        name: "Thiamin",
+        unit: "mg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-b1"],
+    },
+    CatalogNutrient {
+        number: "405",
+        tagname: "RIBF",
+        name: "Riboflavin",
+        unit: "mg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-b2"],
+    },
+    CatalogNutrient {
+        number: "406",
+        tagname: "NIA",
+        name: "Niacin",
+        unit: "mg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-pp"],
+    },
+    CatalogNutrient {
+        number: "415",
+        tagname: "VITB6A",
+        name: "Vitamin B-6",
+        unit: "mg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-b6"],
+    },
+    CatalogNutrient {
+        number: "417",
+        tagname: "FOL",
+        name: "Folate, total",
+        unit: "µg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-b9"],
+    },
+    CatalogNutrient {
+        number: "418",
+        tagname: "VITB12",
+        name: "Vitamin B-12",
+        unit: "µg",
+        group: NutrientGroup::Vitamin,
+        aliases: &["vitamin-b12"],
+    },
+];
+
+/// Set once the catalog was written to the database by this process
+static SYNCED: OnceCell<()> = OnceCell::const_new();
+
+/// Writes the catalog to the `nutrients` table the first time it is called, so catalog nutrients
+/// have their catalog data before any food references them
+pub async fn sync(pool: &PgPool) -> sqlx::Result<()> {
+    SYNCED
+        .get_or_try_init(|| Nutrients::sync_catalog(pool, CATALOG))
+        .await?;
+    Ok(())
+}
+
+/// Catalog nutrients by every name they are known by. A name can belong to more than one of them,
+/// like USDA reporting energy as `Energy` both in kcal and in kJ, the unit tells them apart
+static BY_NAME: LazyLock<HashMap<&str, Vec<&CatalogNutrient>>> = LazyLock::new(|| {
+    let mut by_name = HashMap::<_, Vec<_>>::new();
+    for nutrient in CATALOG {
+        for name in std::iter::once(&nutrient.name).chain(nutrient.aliases) {
+            by_name.entry(*name).or_default().push(nutrient);
+        }
+    }
+    by_name
+});
+
+/// Catalog nutrients by their USDA nutrient number, which unlike names is unique to each of them
+static BY_NUMBER: LazyLock<HashMap<&str, &CatalogNutrient>> = LazyLock::new(|| {
+    CATALOG
+        .iter()
+        .map(|nutrient| (nutrient.number, nutrient))
+        .collect()
+});
+
+/// Catalog nutrients by their INFOODS tagname, which national composition tables identify
+/// nutrients by
+static BY_TAGNAME: LazyLock<HashMap<&str, &CatalogNutrient>> = LazyLock::new(|| {
+    CATALOG
+        .iter()
+        .map(|nutrient| (nutrient.tagname, nutrient))
+        .collect()
+});
+
+/// Nutrient as it is stored, once mapped onto the catalog
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanonicalNutrient<'a> {
+    pub name: &'a str,
+    pub unit: &'a str,
+    pub value: Option<f32>,
+}
+
+/// Maps a nutrient reported by a source onto the catalog, converting its value to the default
+/// unit of the catalog nutrient. Nutrients are matched by their number or tagname when the source
+/// reports one, and by name otherwise. Nutrients the catalog doesn't know, or reported in a unit
+/// that can't be converted, are kept as the source reported them
+pub fn canonical(nutrient: &impl FoodEntryNutrient) -> CanonicalNutrient<'_> {
+    let (name, unit, value) = (nutrient.name(), nutrient.unit_name(), nutrient.value());
+    let candidates = match (nutrient.number(), nutrient.tagname()) {
+        (Some(number), _) => BY_NUMBER.get(number.trim()).into_iter().copied().collect(),
+        (None, Some(tagname)) => BY_TAGNAME
+            .get(tagname.trim().to_uppercase().as_str())
+            .into_iter()
+            .copied()
+            .collect(),
+        (None, None) => BY_NAME.get(name).cloned().unwrap_or_default(),
+    };
+    let catalog = candidates.into_iter().find_map(|nutrient| {
+        let factor = conversion_factor(unit, nutrient.unit)?;
+        Some((nutrient, factor))
+    });
+
+    match catalog {
+        Some((nutrient, factor)) => CanonicalNutrient {
+            name: nutrient.name,
+            unit: nutrient.unit,
+            value: value.map(|value| (f64::from(value) * factor) as f32),
+        },
+        None => CanonicalNutrient { name, unit, value },
+    }
+}
+
+/// Factor converting a value from one unit to another, `None` when they measure different things
+fn conversion_factor(from: &str, to: &str) -> Option<f64> {
+    match (unit_scale(from)?, unit_scale(to)?) {
+        (Scale::Mass(from), Scale::Mass(to)) => Some(10f64.powi(from - to)),
+        (Scale::Kcal, Scale::Kcal) | (Scale::Kj, Scale::Kj) => Some(1.0),
+        _ => None,
+    }
+}
+
+enum Scale {
+    /// Mass as the power of ten of a gram
+    Mass(i32),
+    Kcal,
+    Kj,
+}
+
+/// Sources spell units differently, USDA uses `G` and `UG` where others use `g` and `µg`
+fn unit_scale(unit: &str) -> Option<Scale> {
+    let scale = match unit.trim().to_lowercase().as_str() {
+        "g" => Scale::Mass(0),
+        "mg" => Scale::Mass(-3),
+        "µg" | "μg" | "ug" | "mcg" => Scale::Mass(-6),
+        "kcal" => Scale::Kcal,
+        "kj" => Scale::Kj,
+        _ => return None,
+    };
+    Some(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::NutrientRecord;
+
+    fn nutrient(name: &str, unit: &str, value: f32) -> NutrientRecord {
+        NutrientRecord {
+            name: name.to_string(),
+            number: None,
+            tagname: None,
+            unit: unit.to_string(),
+            value: Some(value),
+        }
+    }
+
+    fn stored(nutrient: &NutrientRecord) -> (&str, &str, Option<f32>) {
+        let canonical = canonical(nutrient);
+        (canonical.name, canonical.unit, canonical.value)
+    }
+
+    #[test]
+    fn energy_is_told_apart_by_unit() {
+        assert_eq!(
+            stored(&nutrient("Energy", "KCAL", 52.0)),
+            ("Energy", "kcal", Some(52.0))
+        );
+        assert_eq!(
+            stored(&nutrient("Energy", "kJ", 218.0)),
+            ("Energy (kJ)", "kJ", Some(218.0))
+        );
+        assert_eq!(
+            stored(&nutrient("energy-kj", "kj", 218.0)),
+            ("Energy (kJ)", "kJ", Some(218.0))
+        );
+    }
+
+    #[test]
+    fn micrograms_are_scaled_whatever_their_spelling() {
+        for unit in ["UG", "mcg", "µg", "μg"] {
+            assert_eq!(
+                stored(&nutrient("selenium", unit, 30.0)),
+                ("Selenium, Se", "µg", Some(30.0))
+            );
+        }
+        assert_eq!(
+            stored(&nutrient("Selenium, Se", "mg", 0.5)),
+            ("Selenium, Se", "µg", Some(500.0))
+        );
+        assert_eq!(
+            stored(&nutrient("Calcium, Ca", "g", 0.25)),
+            ("Calcium, Ca", "mg", Some(250.0))
+        );
+    }
+
+    #[test]
+    fn number_takes_precedence_over_the_name() {
+        let energy = NutrientRecord {
+            number: Some("268".to_string()),
+            ..nutrient("Energy", "kJ", 218.0)
+        };
+        assert_eq!(stored(&energy), ("Energy (kJ)", "kJ", Some(218.0)));
+
+        let renamed = NutrientRecord {
+            number: Some("203".to_string()),
+            ..nutrient("Protein (g)", "G", 1.5)
+        };
+        assert_eq!(stored(&renamed), ("Protein", "g", Some(1.5)));
+
+        // A number the catalog doesn't know keeps the nutrient as reported, even under a known name
+        let unknown = NutrientRecord {
+            number: Some("999".to_string()),
+            ..nutrient("Protein", "g", 1.5)
+        };
+        assert_eq!(stored(&unknown), ("Protein", "g", Some(1.5)));
+    }
+
+    #[test]
+    fn tagname_maps_table_names_onto_the_catalog() {
+        let energy = NutrientRecord {
+            tagname: Some("enerc_kcal".to_string()),
+            ..nutrient("ENERGY (KILOCALORIES)", "kCal", 52.0)
+        };
+        assert_eq!(stored(&energy), ("Energy", "kcal", Some(52.0)));
+
+        let fat = NutrientRecord {
+            tagname: Some("FAT".to_string()),
+            ..nutrient("Fat, total", "mg", 1500.0)
+        };
+        assert_eq!(stored(&fat), ("Total lipid (fat)", "g", Some(1.5)));
+    }
+
+    #[test]
+    fn unknown_nutrients_and_units_pass_through() {
+        assert_eq!(
+            stored(&nutrient("Vitamin A, IU", "IU", 100.0)),
+            ("Vitamin A, IU", "IU", Some(100.0))
+        );
+        assert_eq!(
+            stored(&nutrient("Vitamin A, RAE", "IU", 100.0)),
+            ("Vitamin A, RAE", "IU", Some(100.0))
+        );
+        assert_eq!(
+            stored(&nutrient("Protein", "kcal", 4.0)),
+            ("Protein", "kcal", Some(4.0))
+        );
+    }
+
+    #[test]
+    fn conversion_factors() {
+        assert_eq!(conversion_factor("g", "mg"), Some(1000.0));
+        assert_eq!(conversion_factor("MG", "g"), Some(0.001));
+        assert_eq!(conversion_factor("mcg", "mg"), Some(0.001));
+        assert_eq!(conversion_factor("µg", "UG"), Some(1.0));
+        assert_eq!(conversion_factor("kcal", "KCAL"), Some(1.0));
+        assert_eq!(conversion_factor("kcal", "kJ"), None);
+        assert_eq!(conversion_factor("g", "kcal"), None);
+        assert_eq!(conversion_factor("IU", "µg"), None);
+    }
+}
@@ -0,0 +1,42 @@
+mod off_dump;
+mod off_types;
+
+use std::path::PathBuf;
+
+pub use off_dump::{OffDump, OffDumpFormat};
+use serde::Deserialize;
+
+use crate::aggregator::PagedAggregator;
+use crate::config::{ConfigError, SourceConfig};
+use crate::registry::{AggregatorContext, BoxedAggregator};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OffOptions {
+    /// Uncompressed JSONL or CSV export to import
+    path: PathBuf,
+    /// Inferred from the file extension when not set
+    #[serde(default)]
+    format: Option<OffDumpFormat>,
+    #[serde(default = "default_page_size")]
+    page_size: usize,
+}
+
+fn default_page_size() -> usize {
+    1000
+}
+
+pub fn build_aggregator(
+    name: &str,
+    config: &SourceConfig,
+    context: &AggregatorContext,
+) -> Result<BoxedAggregator, ConfigError> {
+    let options = config.options::<OffOptions>(name)?;
+    let format = options
+        .format
+        .unwrap_or_else(|| OffDumpFormat::from_path(&options.path));
+    let client = OffDump::new(options.path, format, options.page_size);
+    Ok(Box::new(PagedAggregator::from_file(
+        name, client, config, context,
+    )))
+}
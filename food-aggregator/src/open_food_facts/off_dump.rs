@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::off_types::{OffJsonProduct, OffPage, OffProduct};
+use crate::file_source::{self, FileIndex, PagedFile};
+use crate::{FoodSource, SourceError};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffDumpFormat {
+    /// `openfoodfacts-products.jsonl`, one product object per line
+    Jsonl,
+    /// `en.openfoodfacts.org.products.csv`, tab separated with a header line
+    Csv,
+}
+
+impl OffDumpFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv" | "tsv") => OffDumpFormat::Csv,
+            _ => OffDumpFormat::Jsonl,
+        }
+    }
+}
+
+/// Reads an uncompressed Open Food Facts export from disk.
+///
+/// Both exports hold one product per record, so the file is scanned once to find where each page
+/// of records starts and ends, and every fetch only parses the records of its own page.
+pub struct OffDump {
+    file: PagedFile<Vec<String>>,
+    format: OffDumpFormat,
+    page_size: usize,
+}
+
+impl OffDump {
+    pub fn new(path: impl Into<PathBuf>, format: OffDumpFormat, page_size: usize) -> Self {
+        Self {
+            file: PagedFile::new(path),
+            format,
+            page_size,
+        }
+    }
+}
+
+impl FoodSource for OffDump {
+    type Data = OffPage;
+
+    fn name(&self) -> &str {
+        "Open Food Facts"
+    }
+
+    fn is_finished(&self, current_page: usize) -> bool {
+        self.file.is_finished(current_page)
+    }
+
+    fn fetch(&self, current_page: usize) -> impl Future<Output = Result<Self::Data, SourceError>> {
+        let format = self.format;
+        let page_size = self.page_size;
+
+        Box::pin(async move {
+            let (total_pages, products) = self
+                .file
+                .read(
+                    current_page,
+                    move |path| index_dump(path, format, page_size),
+                    move |_, columns, bytes| read_page(format, columns, &bytes),
+                )
+                .await?;
+
+            Ok(OffPage {
+                total_pages,
+                products,
+            })
+        })
+    }
+}
+
+/// The CSV export is tab separated, product names and ingredient lists hold commas and quotes
+fn csv_builder(has_headers: bool) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(b'\t')
+        .has_headers(has_headers)
+        .flexible(true);
+    builder
+}
+
+/// Splits the dump into pages, returning the columns of the CSV header, empty for JSONL dumps
+fn index_dump(
+    path: &Path,
+    format: OffDumpFormat,
+    page_size: usize,
+) -> Result<FileIndex<Vec<String>>, SourceError> {
+    let (header, pages) = match format {
+        OffDumpFormat::Jsonl => (vec![], file_source::index_lines(path, 0, page_size)?),
+        OffDumpFormat::Csv => {
+            file_source::index_csv(path, &csv_builder(true), page_size, |_| Ok(None))?
+        }
+    };
+
+    Ok(FileIndex { header, pages })
+}
+
+fn read_page(
+    format: OffDumpFormat,
+    columns: &[String],
+    bytes: &[u8],
+) -> Result<Vec<OffProduct>, SourceError> {
+    // A single malformed product shouldn't send the whole page to the dead letter queue
+    let products = match format {
+        OffDumpFormat::Jsonl => bytes
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+            .filter_map(
+                |line| match serde_json::from_slice::<OffJsonProduct>(line) {
+                    Ok(product) => product.into_product(),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Skipping malformed Open Food Facts product");
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>(),
+        OffDumpFormat::Csv => csv_builder(false)
+            .from_reader(bytes)
+            .into_records()
+            .filter_map(|record| match record {
+                Ok(record) => csv_product(columns, &record),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Skipping malformed Open Food Facts product");
+                    None
+                }
+            })
+            .collect::<Vec<_>>(),
+    };
+
+    // The exports carry a few duplicated barcodes, which would make the bulk upsert of the page
+    // touch the same row twice, so only the last occurrence is kept
+    let mut positions = HashMap::with_capacity(products.len());
+    for (position, product) in products.iter().enumerate() {
+        positions.insert(product.code.clone(), position);
+    }
+
+    let products = products
+        .into_iter()
+        .enumerate()
+        .filter(|(position, product)| positions.get(&product.code) == Some(position))
+        .map(|(_, product)| product)
+        .collect();
+
+    Ok(products)
+}
+
+fn csv_product(columns: &[String], record: &csv::StringRecord) -> Option<OffProduct> {
+    let mut code = None;
+    let mut product_name = None;
+    let mut generic_name = None;
+    let mut nutriments = vec![];
+
+    for (column, value) in columns.iter().zip(record) {
+        match column.as_str() {
+            "code" => code = Some(value.to_string()),
+            "product_name" => product_name = Some(value.to_string()),
+            "generic_name" => generic_name = Some(value.to_string()),
+            column if column.ends_with("_100g") => {
+                if let Ok(value) = value.parse::<f32>() {
+                    nutriments.push((column, value));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    OffProduct::new(code?, product_name, generic_name, nutriments.into_iter())
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::supervisor::{FoodData, FoodEntry, FoodEntryNutrient};
+
+#[derive(Debug)]
+pub struct OffPage {
+    pub total_pages: usize,
+    pub products: Vec<OffProduct>,
+}
+
+impl FoodData for OffPage {
+    type Entry = OffProduct;
+    type EntryIter<'a> = std::slice::Iter<'a, Self::Entry>;
+
+    fn entries(&self) -> Self::EntryIter<'_> {
+        self.products.iter()
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+}
+
+#[derive(Debug)]
+pub struct OffProduct {
+    /// Barcode of the product, usually an EAN-13 or UPC-A
+    pub code: String,
+    pub name: String,
+    pub nutrients: Vec<OffNutrient>,
+}
+
+impl FoodEntry for OffProduct {
+    type Nutrient = OffNutrient;
+    type NutrientIter<'a> = std::slice::Iter<'a, Self::Nutrient>;
+
+    fn source(&self) -> String {
+        String::from("Open Food Facts")
+    }
+
+    fn wweia_data(&self) -> Option<(i32, &String)> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fndds_code(&self) -> Option<i32> {
+        None
+    }
+
+    fn id(&self) -> String {
+        self.code.clone()
+    }
+
+    fn nutrients(&self) -> Self::NutrientIter<'_> {
+        self.nutrients.iter()
+    }
+}
+
+#[derive(Debug)]
+pub struct OffNutrient {
+    pub name: String,
+    pub unit_name: String,
+    pub value: f32,
+}
+
+impl FoodEntryNutrient for OffNutrient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit_name(&self) -> &str {
+        &self.unit_name
+    }
+
+    fn value(&self) -> Option<f32> {
+        Some(self.value)
+    }
+}
+
+/// Line of the JSONL export, only the handful of fields we import out of the hundreds available
+#[derive(Debug, Deserialize)]
+pub struct OffJsonProduct {
+    #[serde(default)]
+    pub code: Option<serde_json::Value>,
+    #[serde(default)]
+    pub product_name: Option<String>,
+    #[serde(default)]
+    pub generic_name: Option<String>,
+    #[serde(default)]
+    pub nutriments: HashMap<String, serde_json::Value>,
+}
+
+impl OffJsonProduct {
+    pub fn into_product(self) -> Option<OffProduct> {
+        let code = match self.code? {
+            serde_json::Value::String(code) => code,
+            serde_json::Value::Number(code) => code.to_string(),
+            _ => return None,
+        };
+
+        let nutrients = self.nutriments.iter().filter_map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::Number(value) => value.as_f64()? as f32,
+                serde_json::Value::String(value) => value.parse().ok()?,
+                _ => return None,
+            };
+            Some((key.as_str(), value))
+        });
+
+        OffProduct::new(code, self.product_name, self.generic_name, nutrients)
+    }
+}
+
+impl OffProduct {
+    /// Builds a product out of the per 100g nutriment values of the export, products without a
+    /// barcode or a name can't be matched nor searched so they are skipped
+    pub fn new<'a>(
+        code: String,
+        product_name: Option<String>,
+        generic_name: Option<String>,
+        nutriments: impl Iterator<Item = (&'a str, f32)>,
+    ) -> Option<Self> {
+        let code = code.trim().to_string();
+        let name = [product_name, generic_name]
+            .into_iter()
+            .flatten()
+            .map(|name| name.trim().to_string())
+            .find(|name| !name.is_empty())?;
+
+        if code.is_empty() {
+            return None;
+        }
+
+        let nutrients = nutriments
+            .filter_map(|(key, value)| {
+                let key = key.strip_suffix("_100g")?;
+                let (name, unit_name) = nutrient(key)?;
+                Some(OffNutrient {
+                    name,
+                    unit_name: unit_name.to_string(),
+                    value,
+                })
+            })
+            .collect();
+
+        Some(Self {
+            code,
+            name,
+            nutrients,
+        })
+    }
+}
+
+/// Open Food Facts nutriments holding a mass, whose `_100g` value it normalizes to grams
+const MASS_NUTRIMENTS: &[&str] = &[
+    "proteins",
+    "casein",
+    "carbohydrates",
+    "sugars",
+    "added-sugars",
+    "sucrose",
+    "glucose",
+    "fructose",
+    "lactose",
+    "maltose",
+    "starch",
+    "polyols",
+    "fiber",
+    "soluble-fiber",
+    "insoluble-fiber",
+    "fat",
+    "saturated-fat",
+    "monounsaturated-fat",
+    "polyunsaturated-fat",
+    "trans-fat",
+    "omega-3-fat",
+    "omega-6-fat",
+    "cholesterol",
+    "salt",
+    "sodium",
+    "calcium",
+    "iron",
+    "magnesium",
+    "phosphorus",
+    "potassium",
+    "zinc",
+    "copper",
+    "manganese",
+    "selenium",
+    "iodine",
+    "chloride",
+    "fluoride",
+    "vitamin-a",
+    "beta-carotene",
+    "vitamin-c",
+    "vitamin-d",
+    "vitamin-e",
+    "vitamin-k",
+    "vitamin-b1",
+    "vitamin-b2",
+    "vitamin-pp",
+    "vitamin-b6",
+    "vitamin-b9",
+    "folates",
+    "vitamin-b12",
+    "biotin",
+    "pantothenic-acid",
+    "caffeine",
+    "taurine",
+];
+
+/// Unit the `_100g` value of an Open Food Facts nutriment key is normalized to, keys are kept as
+/// the nutrient name and mapped onto the nutrient catalog when persisted. Nutriments that aren't
+/// masses or energy, like the alcohol content in % vol or the pH, are skipped, as their `_100g`
+/// value comes without its unit
+fn nutrient(key: &str) -> Option<(String, &'static str)> {
+    let unit_name = match key {
+        "energy-kcal" => "kcal",
+        "energy-kj" => "kJ",
+        key if MASS_NUTRIMENTS.contains(&key) => "g",
+        // Along with those, `energy` is skipped as it duplicates `energy-kj`
+        _ => return None,
+    };
+
+    Some((key.to_string(), unit_name))
+}
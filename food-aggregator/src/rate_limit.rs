@@ -0,0 +1,93 @@
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use serde::Deserialize;
+use sqlx::PgPool;
+use tokio::time::Instant;
+
+use crate::models::rate_limit_buckets::RateLimitBucket;
+use crate::schedule::Every;
+
+/// How many requests a source accepts over a period of time
+///
+/// ```toml
+/// [sources.usda]
+/// rate_limit = { requests = 30, per = "1h" }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    pub requests: NonZeroU32,
+    pub per: Every,
+}
+
+impl RateLimit {
+    pub fn per_hour(requests: NonZeroU32) -> Self {
+        Self {
+            requests,
+            per: Every::hours(1),
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        f64::from(self.requests.get())
+    }
+
+    /// Seconds it takes for the budget of a single request to refill
+    fn refill_secs(&self) -> f64 {
+        self.per.duration().as_seconds_f64() / self.capacity()
+    }
+}
+
+/// The budget of the source is spent until `until`
+#[derive(Debug, Clone, Copy)]
+pub struct Exhausted {
+    pub until: Instant,
+}
+
+/// Token bucket limiting the requests made to a source.
+///
+/// The bucket is persisted in Postgres, so the budget survives restarts and is shared by every
+/// process fetching from the same source. Sources without a rate limit never touch the database.
+#[derive(Debug, Clone)]
+pub struct SourceLimiter {
+    source: String,
+    rate_limit: Option<RateLimit>,
+}
+
+impl SourceLimiter {
+    pub fn new(source: impl Into<String>, rate_limit: Option<RateLimit>) -> Self {
+        Self {
+            source: source.into(),
+            rate_limit,
+        }
+    }
+
+    /// Takes the budget of a single request, or tells when there will be budget again
+    pub async fn check(&self, pool: &PgPool) -> sqlx::Result<Result<(), Exhausted>> {
+        let Some(rate_limit) = &self.rate_limit else {
+            return Ok(Ok(()));
+        };
+
+        let capacity = rate_limit.capacity();
+        let refill_secs = rate_limit.refill_secs();
+
+        let mut tx = pool.begin().await?;
+        let (bucket, now) = RateLimitBucket::lock(&mut tx, &self.source, capacity).await?;
+        let elapsed = (now - bucket.refilled_at).as_seconds_f64().max(0.0);
+        // Clamping also shrinks the bucket right away when the configured limit was lowered
+        let tokens = (bucket.tokens + elapsed / refill_secs).min(capacity);
+
+        if tokens < 1.0 {
+            tx.rollback().await?;
+            let wait = Duration::from_secs_f64((1.0 - tokens) * refill_secs);
+            return Ok(Err(Exhausted {
+                until: Instant::now() + wait,
+            }));
+        }
+
+        RateLimitBucket::update(&mut tx, &self.source, tokens - 1.0, now).await?;
+        tx.commit().await?;
+
+        Ok(Ok(()))
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::Aggregator;
+use crate::config::{AggregatorConfig, ConfigError, SourceConfig};
+use crate::monitor::AggregationMonitor;
+
+pub type BoxedAggregator = Box<dyn Aggregator>;
+
+pub type AggregatorFactory = fn(
+    name: &str,
+    config: &SourceConfig,
+    context: &AggregatorContext,
+) -> Result<BoxedAggregator, ConfigError>;
+
+/// Resources shared by every aggregator built from the same registry
+#[derive(Debug, Clone)]
+pub struct AggregatorContext {
+    /// Reusing a single client keeps connections and TLS sessions pooled across pages and sources
+    pub http: reqwest::Client,
+    /// Live state of the sources, reported to by the aggregators while they run
+    pub monitor: AggregationMonitor,
+}
+
+impl AggregatorContext {
+    pub fn from_config(config: &AggregatorConfig) -> Result<Self, ConfigError> {
+        Ok(Self {
+            http: config.http.build_client()?,
+            monitor: AggregationMonitor::default(),
+        })
+    }
+}
+
+/// Maps the name of a food source to the factory that builds its [`Aggregator`], so sources can
+/// be enabled and tuned from [`AggregatorConfig`] without touching the scheduling loop.
+#[derive(Debug, Clone)]
+pub struct AggregatorRegistry {
+    factories: BTreeMap<String, AggregatorFactory>,
+    /// Sources reading a local file given by their `path` option, the only ones that can import
+    file_sources: BTreeSet<String>,
+    context: AggregatorContext,
+}
+
+impl AggregatorRegistry {
+    pub fn empty(context: AggregatorContext) -> Self {
+        Self {
+            factories: BTreeMap::new(),
+            file_sources: BTreeSet::new(),
+            context,
+        }
+    }
+
+    /// Registry with every food source shipped with the aggregator
+    pub fn with_default_sources(context: AggregatorContext) -> Self {
+        let mut registry = Self::empty(context);
+        registry.register("usda", crate::usda::build_aggregator);
+        registry.register_file("usda-bulk", crate::usda::build_bulk_aggregator);
+        registry.register_file("open-food-facts", crate::open_food_facts::build_aggregator);
+        registry.register_file("mapped", crate::mapped::build_aggregator);
+        registry
+    }
+
+    pub fn from_config(config: &AggregatorConfig) -> Result<Self, ConfigError> {
+        let context = AggregatorContext::from_config(config)?;
+        Ok(Self::with_default_sources(context))
+    }
+
+    pub fn register(&mut self, name: impl Into<String>, factory: AggregatorFactory) -> &mut Self {
+        self.factories.insert(name.into(), factory);
+        self
+    }
+
+    /// Registers a source reading the file given by its `path` option, which can also import
+    /// files through [`AggregatorRegistry::build_import`]
+    pub fn register_file(
+        &mut self,
+        name: impl Into<String>,
+        factory: AggregatorFactory,
+    ) -> &mut Self {
+        let name = name.into();
+        self.file_sources.insert(name.clone());
+        self.register(name, factory)
+    }
+
+    pub fn monitor(&self) -> &AggregationMonitor {
+        &self.context.monitor
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    pub fn build(&self, name: &str, config: &SourceConfig) -> Result<BoxedAggregator, ConfigError> {
+        let kind = config.kind.as_deref().unwrap_or(name);
+        let factory = self
+            .factories
+            .get(kind)
+            .ok_or_else(|| ConfigError::UnknownSource(kind.to_string()))?;
+
+        factory(name, config, &self.context)
+    }
+
+    /// Builds `name` to import the file at `path` instead of the one it is configured with
+    pub fn build_import(
+        &self,
+        name: &str,
+        config: &SourceConfig,
+        path: &Path,
+    ) -> Result<BoxedAggregator, ConfigError> {
+        let kind = config.kind.as_deref().unwrap_or(name);
+        if !self.factories.contains_key(kind) {
+            return Err(ConfigError::UnknownSource(kind.to_string()));
+        }
+        if !self.file_sources.contains(kind) {
+            return Err(ConfigError::NotFileSource(name.to_string()));
+        }
+
+        let mut config = config.clone();
+        let path = toml::Value::String(path.display().to_string());
+        config.options.insert(String::from("path"), path);
+        self.build(name, &config)
+    }
+
+    pub fn build_enabled(
+        &self,
+        config: &AggregatorConfig,
+    ) -> Result<Vec<(String, BoxedAggregator)>, ConfigError> {
+        config
+            .enabled_sources()
+            .map(|(name, source_config)| {
+                let aggregator = self.build(name, source_config)?;
+                Ok((name.to_string(), aggregator))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(config: &str) -> Result<Vec<(String, BoxedAggregator)>, ConfigError> {
+        let config = toml::from_str::<AggregatorConfig>(config)?;
+        AggregatorRegistry::from_config(&config)?.build_enabled(&config)
+    }
+
+    #[test]
+    fn builds_sources_with_their_options() -> Result<(), ConfigError> {
+        let aggregators = build(
+            r#"
+            [sources.open-food-facts]
+            max_workers = 2
+            path = "off.jsonl"
+            page_size = 50
+            "#,
+        )?;
+        assert_eq!(aggregators.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn imports_only_through_file_sources() -> Result<(), ConfigError> {
+        let config = AggregatorConfig::default();
+        let registry = AggregatorRegistry::from_config(&config)?;
+        let path = Path::new("foods.jsonl");
+
+        let usda = registry.build_import("usda", &SourceConfig::default(), path);
+        assert!(matches!(usda, Err(ConfigError::NotFileSource(_))));
+
+        registry.build_import("open-food-facts", &SourceConfig::default(), path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_options_the_source_does_not_know() {
+        for config in [
+            "[sources.usda]\nmax_worker = 2",
+            "[sources.usda]\nrate_limits = { requests = 10, per = \"1h\" }",
+            "[sources.usda]\npath = \"foods.json\"",
+            "[sources.open-food-facts]\npath = \"off.jsonl\"\npage_sise = 10",
+        ] {
+            let result = build(config).map(|aggregators| aggregators.len());
+            assert!(
+                matches!(result, Err(ConfigError::InvalidOptions { .. })),
+                "`{config}` should be rejected, got {result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use croner::Cron;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+/// When a source should be aggregated, every time is evaluated in UTC.
+///
+/// ```toml
+/// [sources.open-food-facts]
+/// schedule = { strategy = "cron", expression = "0 3 * * 1" }
+///
+/// [sources.usda]
+/// schedule = { strategy = "interval", every = "30d", window = { start = "02:00", end = "05:00" } }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Runs at every occurrence of a cron expression
+    Cron { expression: CronExpression },
+    /// Runs once `every` has passed since the previous run started, and only within `window`
+    /// when one is given
+    Interval {
+        every: Every,
+        #[serde(default)]
+        window: Option<TimeWindow>,
+    },
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule::Interval {
+            every: Every::days(30),
+            window: None,
+        }
+    }
+}
+
+impl Schedule {
+    /// When a source that was never aggregated should run, which is as soon as possible for
+    /// intervals and the next occurrence for cron expressions
+    pub fn first_run(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron { expression } => expression.next_occurrence(now),
+            Schedule::Interval { window: None, .. } => Some(now),
+            Schedule::Interval {
+                window: Some(window),
+                ..
+            } => Some(window.fit(now)),
+        }
+    }
+
+    /// When a source should run again given when its last run started, `None` when the
+    /// schedule never matches again
+    pub fn next_run(&self, last_run: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron { expression } => expression.next_occurrence(last_run.max(now)),
+            Schedule::Interval { every, window } => {
+                // A run that took longer than the interval is due again right away
+                let next = (last_run + every.duration).max(now);
+                Some(window.as_ref().map_or(next, |window| window.fit(next)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Cron { expression } => write!(f, "cron {expression}"),
+            Schedule::Interval {
+                every,
+                window: None,
+            } => write!(f, "every {every}"),
+            Schedule::Interval {
+                every,
+                window: Some(window),
+            } => write!(
+                f,
+                "every {every} between {} and {}",
+                window.start.format("%H:%M"),
+                window.end.format("%H:%M")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CronExpression {
+    raw: String,
+    // The parsed pattern is a few hundred bytes, which every other schedule would pay for
+    cron: Box<Cron>,
+}
+
+impl CronExpression {
+    fn next_occurrence(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.cron.find_next_occurrence(&after, false) {
+            Ok(next) => Some(next),
+            Err(e) => {
+                tracing::warn!(expression = %self, error = ?e, "Cron expression never matches");
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronExpression {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        let cron = Cron::from_str(&raw)
+            .map_err(|e| D::Error::custom(format!("invalid cron expression `{raw}`: {e}")))?;
+
+        Ok(Self {
+            raw,
+            cron: Box::new(cron),
+        })
+    }
+}
+
+/// Interval written as a number followed by a unit, like `90m`, `12h`, `7d` or `2w`
+#[derive(Debug, Clone)]
+pub struct Every {
+    raw: String,
+    duration: Duration,
+}
+
+impl Every {
+    pub fn hours(hours: i64) -> Self {
+        Self {
+            raw: format!("{hours}h"),
+            duration: Duration::hours(hours),
+        }
+    }
+
+    pub fn days(days: i64) -> Self {
+        Self {
+            raw: format!("{days}d"),
+            duration: Duration::days(days),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl fmt::Display for Every {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for Every {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        let invalid = || D::Error::custom(format!("invalid interval `{raw}`, expected e.g. `7d`"));
+
+        let split = raw
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (amount, unit) = raw.split_at(split);
+        let amount = amount.parse::<i64>().map_err(|_| invalid())?;
+        let duration = match unit {
+            "s" => Duration::try_seconds(amount),
+            "m" => Duration::try_minutes(amount),
+            "h" => Duration::try_hours(amount),
+            "d" => Duration::try_days(amount),
+            "w" => Duration::try_weeks(amount),
+            _ => None,
+        }
+        .filter(|duration| *duration > Duration::zero())
+        .ok_or_else(invalid)?;
+
+        Ok(Self { raw, duration })
+    }
+}
+
+/// Time of the day runs are allowed to start in, wrapping around midnight when `end` comes
+/// before `start`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeWindow {
+    #[serde(deserialize_with = "time_of_day")]
+    start: NaiveTime,
+    #[serde(deserialize_with = "time_of_day")]
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Earliest moment from `at` on that falls within the window
+    fn fit(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        if self.contains(at.time()) {
+            return at;
+        }
+
+        let start = at.date_naive().and_time(self.start).and_utc();
+        if start > at { start } else { start + Duration::days(1) }
+    }
+}
+
+/// Deserializes an [`Every`] straight into the duration it stands for
+pub(crate) fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<std::time::Duration, D::Error> {
+    let every = Every::deserialize(deserializer)?;
+    every
+        .duration()
+        .to_std()
+        .map_err(|e| D::Error::custom(format!("invalid duration `{every}`: {e}")))
+}
+
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&raw, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(&raw, "%H:%M:%S"))
+        .map_err(|e| D::Error::custom(format!("invalid time of day `{raw}`: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+    const DAILY_AT_THREE: &str = r#"
+        strategy = "cron"
+        expression = "0 3 * * *"
+    "#;
+    const DAILY_AT_NIGHT: &str = r#"
+        strategy = "interval"
+        every = "1d"
+        window = { start = "02:00", end = "05:00" }
+    "#;
+
+    fn at(datetime: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+        DateTime::parse_from_rfc3339(datetime).map(|datetime| datetime.to_utc())
+    }
+
+    fn window(start: &str, end: &str) -> Result<TimeWindow, toml::de::Error> {
+        toml::from_str(&format!(
+            r#"start = "{start}"
+end = "{end}""#
+        ))
+    }
+
+    fn every(raw: &str) -> Result<Every, toml::de::Error> {
+        #[derive(Deserialize)]
+        struct Interval {
+            every: Every,
+        }
+
+        let interval = toml::from_str::<Interval>(&format!(r#"every = "{raw}""#))?;
+        Ok(interval.every)
+    }
+
+    #[test]
+    fn cron_first_run_waits_for_the_next_occurrence() -> TestResult {
+        let cron = toml::from_str::<Schedule>(DAILY_AT_THREE)?;
+
+        assert_eq!(
+            cron.first_run(at("2025-10-17T12:00:00Z")?),
+            Some(at("2025-10-18T03:00:00Z")?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn interval_first_run_is_right_away_or_within_the_window() -> TestResult {
+        let now = at("2025-10-17T12:00:00Z")?;
+        assert_eq!(Schedule::default().first_run(now), Some(now));
+
+        let windowed = toml::from_str::<Schedule>(DAILY_AT_NIGHT)?;
+        assert_eq!(windowed.first_run(now), Some(at("2025-10-18T02:00:00Z")?));
+        Ok(())
+    }
+
+    #[test]
+    fn cron_next_run_follows_the_last_run_or_now() -> TestResult {
+        let cron = toml::from_str::<Schedule>(DAILY_AT_THREE)?;
+        let now = at("2025-10-17T12:00:00Z")?;
+
+        assert_eq!(
+            cron.next_run(at("2025-10-10T03:00:00Z")?, now),
+            Some(at("2025-10-18T03:00:00Z")?)
+        );
+        assert_eq!(
+            cron.next_run(at("2025-10-18T03:00:00Z")?, now),
+            Some(at("2025-10-19T03:00:00Z")?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn interval_next_run_waits_for_the_interval() -> TestResult {
+        let schedule = Schedule::default();
+        let now = at("2025-10-17T12:00:00Z")?;
+
+        assert_eq!(
+            schedule.next_run(at("2025-10-10T12:00:00Z")?, now),
+            Some(at("2025-11-09T12:00:00Z")?)
+        );
+        // A run that took longer than the interval is due right away
+        assert_eq!(
+            schedule.next_run(at("2025-09-01T12:00:00Z")?, now),
+            Some(now)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn interval_next_run_is_moved_into_the_window() -> TestResult {
+        let windowed = toml::from_str::<Schedule>(DAILY_AT_NIGHT)?;
+        let now = at("2025-10-17T12:00:00Z")?;
+
+        assert_eq!(
+            windowed.next_run(at("2025-10-17T03:00:00Z")?, now),
+            Some(at("2025-10-18T03:00:00Z")?)
+        );
+        assert_eq!(
+            windowed.next_run(at("2025-10-17T10:00:00Z")?, now),
+            Some(at("2025-10-19T02:00:00Z")?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn window_fit() -> TestResult {
+        let window = window("02:00", "05:00")?;
+
+        let inside = at("2025-10-17T03:00:00Z")?;
+        assert_eq!(window.fit(inside), inside);
+        assert_eq!(
+            window.fit(at("2025-10-17T01:00:00Z")?),
+            at("2025-10-17T02:00:00Z")?
+        );
+        assert_eq!(
+            window.fit(at("2025-10-17T05:00:00Z")?),
+            at("2025-10-18T02:00:00Z")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn window_fit_past_midnight() -> TestResult {
+        let window = window("22:00", "04:00")?;
+
+        for inside in ["2025-10-17T23:00:00Z", "2025-10-18T01:00:00Z"] {
+            assert_eq!(window.fit(at(inside)?), at(inside)?);
+        }
+        assert_eq!(
+            window.fit(at("2025-10-17T12:00:00Z")?),
+            at("2025-10-17T22:00:00Z")?
+        );
+        assert_eq!(
+            window.fit(at("2025-10-18T04:00:00Z")?),
+            at("2025-10-18T22:00:00Z")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn every_parses_amount_and_unit() -> TestResult {
+        assert_eq!(every("90m")?.duration(), Duration::minutes(90));
+        assert_eq!(every("12h")?.duration(), Duration::hours(12));
+        assert_eq!(every("7d")?.duration(), Duration::days(7));
+        assert_eq!(every("2w")?.duration(), Duration::weeks(2));
+        assert_eq!(every("7d")?.to_string(), "7d");
+        Ok(())
+    }
+
+    #[test]
+    fn every_rejects_invalid_intervals() {
+        for raw in ["", "7", "d", "0d", "-1d", "7y", "1.5h"] {
+            assert!(every(raw).is_err(), "`{raw}` should be rejected");
+        }
+    }
+}
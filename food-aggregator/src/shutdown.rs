@@ -0,0 +1,49 @@
+use tokio_util::sync::CancellationToken;
+
+/// Resolves once the process is asked to stop, through ctrl-c or SIGTERM. A signal that can't be
+/// listened to never resolves, the process is then stopped the hard way as before.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = ?e, "Failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Token cancelled once the process is asked to stop, see [`signal`]
+pub fn token() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+
+    tokio::spawn(async move {
+        signal().await;
+        tracing::info!("Shutdown requested, winding down aggregation");
+        cancel.cancel();
+    });
+
+    token
+}
@@ -0,0 +1,834 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_array::builder::{Float32Builder, Int32Builder, StringBuilder};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use derive_more::{Display, Error, From};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+use sqlx::{Acquire, PgConnection, PgPool, Postgres, Transaction};
+use tokio::sync::Mutex;
+use tokio::task::JoinError;
+
+use crate::BoxFuture;
+use crate::models::aggregation_checkpoints::{
+    AggregationCheckpoint, CreateCheckpointPayload, PageStatus,
+};
+use crate::models::aggregation_history::{AggregationHistory, AggregationStats};
+use crate::models::aggregation_source_foods::AggregationSourceFoods;
+use crate::models::dead_letters::{CreateDeadLetterPayload, DeadLetter, DeadLetterKind};
+use crate::models::food_diffs::FoodDiff;
+use crate::models::food_ingest::FoodIngest;
+use crate::models::staging::Staging;
+use crate::supervisor::{
+    FoodEntry, FoodEntryNutrient, diff_food_data, persist_food_data, stage_food_data,
+};
+
+#[derive(Debug, Display, Error, From)]
+pub enum SinkError {
+    #[from]
+    Database(sqlx::Error),
+    #[from]
+    Io(std::io::Error),
+    #[from]
+    Json(serde_json::Error),
+    #[from]
+    Arrow(ArrowError),
+    #[from]
+    Parquet(ParquetError),
+    #[from]
+    Join(JoinError),
+}
+
+/// Food of a fetched page, detached from the source it came from so any sink can write it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FoodRecord {
+    pub source: String,
+    pub external_id: String,
+    pub name: String,
+    pub fndds_code: Option<i32>,
+    pub wweia_code: Option<i32>,
+    pub wweia_category: Option<String>,
+    pub nutrients: Vec<NutrientRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NutrientRecord {
+    pub name: String,
+    #[serde(default)]
+    pub number: Option<String>,
+    #[serde(default)]
+    pub tagname: Option<String>,
+    pub unit: String,
+    pub value: Option<f32>,
+}
+
+impl FoodRecord {
+    pub fn from_entry(entry: &impl FoodEntry) -> Self {
+        let (wweia_code, wweia_category) = entry
+            .wweia_data()
+            .map(|(code, category)| (code, category.clone()))
+            .unzip();
+
+        Self {
+            source: entry.source(),
+            external_id: entry.id(),
+            name: entry.name().to_string(),
+            fndds_code: entry.fndds_code(),
+            wweia_code,
+            wweia_category,
+            nutrients: entry
+                .nutrients()
+                .map(|nutrient| NutrientRecord {
+                    name: nutrient.name().to_string(),
+                    number: nutrient.number().map(str::to_string),
+                    tagname: nutrient.tagname().map(str::to_string),
+                    unit: nutrient.unit_name().to_string(),
+                    value: nutrient.value(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FoodEntry for FoodRecord {
+    type Nutrient = NutrientRecord;
+    type NutrientIter<'a> = std::slice::Iter<'a, NutrientRecord>;
+
+    fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    fn wweia_data(&self) -> Option<(i32, &String)> {
+        self.wweia_code.zip(self.wweia_category.as_ref())
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fndds_code(&self) -> Option<i32> {
+        self.fndds_code
+    }
+
+    fn id(&self) -> String {
+        self.external_id.clone()
+    }
+
+    fn nutrients(&self) -> Self::NutrientIter<'_> {
+        self.nutrients.iter()
+    }
+}
+
+impl FoodEntryNutrient for NutrientRecord {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    fn tagname(&self) -> Option<&str> {
+        self.tagname.as_deref()
+    }
+
+    fn unit_name(&self) -> &str {
+        &self.unit
+    }
+
+    fn value(&self) -> Option<f32> {
+        self.value
+    }
+}
+
+/// Run whose pages are handed to a [`FoodSink`]
+#[derive(Debug, Clone)]
+pub struct SinkRun {
+    pub id: Uuid,
+    /// Configured source the run belongs to
+    pub source: String,
+    /// Whether the run has a staged commit policy, so its pages must not be visible until the run
+    /// finishes
+    pub staged: bool,
+    /// History entry of the attempt, its counters are saved along with every commit
+    pub history_id: Option<Uuid>,
+}
+
+/// A page handed to a [`FoodSink`]
+#[derive(Debug, Clone, Copy)]
+pub struct SinkPage<'a> {
+    pub run: &'a SinkRun,
+    pub page: usize,
+    /// How many times the page was fetched before this one
+    pub attempts: usize,
+    pub foods: &'a [FoodRecord],
+}
+
+/// A page given up on, kept aside so it can be replayed later
+#[derive(Debug, Clone, Copy)]
+pub struct FailedPage<'a> {
+    pub run: &'a SinkRun,
+    pub page: usize,
+    pub kind: DeadLetterKind,
+    pub retries: usize,
+    pub error: &'a str,
+}
+
+/// Where the supervisor writes the foods of the pages it fetched, along with what a run needs to
+/// resume where it stopped. Pages are written in batches, a page only counts as done once the
+/// batch it was written in commits, and pages can be written more than once, as a page that was
+/// in flight when a run stopped is fetched again when it resumes.
+pub trait FoodSink: Send + std::fmt::Debug {
+    /// Called before fetching a page, a page left in flight is fetched again when the run resumes
+    fn start_page<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        page: usize,
+        retries: usize,
+    ) -> BoxFuture<'a, Result<(), SinkError>>;
+
+    /// Writes a page to the open batch, returning how many foods and nutrients were written. A
+    /// page failing to write leaves the other pages of the batch untouched
+    fn write<'a>(
+        &'a mut self,
+        page: SinkPage<'a>,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>>;
+
+    /// Gives up on a page, recording why so it can be replayed later
+    fn fail_page<'a>(&'a mut self, page: FailedPage<'a>) -> BoxFuture<'a, Result<(), SinkError>>;
+
+    /// Called right before the open batch commits, returning how many foods and nutrients were
+    /// written by sinks that defer writing pages until then
+    fn flush<'a>(
+        &'a mut self,
+        _: &'a SinkRun,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async { Ok(AggregationStats::default()) })
+    }
+
+    /// Makes the pages of the open batch durable, along with the counters of the run so far
+    fn commit<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        stats: &'a AggregationStats,
+    ) -> BoxFuture<'a, Result<(), SinkError>>;
+
+    /// Discards the open batch after it failed to flush or commit, so the next page starts a new
+    /// one. None of its pages are checkpointed as completed
+    fn rollback(&mut self) -> BoxFuture<'_, ()>;
+
+    /// Called once every page of the run was written, making staged pages visible
+    fn finish<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>>;
+}
+
+/// Checkpoints, dead letters and counters of a run kept in Postgres, whatever the pages are
+/// written to. Pages of a batch share a transaction, and are checkpointed as completed within it,
+/// so a page is only ever checkpointed once it is durable.
+#[derive(Debug)]
+struct RunLedger {
+    pool: PgPool,
+    /// Transaction of the open batch
+    tx: Option<Transaction<'static, Postgres>>,
+}
+
+impl RunLedger {
+    fn new(pool: PgPool) -> Self {
+        Self { pool, tx: None }
+    }
+
+    async fn start_page(&self, run: &SinkRun, page: usize, retries: usize) -> sqlx::Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let payload = CreateCheckpointPayload::new(run.id, page, PageStatus::InFlight, retries);
+        AggregationCheckpoint::create_or_update(conn.as_mut(), payload).await?;
+        Ok(())
+    }
+
+    /// Transaction of the open batch, opening one if needed
+    async fn batch(&mut self) -> sqlx::Result<&mut Transaction<'static, Postgres>> {
+        let tx = match self.tx.take() {
+            Some(tx) => tx,
+            None => self.pool.begin().await?,
+        };
+        Ok(self.tx.insert(tx))
+    }
+
+    async fn complete_page(conn: &mut PgConnection, page: SinkPage<'_>) -> sqlx::Result<()> {
+        let payload = CreateCheckpointPayload::new(
+            page.run.id,
+            page.page,
+            PageStatus::Completed,
+            page.attempts,
+        );
+        AggregationCheckpoint::create_or_update(conn, payload).await?;
+        Ok(())
+    }
+
+    /// Checkpoints the page as failed, and unless `dead_letter` is false keeps it in the dead
+    /// letter queue
+    async fn fail_page(&self, page: FailedPage<'_>, dead_letter: bool) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let payload =
+            CreateCheckpointPayload::new(page.run.id, page.page, PageStatus::Failed, page.retries)
+                .with_error(page.error);
+        AggregationCheckpoint::create_or_update(tx.as_mut(), payload).await?;
+
+        if dead_letter {
+            let payload = CreateDeadLetterPayload::new(
+                page.run.id,
+                page.page,
+                page.kind,
+                page.error,
+                page.retries + 1,
+            );
+            DeadLetter::create_or_update(tx.as_mut(), payload).await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn rollback(&mut self) {
+        let Some(tx) = self.tx.take() else {
+            return;
+        };
+
+        if let Err(e) = tx.rollback().await {
+            tracing::warn!(error = ?e, "Failed to roll back batch");
+        }
+    }
+
+    async fn commit(&mut self, run: &SinkRun, stats: &AggregationStats) -> sqlx::Result<()> {
+        let Some(mut tx) = self.tx.take() else {
+            return Ok(());
+        };
+
+        if let Some(history_id) = run.history_id {
+            AggregationHistory::update_stats(tx.as_mut(), history_id, stats).await?;
+        }
+        tx.commit().await
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestMethod {
+    /// Every page is upserted on its own as soon as it is written
+    #[default]
+    Insert,
+    /// Pages are copied into temporary tables and merged with a single upsert when the
+    /// transaction they share commits, which is how many pages get merged at once depends on the
+    /// commit policy. A page failing to merge fails every page of its transaction
+    Copy,
+}
+
+/// Upserts pages into the live tables, or into the staging tables until the run is swapped in.
+/// Dry runs instead diff pages against the live tables, recording what persisting them would
+/// change.
+#[derive(Debug)]
+pub struct PostgresSink {
+    ledger: RunLedger,
+    ingest: IngestMethod,
+    dry_run: bool,
+}
+
+impl PostgresSink {
+    pub fn new(pool: PgPool, ingest: IngestMethod) -> Self {
+        Self {
+            ledger: RunLedger::new(pool),
+            ingest,
+            dry_run: false,
+        }
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+impl FoodSink for PostgresSink {
+    fn start_page<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        page: usize,
+        retries: usize,
+    ) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { Ok(self.ledger.start_page(run, page, retries).await?) })
+    }
+
+    fn write<'a>(
+        &'a mut self,
+        page: SinkPage<'a>,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let pool = self.ledger.pool.clone();
+            let tx = self.ledger.batch().await?;
+
+            // Each page gets its own savepoint so a page failing to persist doesn't take down the
+            // other pages sharing the transaction
+            let mut savepoint = tx.begin().await?;
+            let conn = savepoint.as_mut();
+
+            let written = if self.dry_run {
+                diff_food_data(conn, page.run.id, page.foods).await?;
+                AggregationStats::default()
+            } else {
+                if page.run.staged {
+                    Staging::route_transaction(conn).await?;
+                }
+
+                let written = match self.ingest {
+                    IngestMethod::Insert => persist_food_data(&pool, conn, page.foods).await?,
+                    IngestMethod::Copy => {
+                        stage_food_data(&pool, conn, page.foods).await?;
+                        AggregationStats::default()
+                    }
+                };
+
+                let foods = page
+                    .foods
+                    .iter()
+                    .map(|food| (food.source.as_str(), food.external_id.as_str()));
+                AggregationSourceFoods::record(conn, &page.run.source, foods).await?;
+                written
+            };
+
+            RunLedger::complete_page(conn, page).await?;
+            savepoint.commit().await?;
+
+            // Staged pages only reach the live tables once the run is swapped in, which is when
+            // they get counted
+            if page.run.staged {
+                return Ok(AggregationStats::default());
+            }
+            Ok(written)
+        })
+    }
+
+    fn fail_page<'a>(&'a mut self, page: FailedPage<'a>) -> BoxFuture<'a, Result<(), SinkError>> {
+        // Replaying a page of a dry run would persist it
+        Box::pin(async move { Ok(self.ledger.fail_page(page, !self.dry_run).await?) })
+    }
+
+    fn flush<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let (IngestMethod::Copy, false, Some(tx)) =
+                (self.ingest, self.dry_run, &mut self.ledger.tx)
+            else {
+                return Ok(AggregationStats::default());
+            };
+
+            let merged = FoodIngest::merge(tx.as_mut()).await?;
+            if run.staged {
+                return Ok(AggregationStats::default());
+            }
+            Ok(merged)
+        })
+    }
+
+    fn commit<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        stats: &'a AggregationStats,
+    ) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { Ok(self.ledger.commit(run, stats).await?) })
+    }
+
+    fn rollback(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(self.ledger.rollback())
+    }
+
+    fn finish<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let pool = &self.ledger.pool;
+            if self.dry_run {
+                let mut conn = pool.acquire().await?;
+                FoodDiff::record_removed(conn.as_mut(), run.id, &run.source).await?;
+                return Ok(AggregationStats::default());
+            }
+
+            if !run.staged {
+                return Ok(AggregationStats::default());
+            }
+
+            let mut tx = pool.begin().await?;
+            let swapped = Staging::swap_source(tx.as_mut(), &run.source).await?;
+            tx.commit().await?;
+            tracing::info!(source = %run.source, "Swapped staged data into live tables");
+            Ok(swapped)
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+    /// One JSON object per food, with its nutrients nested
+    #[default]
+    Ndjson,
+    /// One row per nutrient of each food, foods without nutrients get a single row
+    Parquet,
+}
+
+impl SnapshotFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Ndjson => "ndjson",
+            SnapshotFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Writes every page of a run to its own file under `<directory>/<run id>`, producing an offline
+/// dataset snapshot instead of touching the live tables. Writing a page again replaces its file,
+/// so pages fetched again after a restart don't end up twice in the snapshot. Checkpoints are
+/// still kept in Postgres, so an interrupted snapshot resumes like any other run.
+#[derive(Debug)]
+pub struct SnapshotSink {
+    ledger: RunLedger,
+    directory: PathBuf,
+    format: SnapshotFormat,
+}
+
+impl SnapshotSink {
+    pub fn new(pool: PgPool, directory: impl Into<PathBuf>, format: SnapshotFormat) -> Self {
+        Self {
+            ledger: RunLedger::new(pool),
+            directory: directory.into(),
+            format,
+        }
+    }
+
+    pub fn run_directory(&self, run_id: Uuid) -> PathBuf {
+        self.directory.join(run_id.to_string())
+    }
+}
+
+impl FoodSink for SnapshotSink {
+    fn start_page<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        page: usize,
+        retries: usize,
+    ) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { Ok(self.ledger.start_page(run, page, retries).await?) })
+    }
+
+    fn write<'a>(
+        &'a mut self,
+        page: SinkPage<'a>,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let directory = self.run_directory(page.run.id);
+            let file_name = format!("page-{:06}.{}", page.page, self.format.extension());
+            let format = self.format;
+            let foods = page.foods.to_vec();
+            let stats = written_stats(&foods);
+
+            tokio::task::spawn_blocking(move || {
+                std::fs::create_dir_all(&directory)?;
+
+                // Written aside and renamed, so a crash mid-write never leaves a truncated page
+                let path = directory.join(file_name);
+                let partial = path.with_extension("partial");
+                match format {
+                    SnapshotFormat::Ndjson => write_ndjson(&partial, &foods)?,
+                    SnapshotFormat::Parquet => write_parquet(&partial, &foods)?,
+                }
+                std::fs::rename(partial, path)?;
+                Ok::<_, SinkError>(())
+            })
+            .await??;
+
+            let tx = self.ledger.batch().await?;
+            RunLedger::complete_page(tx.as_mut(), page).await?;
+            Ok(stats)
+        })
+    }
+
+    fn fail_page<'a>(&'a mut self, page: FailedPage<'a>) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { Ok(self.ledger.fail_page(page, true).await?) })
+    }
+
+    fn commit<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        stats: &'a AggregationStats,
+    ) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { Ok(self.ledger.commit(run, stats).await?) })
+    }
+
+    fn rollback(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(self.ledger.rollback())
+    }
+
+    fn finish<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let directory = self.run_directory(run.id);
+            tracing::info!(source = %run.source, directory = %directory.display(), "Snapshot complete");
+            Ok(AggregationStats::default())
+        })
+    }
+}
+
+fn write_ndjson(path: &Path, foods: &[FoodRecord]) -> Result<(), SinkError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for food in foods {
+        serde_json::to_writer(&mut writer, food)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_parquet(path: &Path, foods: &[FoodRecord]) -> Result<(), SinkError> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("source", DataType::Utf8, false),
+        Field::new("external_id", DataType::Utf8, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("fndds_code", DataType::Int32, true),
+        Field::new("wweia_code", DataType::Int32, true),
+        Field::new("wweia_category", DataType::Utf8, true),
+        Field::new("nutrient", DataType::Utf8, true),
+        Field::new("nutrient_number", DataType::Utf8, true),
+        Field::new("nutrient_tagname", DataType::Utf8, true),
+        Field::new("unit", DataType::Utf8, true),
+        Field::new("value", DataType::Float32, true),
+    ]));
+
+    let mut source = StringBuilder::new();
+    let mut external_id = StringBuilder::new();
+    let mut name = StringBuilder::new();
+    let mut fndds_code = Int32Builder::new();
+    let mut wweia_code = Int32Builder::new();
+    let mut wweia_category = StringBuilder::new();
+    let mut nutrient = StringBuilder::new();
+    let mut nutrient_number = StringBuilder::new();
+    let mut nutrient_tagname = StringBuilder::new();
+    let mut unit = StringBuilder::new();
+    let mut value = Float32Builder::new();
+
+    for food in foods {
+        let nutrients = food
+            .nutrients
+            .iter()
+            .map(Some)
+            .chain(food.nutrients.is_empty().then_some(None));
+
+        for food_nutrient in nutrients {
+            source.append_value(&food.source);
+            external_id.append_value(&food.external_id);
+            name.append_value(&food.name);
+            fndds_code.append_option(food.fndds_code);
+            wweia_code.append_option(food.wweia_code);
+            wweia_category.append_option(food.wweia_category.as_deref());
+            nutrient.append_option(food_nutrient.map(|n| n.name.as_str()));
+            nutrient_number.append_option(food_nutrient.and_then(|n| n.number.as_deref()));
+            nutrient_tagname.append_option(food_nutrient.and_then(|n| n.tagname.as_deref()));
+            unit.append_option(food_nutrient.map(|n| n.unit.as_str()));
+            value.append_option(food_nutrient.and_then(|n| n.value));
+        }
+    }
+
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(source.finish()),
+            Arc::new(external_id.finish()),
+            Arc::new(name.finish()),
+            Arc::new(fndds_code.finish()),
+            Arc::new(wweia_code.finish()),
+            Arc::new(wweia_category.finish()),
+            Arc::new(nutrient.finish()),
+            Arc::new(nutrient_number.finish()),
+            Arc::new(nutrient_tagname.finish()),
+            Arc::new(unit.finish()),
+            Arc::new(value.finish()),
+        ],
+    )?;
+
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Keeps written pages and their checkpoints in memory, so the supervisor can be exercised without
+/// a place to persist to. Pages only show up once their batch commits, and staged pages are held
+/// aside until the run finishes, like they are in Postgres. Clones share the same pages.
+#[derive(Debug, Default, Clone)]
+pub struct MemorySink {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    /// Pages of the open batch, along with whether they are staged
+    batch: Vec<((Uuid, usize), bool, Vec<FoodRecord>)>,
+    pages: BTreeMap<(Uuid, usize), Vec<FoodRecord>>,
+    staged: BTreeMap<(Uuid, usize), Vec<FoodRecord>>,
+    checkpoints: BTreeMap<(Uuid, usize), PageStatus>,
+    dead_letters: BTreeMap<(Uuid, usize), String>,
+    /// Counters saved by the last commit
+    stats: AggregationStats,
+    /// Whether the next commit fails, leaving its batch open
+    fail_commit: bool,
+}
+
+impl MemorySink {
+    /// Every food committed so far, in page order
+    pub async fn foods(&self) -> Vec<FoodRecord> {
+        let state = self.state.lock().await;
+        state.pages.values().flatten().cloned().collect()
+    }
+
+    pub async fn page(&self, run_id: Uuid, page: usize) -> Option<Vec<FoodRecord>> {
+        self.state.lock().await.pages.get(&(run_id, page)).cloned()
+    }
+
+    pub async fn checkpoint(&self, run_id: Uuid, page: usize) -> Option<PageStatus> {
+        self.state
+            .lock()
+            .await
+            .checkpoints
+            .get(&(run_id, page))
+            .copied()
+    }
+
+    /// Error the page was given up on with, if it was
+    pub async fn dead_letter(&self, run_id: Uuid, page: usize) -> Option<String> {
+        self.state
+            .lock()
+            .await
+            .dead_letters
+            .get(&(run_id, page))
+            .cloned()
+    }
+
+    /// Counters of the run as of the last commit
+    pub async fn stats(&self) -> AggregationStats {
+        self.state.lock().await.stats.clone()
+    }
+
+    /// Makes the next commit fail, like a batch the database refused
+    pub async fn fail_next_commit(&self) {
+        self.state.lock().await.fail_commit = true;
+    }
+}
+
+fn written_stats(foods: &[FoodRecord]) -> AggregationStats {
+    AggregationStats {
+        foods_inserted: foods.len() as i64,
+        nutrients_written: foods.iter().map(|food| food.nutrients.len() as i64).sum(),
+        ..AggregationStats::default()
+    }
+}
+
+impl FoodSink for MemorySink {
+    fn start_page<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+        page: usize,
+        _: usize,
+    ) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            state
+                .checkpoints
+                .insert((run.id, page), PageStatus::InFlight);
+            Ok(())
+        })
+    }
+
+    fn write<'a>(
+        &'a mut self,
+        page: SinkPage<'a>,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            let key = (page.run.id, page.page);
+            state
+                .batch
+                .push((key, page.run.staged, page.foods.to_vec()));
+
+            if page.run.staged {
+                return Ok(AggregationStats::default());
+            }
+            Ok(written_stats(page.foods))
+        })
+    }
+
+    fn fail_page<'a>(&'a mut self, page: FailedPage<'a>) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            let key = (page.run.id, page.page);
+            state.checkpoints.insert(key, PageStatus::Failed);
+            state.dead_letters.insert(key, page.error.to_string());
+            Ok(())
+        })
+    }
+
+    fn commit<'a>(
+        &'a mut self,
+        _: &'a SinkRun,
+        stats: &'a AggregationStats,
+    ) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            if std::mem::take(&mut state.fail_commit) {
+                return Err(std::io::Error::other("batch failed to commit").into());
+            }
+
+            for (key, staged, foods) in std::mem::take(&mut state.batch) {
+                state.checkpoints.insert(key, PageStatus::Completed);
+                let pages = if staged { &mut state.staged } else { &mut state.pages };
+                pages.insert(key, foods);
+            }
+            state.stats = stats.clone();
+            Ok(())
+        })
+    }
+
+    fn rollback(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { self.state.lock().await.batch.clear() })
+    }
+
+    fn finish<'a>(
+        &'a mut self,
+        run: &'a SinkRun,
+    ) -> BoxFuture<'a, Result<AggregationStats, SinkError>> {
+        Box::pin(async move {
+            let mut state = self.state.lock().await;
+            let (swapped, kept) = std::mem::take(&mut state.staged)
+                .into_iter()
+                .partition::<BTreeMap<_, _>, _>(|((id, _), _)| *id == run.id);
+            state.staged = kept;
+
+            let mut stats = AggregationStats::default();
+            for foods in swapped.values() {
+                stats.merge(written_stats(foods));
+            }
+            state.pages.extend(swapped);
+            Ok(stats)
+        })
+    }
+}
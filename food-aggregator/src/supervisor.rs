@@ -1,20 +1,31 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 
 use derive_more::{Display, Error, From};
-use governor::RateLimiter;
-use governor::clock::{Clock, QuantaClock, Reference};
-use governor::state::{InMemoryState, NotKeyed};
-use sqlx::{PgConnection, QueryBuilder};
+use rand::Rng;
+use sqlx::types::Uuid;
+use sqlx::{PgConnection, PgPool};
 use tokio::task::{JoinError, JoinHandle};
+use tokio_util::sync::CancellationToken;
 
+use crate::concurrency::{ConcurrencyLimit, Signal};
+use crate::config::{CommitPolicy, RestartPolicy};
+use crate::models::aggregation_checkpoints::{AggregationCheckpoint, PageStatus};
+use crate::models::aggregation_history::AggregationStats;
+use crate::models::dead_letters::DeadLetterKind;
+use crate::models::food_diffs::{CreateFoodDiffPayload, FoodDiff, FoodDiffKind};
+use crate::models::food_ingest::{FoodIngest, IngestFoodNutrientPayload};
 use crate::models::food_nutrients::{CreateFoodNutrientPayload, FoodNutrients};
-use crate::models::food_sources::{CreateFoodSourcePayload, FoodSources};
+use crate::models::food_sources::FoodSources;
 use crate::models::foods::{CreateFoodPayload, Foods};
 use crate::models::nutrients::Nutrients;
 use crate::models::units::Units;
-use crate::models::wweia_categories::{CreateWWEIACategoryPayload, WWEIACategories};
-use crate::{AggregateStatus, FoodSource, SourceError};
+use crate::models::wweia_categories::WWEIACategories;
+use crate::monitor::{ProgressEventKind, SourceMonitor};
+use crate::rate_limit::SourceLimiter;
+use crate::sink::{FailedPage, FoodRecord, FoodSink, SinkError, SinkPage, SinkRun};
+use crate::{AggregateStatus, FoodSource, SourceError, nutrient_catalog};
 
 pub trait FoodData {
     type Entry: FoodEntry + Send + Sync;
@@ -23,6 +34,8 @@ pub trait FoodData {
         Self: 'a;
 
     fn entries(&self) -> Self::EntryIter<'_>;
+    /// Total pages of the source, as reported by the page this data was fetched from
+    fn total_pages(&self) -> usize;
 }
 
 pub trait FoodEntry {
@@ -35,14 +48,36 @@ pub trait FoodEntry {
     fn wweia_data(&self) -> Option<(i32, &String)>;
     fn name(&self) -> &str;
     fn fndds_code(&self) -> Option<i32>;
-    fn id(&self) -> i32;
+    /// Identifier of the food within its source, like an FDC id or a barcode
+    fn id(&self) -> String;
     fn nutrients(&self) -> Self::NutrientIter<'_>;
 }
 
 pub trait FoodEntryNutrient {
     fn name(&self) -> &str;
+    /// USDA nutrient number, for sources that identify nutrients by it. Names are ambiguous, USDA
+    /// reports energy as `Energy` both in kcal and in kJ, while the number tells them apart
+    fn number(&self) -> Option<&str> {
+        None
+    }
+    /// INFOODS tagname, for national composition tables that identify nutrients by it
+    fn tagname(&self) -> Option<&str> {
+        None
+    }
     fn unit_name(&self) -> &str;
-    fn value(&self) -> f32;
+    /// Amount of the nutrient, `None` when the source lists the nutrient without reporting how
+    /// much of it the food has, which is not the same as the food having none of it
+    fn value(&self) -> Option<f32>;
+}
+
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug)]
+struct PendingRetry {
+    page: usize,
+    retries: usize,
+    not_before: tokio::time::Instant,
 }
 
 #[derive(Debug)]
@@ -51,6 +86,8 @@ struct WorkerResult<D> {
     page: usize,
     result: Result<D, SourceError>,
     retries: usize,
+    /// How long the fetch took
+    latency: Duration,
 }
 
 #[derive(Debug)]
@@ -62,7 +99,7 @@ enum WorkerMessage<D> {
 pub struct WorkerId(usize);
 
 impl WorkerId {
-    fn next(&mut self) {
+    pub(crate) fn next(&mut self) {
         self.0 += 1;
     }
 }
@@ -82,6 +119,217 @@ pub enum SupervisorError {
     Database(sqlx::Error),
     #[from]
     Join(JoinError),
+    #[from]
+    Sink(SinkError),
+    /// A batch failed to commit. Its pages are left in flight and fetched again once the run
+    /// resumes
+    #[display("failed to commit batch: {_0}")]
+    Commit(SinkError),
+}
+
+/// Where a run should pick up from, derived from the checkpoints of a previous attempt
+#[derive(Debug)]
+pub struct ResumePoint {
+    /// First page that was never handed to a worker
+    next_page: usize,
+    /// Pages that were being fetched or persisted when the previous attempt stopped
+    interrupted: VecDeque<usize>,
+}
+
+impl ResumePoint {
+    pub fn from_checkpoints(checkpoints: &[AggregationCheckpoint]) -> Self {
+        let next_page = checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.page as usize + 1)
+            .max()
+            .unwrap_or(1);
+
+        let interrupted = checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.status == PageStatus::InFlight)
+            .map(|checkpoint| checkpoint.page as usize)
+            .collect();
+
+        Self {
+            next_page,
+            interrupted,
+        }
+    }
+
+    /// Takes the next page that still has to be fetched, interrupted pages first
+    pub fn take_next(&mut self) -> usize {
+        self.interrupted.pop_front().unwrap_or_else(|| {
+            let page = self.next_page;
+            self.next_page += 1;
+            page
+        })
+    }
+
+    pub fn is_complete(&self, total_pages: usize) -> bool {
+        self.interrupted.is_empty() && self.next_page > total_pages
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SupervisorConfig {
+    pub restart: RestartPolicy,
+    pub min_workers: usize,
+    pub max_workers: usize,
+    pub max_retries: usize,
+    pub commit: CommitPolicy,
+    pub dry_run: bool,
+}
+
+/// Hands pages to a [`FoodSink`] according to a [`CommitPolicy`], keeping the counters of the
+/// attempt along the way.
+#[derive(Debug)]
+pub struct PageCommitter {
+    run: SinkRun,
+    policy: CommitPolicy,
+    sink: Box<dyn FoodSink>,
+    uncommitted_pages: usize,
+    stats: AggregationStats,
+    /// Counters of the pages in the open batch
+    pending: AggregationStats,
+}
+
+impl PageCommitter {
+    pub fn new(
+        run_id: Uuid,
+        source: impl Into<String>,
+        policy: CommitPolicy,
+        sink: Box<dyn FoodSink>,
+    ) -> Self {
+        let run = SinkRun {
+            id: run_id,
+            source: source.into(),
+            staged: matches!(policy, CommitPolicy::Staged),
+            history_id: None,
+        };
+
+        Self {
+            run,
+            policy,
+            sink,
+            uncommitted_pages: 0,
+            stats: AggregationStats::default(),
+            pending: AggregationStats::default(),
+        }
+    }
+
+    pub fn with_history(mut self, history_id: Uuid) -> Self {
+        self.run.history_id = Some(history_id);
+        self
+    }
+
+    pub fn stats(&self) -> &AggregationStats {
+        &self.stats
+    }
+
+    pub fn stats_mut(&mut self) -> &mut AggregationStats {
+        &mut self.stats
+    }
+
+    pub async fn start_page(&mut self, page: usize, retries: usize) -> Result<(), SupervisorError> {
+        self.sink.start_page(&self.run, page, retries).await?;
+        Ok(())
+    }
+
+    pub async fn persist<D>(
+        &mut self,
+        page: usize,
+        attempts: usize,
+        data: D,
+    ) -> Result<(), SupervisorError>
+    where
+        D: FoodData + Send + Sync,
+    {
+        let foods = data
+            .entries()
+            .map(FoodRecord::from_entry)
+            .collect::<Vec<_>>();
+        let page = SinkPage {
+            run: &self.run,
+            page,
+            attempts,
+            foods: &foods,
+        };
+        let written = self.sink.write(page).await?;
+
+        self.pending.pages_fetched += 1;
+        self.pending.merge(written);
+
+        self.uncommitted_pages += 1;
+        let batch_size = match self.policy {
+            CommitPolicy::PerPage | CommitPolicy::Staged => 1,
+            CommitPolicy::Batch { pages } => pages,
+        };
+
+        if self.uncommitted_pages >= batch_size {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Gives up on a page, keeping it aside so it can be replayed later
+    pub async fn fail_page(
+        &mut self,
+        page: usize,
+        kind: DeadLetterKind,
+        retries: usize,
+        error: &str,
+    ) -> Result<(), SupervisorError> {
+        let failed = FailedPage {
+            run: &self.run,
+            page,
+            kind,
+            retries,
+            error,
+        };
+        self.sink.fail_page(failed).await?;
+
+        self.stats.pages_failed += 1;
+        self.stats.record_error(error);
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), SupervisorError> {
+        let pages = std::mem::take(&mut self.uncommitted_pages);
+        let pending = std::mem::take(&mut self.pending);
+        if pages == 0 {
+            return Ok(());
+        }
+
+        // A batch that failed to commit leaves its pages in flight rather than dead lettering
+        // them, as nothing is wrong with the pages themselves
+        if let Err(e) = self.commit(pending).await {
+            self.sink.rollback().await;
+            return Err(SupervisorError::Commit(e));
+        }
+
+        tracing::debug!(%pages, "Committed pages");
+        Ok(())
+    }
+
+    async fn commit(&mut self, mut pending: AggregationStats) -> Result<(), SinkError> {
+        pending.merge(self.sink.flush(&self.run).await?);
+        let mut stats = self.stats.clone();
+        stats.merge(pending);
+
+        self.sink.commit(&self.run, &stats).await?;
+        self.stats = stats;
+        Ok(())
+    }
+
+    /// Commits every pending page, and with a staged policy makes the whole run visible at once
+    pub async fn finish(&mut self) -> Result<(), SupervisorError> {
+        self.flush().await?;
+
+        let finished = self.sink.finish(&self.run).await?;
+        self.stats.merge(finished);
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -92,11 +340,14 @@ where
 {
     worker_id: WorkerId,
     task_bound: usize,
+    concurrency: ConcurrencyLimit,
     max_retries: usize,
-    limiter: &'a mut RateLimiter<NotKeyed, InMemoryState, QuantaClock>,
+    limiter: &'a SourceLimiter,
+    committer: &'a mut PageCommitter,
     workers: HashMap<WorkerId, JoinHandle<Result<(), WorkerError>>>,
-    retry_queue: Vec<(usize, usize)>, // (page, retry_count)
+    retry_queue: Vec<PendingRetry>,
     client: Arc<C>,
+    monitor: SourceMonitor,
 }
 
 impl<'a, C, D> AggregatorSupervisor<'a, C, D>
@@ -105,52 +356,90 @@ where
     D: FoodData + Send + Sync + 'static,
 {
     pub fn new(
-        limiter: &'a mut RateLimiter<NotKeyed, InMemoryState, QuantaClock>,
+        limiter: &'a SourceLimiter,
+        committer: &'a mut PageCommitter,
         client: Arc<C>,
         total_pages: usize,
+        config: SupervisorConfig,
+        monitor: SourceMonitor,
     ) -> Self {
-        let remaining_pages = total_pages - 1;
-        // Limit concurrent requests to avoid spamming the data source API
-        let task_bound = usize::min(10, remaining_pages);
+        let remaining_pages = total_pages.saturating_sub(1);
+        // Never more workers than pages, the limit adapts within the bounds to how the data
+        // source copes with the load
+        let task_bound = usize::min(config.max_workers, remaining_pages);
+        let concurrency = ConcurrencyLimit::new(config.min_workers, task_bound);
 
         Self {
             client,
             limiter,
+            committer,
             task_bound,
-            max_retries: 3,
+            concurrency,
+            max_retries: config.max_retries,
             worker_id: WorkerId::default(),
             workers: HashMap::with_capacity(task_bound),
             retry_queue: Vec::new(),
+            monitor,
         }
     }
 
-    #[tracing::instrument(skip(self, tx), fields(source = %self.client.name()))]
-    pub async fn run(&mut self, tx: &mut PgConnection) -> Result<AggregateStatus, SupervisorError> {
-        let (sender, mut receiver) = tokio::sync::mpsc::channel(self.task_bound);
-        // Start from page 2 as first page will always be fetched outside to get the total_pages
-        // data from the api
-        // TODO: maybe receive this as an argument
-        let mut current_page = 2;
-        tracing::info!(%current_page, "supervisor starting");
+    /// Fetches the remaining pages of the run. Once rate limited or cancelled no more pages are
+    /// fetched, but the ones in flight are still persisted and committed before returning. The
+    /// pool is only used by sources with a rate limit, to take from their budget.
+    #[tracing::instrument(skip(self, pool, resume, cancel), fields(source = %self.client.name()))]
+    pub async fn run(
+        &mut self,
+        pool: &PgPool,
+        resume: ResumePoint,
+        cancel: CancellationToken,
+    ) -> Result<AggregateStatus, SupervisorError> {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(self.task_bound.max(1));
+        let mut current_page = resume.next_page;
+        tracing::info!(%current_page, interrupted = ?resume.interrupted, "supervisor starting");
         let mut status = AggregateStatus::Finished;
 
+        // Pages that were in flight when the previous attempt stopped never got persisted, so
+        // they are fetched again before moving on to new pages
+        let now = tokio::time::Instant::now();
+        self.retry_queue
+            .extend(resume.interrupted.into_iter().map(|page| PendingRetry {
+                page,
+                retries: 0,
+                not_before: now,
+            }));
+        self.monitor.set_pending_retries(self.retry_queue.len());
+
         loop {
-            // Process retry queue first
-            while self.workers.len() < self.task_bound && !self.retry_queue.is_empty() {
-                if let Err(err) = self.limiter.check() {
-                    let now = governor::clock::QuantaClock::default().now();
-                    let earliest = err.earliest_possible();
-                    let wait_duration = earliest.duration_since(now);
-                    let wake_time = tokio::time::Instant::now() + wait_duration.into();
-                    status = AggregateStatus::PendingUntil(wake_time);
+            if cancel.is_cancelled() && matches!(status, AggregateStatus::Finished) {
+                tracing::info!(in_flight = %self.workers.len(), "Cancelled, draining workers");
+                status = AggregateStatus::Cancelled;
+            }
+
+            // Process retry queue first. Once rate limited or cancelled we stop creating workers
+            // and only drain the ones in flight, the remaining pages are picked up from the
+            // checkpoints when the aggregator runs again
+            while matches!(status, AggregateStatus::Finished)
+                && self.workers.len() < self.concurrency.limit()
+            {
+                let now = tokio::time::Instant::now();
+                let Some(index) = self.retry_queue.iter().position(|r| r.not_before <= now) else {
+                    break;
+                };
+
+                if let Err(exhausted) = self.limiter.check(pool).await? {
+                    status = AggregateStatus::PendingUntil(exhausted.until);
                     break;
                 }
 
-                let (page, retry_count) = self.retry_queue.remove(0);
-                self.spawn_worker(&sender, page, retry_count);
+                let retry = self.retry_queue.remove(index);
+                self.monitor.set_pending_retries(self.retry_queue.len());
+                self.committer.start_page(retry.page, retry.retries).await?;
+                self.spawn_worker(&sender, retry.page, retry.retries);
             }
 
-            while self.workers.len() < self.task_bound {
+            while matches!(status, AggregateStatus::Finished)
+                && self.workers.len() < self.concurrency.limit()
+            {
                 // stop creating workers if the client is finished and no retries are pending
                 if self.client.is_finished(current_page) {
                     break;
@@ -158,106 +447,178 @@ where
 
                 // if we hit the rate limit, we stop creating workers, but cache the status to
                 // return later
-                if let Err(err) = self.limiter.check() {
-                    let now = governor::clock::QuantaClock::default().now();
-                    let earliest = err.earliest_possible();
-
-                    let wait_duration = earliest.duration_since(now);
-                    let wake_time = tokio::time::Instant::now() + wait_duration.into();
-                    status = AggregateStatus::PendingUntil(wake_time);
+                if let Err(exhausted) = self.limiter.check(pool).await? {
+                    status = AggregateStatus::PendingUntil(exhausted.until);
                     break;
                 }
 
+                self.committer.start_page(current_page, 0).await?;
                 self.spawn_worker(&sender, current_page, 0);
                 current_page += 1;
             }
 
-            if self.workers.is_empty() {
+            // Retries still backing off are left to the next attempt once stopping, as their
+            // pages remain checkpointed as in flight
+            let stopping = !matches!(status, AggregateStatus::Finished);
+            if self.workers.is_empty() && (self.retry_queue.is_empty() || stopping) {
                 break;
             }
 
-            while let Some(message) = receiver.recv().await {
-                match message {
-                    WorkerMessage::Completed(worker_result) => {
-                        let worker_handle = self
-                            .workers
-                            .remove(&worker_result.worker_id)
-                            .expect("unexisting worker id sent through channel");
-
-                        // Await the worker to ensure it completed properly
-                        match worker_handle.await? {
-                            Ok(()) => {
-                                match worker_result.result {
-                                    Ok(data) => {
-                                        let now = std::time::Instant::now();
-                                        tracing::debug!(worker_id = %result.worker_id, page = %result.page, "Persisting food data");
-
-                                        match persist_food_data(tx, data).await {
-                                            Ok(_) => tracing::info!(
-                                                worker_id = %worker_result.worker_id,
-                                                page = %worker_result.page,
-                                                "Data persisted successfully, took: {took:?}",
-                                                took = now.elapsed()
-                                            ),
-                                            Err(e) => tracing::error!(
-                                                worker_id = %worker_result.worker_id,
-                                                page = %worker_result.page,
-                                                error = ?e,
-                                                "Failed to persist data"
-                                            ),
-                                        }
-                                    }
-                                    Err(e) => {
-                                        tracing::error!(
-                                            worker_id = %worker_result.worker_id,
-                                            page = %worker_result.page,
-                                            retries = %worker_result.retries,
-                                            error = ?e,
-                                            "Worker failed to fetch data"
-                                        );
-
-                                        // Add to retry queue if we haven't exceeded max retries
-                                        if worker_result.retries < self.max_retries {
-                                            tracing::info!(
-                                                page = %worker_result.page,
-                                                retry_count = %(worker_result.retries + 1),
-                                                "Adding page to retry queue"
-                                            );
-                                            self.retry_queue.push((
-                                                worker_result.page,
-                                                worker_result.retries + 1,
-                                            ));
-                                        } else {
-                                            tracing::error!(
-                                                page = %worker_result.page,
-                                                "Max retries exceeded, giving up on page"
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!(
-                                    worker_id = %worker_result.worker_id,
-                                    error = ?e,
-                                    "Worker task failed"
-                                );
-                            }
-                        }
+            let next_retry = self.retry_queue.iter().map(|retry| retry.not_before).min();
+            let message = match next_retry {
+                Some(not_before) if !stopping => tokio::select! {
+                    message = receiver.recv() => message,
+                    _ = tokio::time::sleep_until(not_before) => continue,
+                    _ = cancel.cancelled() => continue,
+                },
+                _ => receiver.recv().await,
+            };
+
+            let Some(WorkerMessage::Completed(worker_result)) = message else {
+                break;
+            };
+
+            let worker_handle = self
+                .workers
+                .remove(&worker_result.worker_id)
+                .expect("unexisting worker id sent through channel");
+
+            // Await the worker to ensure it completed properly
+            if let Err(e) = worker_handle.await? {
+                tracing::error!(
+                    worker_id = %worker_result.worker_id,
+                    error = ?e,
+                    "Worker task failed"
+                );
+                continue;
+            }
+
+            self.handle_worker_result(worker_result).await?;
+        }
+
+        self.committer.flush().await?;
+
+        if matches!(status, AggregateStatus::Finished) {
+            tracing::info!("All workers completed");
+        }
+
+        Ok(status)
+    }
+
+    async fn handle_worker_result(
+        &mut self,
+        worker_result: WorkerResult<D>,
+    ) -> Result<(), SupervisorError> {
+        let WorkerResult {
+            worker_id,
+            page,
+            result,
+            retries,
+            latency,
+        } = worker_result;
+
+        let signal = match &result {
+            Ok(_) => Some(Signal::Healthy(latency)),
+            // Errors retrying can't fix say nothing about the load on the source
+            Err(e) if e.is_retryable() => Some(Signal::Overloaded),
+            Err(_) => None,
+        };
+        if let Some(signal) = signal
+            && let Some(workers) = self.concurrency.observe(worker_id, self.worker_id, signal)
+        {
+            tracing::info!(%workers, ?signal, "Adjusted concurrency");
+            self.monitor
+                .publish(ProgressEventKind::WorkersAdjusted { workers });
+        }
+
+        match result {
+            Ok(data) => {
+                let now = std::time::Instant::now();
+                tracing::debug!(%worker_id, %page, "Persisting food data");
+
+                match self.committer.persist(page, retries, data).await {
+                    Ok(()) => {
+                        let took = now.elapsed();
+                        tracing::info!(
+                            %worker_id,
+                            %page,
+                            "Data persisted successfully, took: {took:?}",
+                        );
+                        self.monitor.publish(ProgressEventKind::PagePersisted {
+                            worker_id: Some(worker_id.0),
+                            page,
+                            retries,
+                            persist_ms: took.as_millis() as u64,
+                        });
+                    }
+                    Err(e @ SupervisorError::Commit(_)) => return Err(e),
+                    Err(e) => {
+                        tracing::error!(%worker_id, %page, error = ?e, "Failed to persist data");
+                        self.dead_letter(page, DeadLetterKind::Persist, retries, &e.to_string())
+                            .await?;
                     }
                 }
+            }
+            Err(e) => {
+                tracing::error!(
+                    %worker_id,
+                    %page,
+                    %retries,
+                    error = ?e,
+                    "Worker failed to fetch data"
+                );
 
-                if self.client.is_finished(current_page)
-                    && self.workers.is_empty()
-                    && self.retry_queue.is_empty()
-                {
-                    tracing::info!("All workers completed");
-                    break;
+                // Add to retry queue if the error is transient and we haven't exceeded max retries
+                if e.is_retryable() && retries < self.max_retries {
+                    let delay = retry_delay(retries, &e);
+                    let stats = self.committer.stats_mut();
+                    stats.pages_retried += 1;
+                    stats.record_error(e.to_string());
+                    self.monitor.publish(ProgressEventKind::PageRetrying {
+                        page,
+                        retries: retries + 1,
+                        delay_ms: delay.as_millis() as u64,
+                        error: e.to_string(),
+                    });
+
+                    tracing::info!(
+                        %page,
+                        retry_count = %(retries + 1),
+                        ?delay,
+                        "Adding page to retry queue"
+                    );
+                    self.retry_queue.push(PendingRetry {
+                        page,
+                        retries: retries + 1,
+                        not_before: tokio::time::Instant::now() + delay,
+                    });
+                    self.monitor.set_pending_retries(self.retry_queue.len());
+                } else {
+                    tracing::error!(%page, retryable = %e.is_retryable(), "Giving up on page");
+                    self.dead_letter(page, DeadLetterKind::Fetch, retries, &e.to_string())
+                        .await?;
                 }
             }
         }
 
-        Ok(status)
+        Ok(())
+    }
+
+    /// Gives up on a page, keeping it in the dead letter queue so it can be replayed later
+    async fn dead_letter(
+        &mut self,
+        page: usize,
+        kind: DeadLetterKind,
+        retries: usize,
+        error: &str,
+    ) -> Result<(), SupervisorError> {
+        self.committer.fail_page(page, kind, retries, error).await?;
+        self.monitor.publish(ProgressEventKind::PageFailed {
+            page,
+            retries,
+            error: error.to_string(),
+        });
+        Ok(())
     }
 
     fn spawn_worker(
@@ -270,6 +631,11 @@ where
         let client = self.client.clone();
         let worker_id = self.worker_id;
         self.worker_id.next();
+        self.monitor.publish(ProgressEventKind::PageStarted {
+            worker_id: Some(worker_id.0),
+            page,
+            retries: retry_count,
+        });
 
         let span = tracing::span!(
             tracing::Level::INFO,
@@ -283,6 +649,7 @@ where
             let _guard = span.enter();
             tracing::info!("Worker started");
 
+            let now = std::time::Instant::now();
             let result = client.fetch(page).await;
 
             let worker_result = WorkerResult {
@@ -290,6 +657,7 @@ where
                 page,
                 result,
                 retries: retry_count,
+                latency: now.elapsed(),
             };
 
             // Always send the result, don't fail the worker for channel issues
@@ -307,75 +675,557 @@ where
     }
 }
 
-pub async fn persist_food_data<D>(tx: &mut PgConnection, data: D) -> Result<(), SupervisorError>
-where
-    D: FoodData + Send + Sync,
-{
-    let mut sources = HashSet::new();
-    let mut categories = HashSet::new();
-    let mut nutrients = HashSet::new();
-    let mut units = HashSet::new();
+/// Exponential backoff with jitter, so pages failing together don't all retry at the same time.
+/// A delay requested by the source through `Retry-After` always takes precedence.
+fn retry_delay(retries: usize, error: &SourceError) -> Duration {
+    let exponent = u32::try_from(retries).unwrap_or(u32::MAX).min(16);
+    let backoff = RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(RETRY_MAX_DELAY);
 
-    for entry in data.entries() {
-        sources.insert(entry.source());
+    let half = backoff / 2;
+    let jitter = half.mul_f64(rand::rng().random::<f64>());
+    let delay = half + jitter;
 
-        if let Some((id, name)) = entry.wweia_data() {
-            categories.insert((id, name));
-        }
+    match error.retry_after() {
+        Some(retry_after) => delay.max(retry_after),
+        None => delay,
+    }
+}
 
-        for nutrient in entry.nutrients() {
-            nutrients.insert(nutrient.name());
-            units.insert(nutrient.unit_name());
+/// Ids of the sources, categories, nutrients and units the foods of a page refer to, created
+/// when they don't exist yet. Nutrients and units are created outside of the page transaction,
+/// see [`Nutrients::get_or_create_ids`]
+struct PageLookups {
+    sources: HashMap<String, Uuid>,
+    categories: HashMap<String, Uuid>,
+    nutrients: HashMap<String, Uuid>,
+    units: HashMap<String, Uuid>,
+}
+
+impl PageLookups {
+    async fn resolve(
+        pool: &PgPool,
+        tx: &mut PgConnection,
+        foods: &[FoodRecord],
+    ) -> sqlx::Result<Self> {
+        nutrient_catalog::sync(pool).await?;
+
+        let mut sources = HashSet::new();
+        let mut categories = HashSet::new();
+        let mut nutrients = HashSet::new();
+        let mut units = HashSet::new();
+
+        for entry in foods {
+            sources.insert(entry.source());
+
+            if let Some((id, name)) = entry.wweia_data() {
+                categories.insert((id, name));
+            }
+
+            for nutrient in entry.nutrients() {
+                let nutrient = nutrient_catalog::canonical(nutrient);
+                nutrients.insert(nutrient.name);
+                units.insert(nutrient.unit);
+            }
         }
+
+        Ok(Self {
+            sources: FoodSources::maybe_create_bulk(tx, sources.into_iter()).await?,
+            categories: WWEIACategories::maybe_create_bulk(tx, categories.into_iter()).await?,
+            nutrients: Nutrients::get_or_create_ids(pool, nutrients.into_iter()).await?,
+            units: Units::get_or_create_ids(pool, units.into_iter()).await?,
+        })
     }
 
-    let source_id_map = FoodSources::maybe_create_bulk(tx, sources.into_iter()).await?;
-    let category_id_map = WWEIACategories::maybe_create_bulk(tx, categories.into_iter()).await?;
-    let nutient_id_map = Nutrients::maybe_create_bulk(tx, nutrients.into_iter()).await?;
-    let unit_id_map = Units::maybe_create_bulk(tx, units.into_iter()).await?;
-
-    let mut foods = vec![];
-    for entry in data.entries() {
-        let source_id = source_id_map[&entry.source()];
-        let category_id = entry
-            .wweia_data()
-            .and_then(|(_, name)| category_id_map.get(name).copied());
-
-        let payload = CreateFoodPayload::new(
-            entry.name(),
-            entry.fndds_code(),
-            source_id,
-            entry.id(),
-            category_id,
-        );
-        foods.push(payload);
+    fn food_payloads<'a>(&self, foods: &'a [FoodRecord]) -> Vec<CreateFoodPayload<'a>> {
+        foods
+            .iter()
+            .map(|entry| {
+                let category_id = entry
+                    .wweia_data()
+                    .and_then(|(_, name)| self.categories.get(name).copied());
+
+                CreateFoodPayload::new(
+                    entry.name(),
+                    entry.fndds_code(),
+                    self.sources[&entry.source],
+                    entry.id(),
+                    category_id,
+                )
+            })
+            .collect()
     }
+}
+
+/// Upserts the foods of a page into the live tables, returning how many foods and nutrients were
+/// written.
+pub async fn persist_food_data(
+    pool: &PgPool,
+    tx: &mut PgConnection,
+    foods: &[FoodRecord],
+) -> sqlx::Result<AggregationStats> {
+    let lookups = PageLookups::resolve(pool, tx, foods).await?;
+    let payloads = lookups.food_payloads(foods);
 
-    let food_id_map = Foods::create_or_update_bulk(tx, foods.into_iter()).await?;
+    let upserted = Foods::create_or_update_bulk(tx, payloads.into_iter()).await?;
+    let mut stats = AggregationStats {
+        foods_inserted: upserted.inserted as i64,
+        foods_updated: upserted.updated as i64,
+        ..AggregationStats::default()
+    };
 
-    for entry in data.entries() {
-        let food_key = (entry.source(), entry.id());
-        let food_id = food_id_map[&food_key];
-        let source_id = source_id_map[&entry.source()];
-        let mut food_nutrients = vec![];
+    for entry in foods {
+        let source_id = lookups.sources[&entry.source];
+        let food_id = upserted.ids[&(source_id, entry.id())];
+        // A nutrient can only be upserted once per statement, the last one listed by the source wins
+        let mut food_nutrients = HashMap::new();
 
         for nutrient in entry.nutrients() {
-            let nutrient_id = nutient_id_map[nutrient.name()];
-            let unit_id = unit_id_map[nutrient.unit_name()];
+            let nutrient = nutrient_catalog::canonical(nutrient);
+            let nutrient_id = lookups.nutrients[nutrient.name];
+            let unit_id = lookups.units[nutrient.unit];
 
             let payload = CreateFoodNutrientPayload::new(
                 food_id,
                 nutrient_id,
                 unit_id,
                 source_id,
-                nutrient.value(),
+                nutrient.value,
             );
 
-            food_nutrients.push(payload);
+            food_nutrients.insert(nutrient_id, payload);
+        }
+
+        let food_nutrients = food_nutrients.into_values().collect();
+        let written = FoodNutrients::create_or_update_bulk(tx, food_nutrients).await?;
+        stats.nutrients_written += written as i64;
+    }
+
+    Ok(stats)
+}
+
+/// Copies the foods of a page into the bulk ingest tables of the transaction, they are only
+/// written once [`FoodIngest::merge`] runs before it commits.
+pub async fn stage_food_data(
+    pool: &PgPool,
+    tx: &mut PgConnection,
+    foods: &[FoodRecord],
+) -> sqlx::Result<()> {
+    let lookups = PageLookups::resolve(pool, tx, foods).await?;
+    let payloads = lookups.food_payloads(foods);
+
+    let mut food_nutrients = vec![];
+    for entry in foods {
+        for nutrient in entry.nutrients() {
+            let nutrient = nutrient_catalog::canonical(nutrient);
+            food_nutrients.push(IngestFoodNutrientPayload {
+                source_id: lookups.sources[&entry.source],
+                external_id: &entry.external_id,
+                nutrient_id: lookups.nutrients[nutrient.name],
+                unit_id: lookups.units[nutrient.unit],
+                value: nutrient.value,
+            });
+        }
+    }
+
+    FoodIngest::stage(tx, &payloads, &food_nutrients).await
+}
+
+/// Compares the entries of a page against the stored foods of the same source, recording what
+/// persisting the page would change without writing to the live tables.
+pub async fn diff_food_data(
+    tx: &mut PgConnection,
+    run_id: Uuid,
+    foods: &[FoodRecord],
+) -> sqlx::Result<()> {
+    let mut entries_by_source = HashMap::<String, Vec<&FoodRecord>>::new();
+    for entry in foods {
+        entries_by_source
+            .entry(entry.source())
+            .or_default()
+            .push(entry);
+    }
+
+    for (source, entries) in entries_by_source {
+        let external_ids = entries.iter().map(|entry| entry.id()).collect::<Vec<_>>();
+        let stored_foods = Foods::get_by_external_ids(tx, &source, &external_ids).await?;
+        let food_ids = stored_foods.iter().map(|food| food.id).collect::<Vec<_>>();
+
+        let mut stored_nutrients = HashMap::<Uuid, HashMap<String, Option<f32>>>::new();
+        for nutrient in FoodNutrients::get_for_foods(tx, &food_ids).await? {
+            stored_nutrients
+                .entry(nutrient.food_id)
+                .or_default()
+                .insert(nutrient.nutrient, nutrient.value);
+        }
+
+        let stored_foods = stored_foods
+            .iter()
+            .map(|food| (food.external_id.as_str(), food))
+            .collect::<HashMap<_, _>>();
+
+        let mut diffs = vec![];
+        for entry in entries {
+            let external_id = entry.id();
+            let Some(stored) = stored_foods.get(external_id.as_str()) else {
+                diffs.push(CreateFoodDiffPayload::new(
+                    external_id,
+                    FoodDiffKind::Added,
+                    entry.name(),
+                ));
+                continue;
+            };
+
+            let diffs_before = diffs.len();
+            if stored.name != entry.name() {
+                let payload = CreateFoodDiffPayload::new(
+                    external_id.clone(),
+                    FoodDiffKind::Renamed,
+                    entry.name(),
+                )
+                .with_previous_name(&stored.name);
+                diffs.push(payload);
+            }
+
+            // Nutrients missing from the page are not reported, as persisting never removes them
+            let nutrients = stored_nutrients.get(&stored.id);
+            for nutrient in entry.nutrients() {
+                let nutrient = nutrient_catalog::canonical(nutrient);
+                let previous = nutrients.and_then(|nutrients| nutrients.get(nutrient.name));
+                if previous == Some(&nutrient.value) {
+                    continue;
+                }
+
+                let payload = CreateFoodDiffPayload::new(
+                    external_id.clone(),
+                    FoodDiffKind::NutrientChanged,
+                    entry.name(),
+                )
+                .with_nutrient(
+                    nutrient.name,
+                    nutrient.unit,
+                    previous.copied().flatten(),
+                    nutrient.value,
+                );
+                diffs.push(payload);
+            }
+
+            if diffs.len() == diffs_before {
+                diffs.push(CreateFoodDiffPayload::new(
+                    external_id,
+                    FoodDiffKind::Unchanged,
+                    entry.name(),
+                ));
+            }
         }
 
-        FoodNutrients::create_or_update_bulk(tx, food_nutrients).await?;
+        FoodDiff::replace_for_foods(tx, run_id, &external_ids, diffs).await?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::AggregatorError;
+    use crate::config::SourceConfig;
+    use crate::monitor::AggregationMonitor;
+    use crate::sink::{MemorySink, NutrientRecord};
+
+    const SOURCE: &str = "fake";
+    const RUN_ID: Uuid = Uuid::from_u128(1);
+
+    /// Source of `total_pages` pages holding a single food each, failing to decode the pages in
+    /// `failing`
+    #[derive(Debug)]
+    struct FakeSource {
+        total_pages: usize,
+        failing: HashSet<usize>,
+    }
+
+    #[derive(Debug, Default)]
+    struct FakePage {
+        total_pages: usize,
+        foods: Vec<FoodRecord>,
+    }
+
+    impl FoodData for FakePage {
+        type Entry = FoodRecord;
+        type EntryIter<'a> = std::slice::Iter<'a, FoodRecord>;
+
+        fn entries(&self) -> Self::EntryIter<'_> {
+            self.foods.iter()
+        }
+
+        fn total_pages(&self) -> usize {
+            self.total_pages
+        }
+    }
+
+    impl FoodSource for FakeSource {
+        type Data = FakePage;
+
+        fn name(&self) -> &str {
+            SOURCE
+        }
+
+        fn is_finished(&self, current_page: usize) -> bool {
+            current_page > self.total_pages
+        }
+
+        fn fetch(&self, page: usize) -> impl Future<Output = Result<FakePage, SourceError>> {
+            let result = if self.failing.contains(&page) {
+                Err(SourceError::Decode(format!("page {page} is malformed")))
+            } else {
+                Ok(FakePage {
+                    total_pages: self.total_pages,
+                    foods: vec![food(page)],
+                })
+            };
+            std::future::ready(result)
+        }
+    }
+
+    fn food(page: usize) -> FoodRecord {
+        FoodRecord {
+            source: "Fake".to_string(),
+            external_id: page.to_string(),
+            name: format!("Food {page}"),
+            fndds_code: None,
+            wweia_code: None,
+            wweia_category: None,
+            nutrients: vec![NutrientRecord {
+                name: "Protein".to_string(),
+                number: Some("203".to_string()),
+                tagname: None,
+                unit: "g".to_string(),
+                value: Some(1.0),
+            }],
+        }
+    }
+
+    fn source(total_pages: usize, failing: &[usize]) -> FakeSource {
+        FakeSource {
+            total_pages,
+            failing: failing.iter().copied().collect(),
+        }
+    }
+
+    fn committer(policy: CommitPolicy, sink: &MemorySink) -> PageCommitter {
+        PageCommitter::new(RUN_ID, SOURCE, policy, Box::new(sink.clone()))
+    }
+
+    /// Runs the supervisor from `resume` until it stops, the way the aggregator does once it
+    /// persisted the first page
+    async fn supervise(
+        source: FakeSource,
+        committer: &mut PageCommitter,
+        resume: ResumePoint,
+    ) -> Result<AggregateStatus, AggregatorError> {
+        // Sources without a rate limit never take from their budget, so the pool never connects
+        let pool = PgPoolOptions::new().connect_lazy("postgres://localhost/unused")?;
+        let limiter = SourceLimiter::new(SOURCE, None);
+        let monitor = AggregationMonitor::default().source(SOURCE);
+        let total_pages = source.total_pages;
+
+        let mut supervisor = AggregatorSupervisor::new(
+            &limiter,
+            committer,
+            Arc::new(source),
+            total_pages,
+            SourceConfig::default().supervisor(),
+            monitor,
+        );
+        let status = supervisor
+            .run(&pool, resume, CancellationToken::new())
+            .await?;
+        Ok(status)
+    }
+
+    /// Persists the first page like the aggregator does, resuming with the pages after it
+    async fn first_page(
+        source: &FakeSource,
+        committer: &mut PageCommitter,
+    ) -> Result<ResumePoint, AggregatorError> {
+        committer.start_page(1, 0).await?;
+        committer.persist(1, 0, source.fetch(1).await?).await?;
+
+        Ok(ResumePoint {
+            next_page: 2,
+            interrupted: VecDeque::new(),
+        })
+    }
+
+    async fn food_names(sink: &MemorySink) -> Vec<String> {
+        let mut names = sink
+            .foods()
+            .await
+            .into_iter()
+            .map(|food| food.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn persists_and_checkpoints_every_page() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let source = source(4, &[]);
+        let mut committer = committer(CommitPolicy::PerPage, &sink);
+
+        let resume = first_page(&source, &mut committer).await?;
+        let status = supervise(source, &mut committer, resume).await?;
+        committer.finish().await?;
+
+        assert!(matches!(status, AggregateStatus::Finished));
+        assert_eq!(
+            food_names(&sink).await,
+            ["Food 1", "Food 2", "Food 3", "Food 4"]
+        );
+        for page in 1..=4 {
+            assert_eq!(
+                sink.checkpoint(RUN_ID, page).await,
+                Some(PageStatus::Completed)
+            );
+        }
+
+        let stats = sink.stats().await;
+        assert_eq!(stats.pages_fetched, 4);
+        assert_eq!(stats.foods_inserted, 4);
+        assert_eq!(stats.nutrients_written, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dead_letters_pages_that_cannot_be_fetched() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let source = source(4, &[3]);
+        let mut committer = committer(CommitPolicy::PerPage, &sink);
+
+        let resume = first_page(&source, &mut committer).await?;
+        let status = supervise(source, &mut committer, resume).await?;
+
+        assert!(matches!(status, AggregateStatus::Finished));
+        assert_eq!(food_names(&sink).await, ["Food 1", "Food 2", "Food 4"]);
+        assert_eq!(sink.checkpoint(RUN_ID, 3).await, Some(PageStatus::Failed));
+        assert!(sink.dead_letter(RUN_ID, 3).await.is_some());
+        assert_eq!(sink.dead_letter(RUN_ID, 2).await, None);
+        assert_eq!(committer.stats().pages_failed, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetches_interrupted_pages_again() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let mut committer = committer(CommitPolicy::PerPage, &sink);
+
+        // Every page but the second one was persisted by the previous attempt
+        let resume = ResumePoint {
+            next_page: 5,
+            interrupted: VecDeque::from([2]),
+        };
+        let status = supervise(source(4, &[]), &mut committer, resume).await?;
+
+        assert!(matches!(status, AggregateStatus::Finished));
+        assert_eq!(food_names(&sink).await, ["Food 2"]);
+        assert_eq!(
+            sink.checkpoint(RUN_ID, 2).await,
+            Some(PageStatus::Completed)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batches_only_show_up_once_committed() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let source = source(3, &[]);
+        let mut committer = committer(CommitPolicy::Batch { pages: 2 }, &sink);
+
+        committer.start_page(1, 0).await?;
+        committer.persist(1, 0, source.fetch(1).await?).await?;
+        assert!(sink.foods().await.is_empty());
+        assert_eq!(sink.checkpoint(RUN_ID, 1).await, Some(PageStatus::InFlight));
+
+        committer.persist(2, 0, source.fetch(2).await?).await?;
+        assert_eq!(food_names(&sink).await, ["Food 1", "Food 2"]);
+
+        committer.persist(3, 0, source.fetch(3).await?).await?;
+        committer.flush().await?;
+        assert_eq!(food_names(&sink).await, ["Food 1", "Food 2", "Food 3"]);
+        assert_eq!(sink.stats().await.pages_fetched, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batches_that_fail_to_commit_are_left_in_flight() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let source = source(3, &[]);
+        let mut committer = committer(CommitPolicy::Batch { pages: 2 }, &sink);
+
+        committer.start_page(1, 0).await?;
+        committer.persist(1, 0, source.fetch(1).await?).await?;
+        committer.start_page(2, 0).await?;
+        sink.fail_next_commit().await;
+        let failed = committer.persist(2, 0, source.fetch(2).await?).await;
+
+        assert!(matches!(failed, Err(SupervisorError::Commit(_))));
+        assert!(sink.foods().await.is_empty());
+        for page in 1..=2 {
+            assert_eq!(
+                sink.checkpoint(RUN_ID, page).await,
+                Some(PageStatus::InFlight)
+            );
+            assert_eq!(sink.dead_letter(RUN_ID, page).await, None);
+        }
+        assert_eq!(committer.stats().pages_fetched, 0);
+
+        // The failed batch is gone, the next one only holds its own pages
+        committer.persist(3, 0, source.fetch(3).await?).await?;
+        committer.flush().await?;
+        assert_eq!(food_names(&sink).await, ["Food 3"]);
+        assert_eq!(sink.stats().await.pages_fetched, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn supervisor_stops_when_a_batch_fails_to_commit() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let source = source(3, &[]);
+        let mut committer = committer(CommitPolicy::PerPage, &sink);
+
+        let resume = first_page(&source, &mut committer).await?;
+        sink.fail_next_commit().await;
+        let failed = supervise(source, &mut committer, resume).await;
+
+        assert!(matches!(
+            failed,
+            Err(AggregatorError::Supervisor(SupervisorError::Commit(_)))
+        ));
+        assert_eq!(committer.stats().pages_failed, 0);
+        for page in 2..=3 {
+            assert_eq!(sink.dead_letter(RUN_ID, page).await, None);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staged_runs_show_up_once_finished() -> Result<(), AggregatorError> {
+        let sink = MemorySink::default();
+        let source = source(3, &[]);
+        let mut committer = committer(CommitPolicy::Staged, &sink);
+
+        let resume = first_page(&source, &mut committer).await?;
+        supervise(source, &mut committer, resume).await?;
+        assert!(sink.foods().await.is_empty());
+        assert_eq!(committer.stats().foods_inserted, 0);
+
+        committer.finish().await?;
+        assert_eq!(food_names(&sink).await, ["Food 1", "Food 2", "Food 3"]);
+        assert_eq!(committer.stats().foods_inserted, 3);
+        Ok(())
+    }
+}
@@ -1,92 +1,103 @@
+mod usda_bulk_file;
 mod usda_client;
+mod usda_csv_download;
 mod usda_types;
 
 use std::num::NonZeroU32;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
 
-use governor::clock::QuantaClock;
-use governor::state::{InMemoryState, NotKeyed};
-use governor::{Quota, RateLimiter};
-use sqlx::PgPool;
+use serde::Deserialize;
+pub use usda_bulk_file::UsdaBulkFile;
 pub use usda_client::UsdaClient;
-use usda_types::UsdaFoodSearchResponse;
+pub use usda_csv_download::UsdaCsvDownload;
 
-use crate::supervisor::{AggregatorSupervisor, persist_food_data};
-use crate::{AggregateStatus, Aggregator, AggregatorError, BoxFuture, FoodSource};
+use crate::aggregator::PagedAggregator;
+use crate::config::{ConfigError, SourceConfig};
+use crate::rate_limit::RateLimit;
+use crate::registry::{AggregatorContext, BoxedAggregator};
 
-#[derive(Debug)]
-pub struct UsdaAggregator<C>
-where
-    C: FoodSource<Data = UsdaFoodSearchResponse>,
-{
-    limiter: RateLimiter<NotKeyed, InMemoryState, QuantaClock>,
-    client: Arc<C>,
+/// Requests FoodData Central accepts per hour for a single API key
+const REQUESTS_PER_HOUR: NonZeroU32 = NonZeroU32::new(30).expect("30 is not zero");
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsdaOptions {
+    #[serde(default = "default_page_size")]
+    page_size: usize,
 }
 
-impl<C> UsdaAggregator<C>
-where
-    C: FoodSource<Data = UsdaFoodSearchResponse>,
-{
-    pub fn new(client: C) -> Self {
-        let quota = Quota::per_hour(NonZeroU32::new(30).unwrap());
-        let limiter = RateLimiter::direct(quota);
+fn default_page_size() -> usize {
+    200
+}
 
-        Self {
-            limiter,
-            client: Arc::new(client),
-        }
-    }
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsdaBulkOptions {
+    /// FoodData Central JSON download, or the directory a CSV download unpacks to
+    path: PathBuf,
+    /// Inferred from the path when not set, directories are read as CSV downloads
+    #[serde(default)]
+    format: Option<UsdaBulkFormat>,
+    #[serde(default = "default_bulk_page_size")]
+    page_size: usize,
 }
 
-impl<C> Aggregator for UsdaAggregator<C>
-where
-    C: FoodSource<Data = UsdaFoodSearchResponse> + 'static,
-{
-    #[tracing::instrument(skip(self, pool))]
-    fn aggregate(&mut self, pool: PgPool) -> BoxFuture<Result<AggregateStatus, AggregatorError>> {
-        Box::pin(async move {
-            // Use one entry from limiter to account for the first request
-            // Safety: first request will not fail rate-limit.
-            if self.limiter.check().is_err() {
-                return Err(AggregatorError::UnexpectedRateLimit);
-            }
-            let mut tx = pool.begin().await?;
+fn default_bulk_page_size() -> usize {
+    1000
+}
 
-            // This first request is made separately in order to fetch the total_pages from USDA
-            // api, so that we can coordinate the concurrent syncing
-            let first_page = match self.client.fetch(1).await {
-                Ok(page) => page,
-                Err(e) => {
-                    tracing::error!(error = ?e, "Failed to fetch first USDA page");
-                    tx.rollback().await?;
-                    return Err(e.into());
-                }
-            };
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UsdaBulkFormat {
+    Json,
+    Csv,
+}
 
-            let total_pages = first_page.total_pages;
-            tracing::info!(%total_pages, "Starting USDA sync");
+impl UsdaBulkFormat {
+    fn from_path(path: &Path) -> Self {
+        if path.is_dir() { UsdaBulkFormat::Csv } else { UsdaBulkFormat::Json }
+    }
+}
 
-            if let Err(e) = persist_food_data(tx.as_mut(), first_page).await {
-                tracing::error!(error = ?e, "Failed to persist USDA first page food data");
-                tx.rollback().await?;
-                return Err(e.into());
-            };
+pub fn build_aggregator(
+    name: &str,
+    config: &SourceConfig,
+    context: &AggregatorContext,
+) -> Result<BoxedAggregator, ConfigError> {
+    let options = config.options::<UsdaOptions>(name)?;
+    let client = UsdaClient::new(context.http.clone(), options.page_size);
+    let rate_limit = config
+        .rate_limit
+        .clone()
+        .unwrap_or_else(|| RateLimit::per_hour(REQUESTS_PER_HOUR));
+    Ok(Box::new(PagedAggregator::new(
+        name,
+        client,
+        Some(rate_limit),
+        config.supervisor(),
+        config.sink.clone(),
+        context.monitor.source(name),
+    )))
+}
 
-            let client = self.client.clone();
-            let mut supervisor = AggregatorSupervisor::new(&mut self.limiter, client, total_pages);
+pub fn build_bulk_aggregator(
+    name: &str,
+    config: &SourceConfig,
+    context: &AggregatorContext,
+) -> Result<BoxedAggregator, ConfigError> {
+    let options = config.options::<UsdaBulkOptions>(name)?;
+    let format = options
+        .format
+        .unwrap_or_else(|| UsdaBulkFormat::from_path(&options.path));
 
-            match supervisor.run(tx.as_mut()).await {
-                Ok(status) => {
-                    tracing::info!(?status, "USDA sync complete");
-                    tx.commit().await?;
-                    Ok(status)
-                }
-                Err(e) => {
-                    tracing::error!(error = ?e, "USDA sync failed");
-                    tx.rollback().await?;
-                    Err(e.into())
-                }
-            }
-        })
-    }
+    Ok(match format {
+        UsdaBulkFormat::Json => {
+            let client = UsdaBulkFile::new(options.path, options.page_size);
+            Box::new(PagedAggregator::from_file(name, client, config, context))
+        }
+        UsdaBulkFormat::Csv => {
+            let client = UsdaCsvDownload::new(options.path, options.page_size);
+            Box::new(PagedAggregator::from_file(name, client, config, context))
+        }
+    })
 }
@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::usda_types::{UsdaBulkFood, UsdaFoodSearchResponse};
+use crate::file_source::{FileIndex, PageSplitter, PagedFile};
+use crate::{FoodSource, SourceError};
+
+/// Reads one of the FoodData Central JSON downloads (Foundation, SR Legacy, FNDDS or Branded)
+/// from disk.
+///
+/// Those files are a single object holding one array with every food of the dataset, which for
+/// the branded foods is a few gigabytes. Instead of loading it whole, the file is scanned once to
+/// find where each page of foods starts and ends, and every fetch only parses its own page.
+pub struct UsdaBulkFile {
+    file: PagedFile<()>,
+    page_size: usize,
+}
+
+impl UsdaBulkFile {
+    pub fn new(path: impl Into<PathBuf>, page_size: usize) -> Self {
+        Self {
+            file: PagedFile::new(path),
+            page_size,
+        }
+    }
+}
+
+impl FoodSource for UsdaBulkFile {
+    type Data = UsdaFoodSearchResponse;
+
+    fn name(&self) -> &str {
+        "USDA"
+    }
+
+    fn is_finished(&self, current_page: usize) -> bool {
+        self.file.is_finished(current_page)
+    }
+
+    fn fetch(&self, current_page: usize) -> impl Future<Output = Result<Self::Data, SourceError>> {
+        let page_size = self.page_size;
+
+        Box::pin(async move {
+            let (total_pages, foods) = self
+                .file
+                .read(
+                    current_page,
+                    move |path| index_pages(path, page_size),
+                    |_, _, bytes| read_page(bytes),
+                )
+                .await?;
+
+            Ok(UsdaFoodSearchResponse {
+                total_pages,
+                foods: foods.into_iter().map(Into::into).collect(),
+            })
+        })
+    }
+}
+
+/// Scans the dataset array, returning the byte range covering the foods of each page
+fn index_pages(path: &Path, page_size: usize) -> Result<FileIndex<()>, SourceError> {
+    // Depth 1 is the wrapping object, 2 the dataset array, so foods open at depth 2
+    const FOOD_DEPTH: usize = 2;
+
+    let mut reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+    let mut splitter = PageSplitter::new(page_size);
+    let mut offset = 0u64;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut food_start = 0u64;
+
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        for &byte in buffer {
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => {
+                        if depth == FOOD_DEPTH {
+                            food_start = offset;
+                        }
+                        depth += 1;
+                    }
+                    b'}' | b']' => {
+                        depth = depth.saturating_sub(1);
+                        if depth == FOOD_DEPTH {
+                            splitter.push(food_start..offset + 1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            offset += 1;
+        }
+
+        let consumed = buffer.len();
+        reader.consume(consumed);
+    }
+
+    if depth != 0 || in_string {
+        return Err(SourceError::Decode(String::from(
+            "bulk file ended in the middle of a json value",
+        )));
+    }
+
+    Ok(FileIndex {
+        header: (),
+        pages: splitter.finish(),
+    })
+}
+
+fn read_page(mut bytes: Vec<u8>) -> Result<Vec<UsdaBulkFood>, SourceError> {
+    // The range holds comma separated foods, wrapping it makes it a valid json array
+    bytes.insert(0, b'[');
+    bytes.push(b']');
+
+    serde_json::from_slice(&bytes).map_err(|e| SourceError::Decode(e.to_string()))
+}
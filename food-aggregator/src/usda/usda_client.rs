@@ -1,9 +1,10 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use super::usda_types::UsdaFoodSearchResponse;
-use crate::{FoodSource, SourceError};
+use crate::{FoodSource, SourceError, http};
 
 pub struct UsdaClient {
+    http: reqwest::Client,
     page_size: usize,
     total_pages: AtomicUsize,
     api_url: String,
@@ -11,14 +12,26 @@ pub struct UsdaClient {
 }
 
 impl UsdaClient {
-    pub fn new() -> Self {
+    pub fn new(http: reqwest::Client, page_size: usize) -> Self {
         let api_key = dotenvy::var("USDA_API_KEY").expect("USDA_API_KEY env var must be set");
-
         let api_url = dotenvy::var("USDA_API_URL").expect("USDA_API_URL env var must be set");
-        let api_url = format!("{api_url}/foods/search");
+
+        Self::with_endpoint(http, api_url, api_key, page_size)
+    }
+
+    /// Builds a client pointed at an arbitrary FoodData Central compatible endpoint
+    pub fn with_endpoint(
+        http: reqwest::Client,
+        api_url: impl AsRef<str>,
+        api_key: impl Into<String>,
+        page_size: usize,
+    ) -> Self {
+        let api_url = format!("{}/foods/search", api_url.as_ref());
+        let api_key = api_key.into();
 
         Self {
-            page_size: 200,
+            http,
+            page_size,
             total_pages: AtomicUsize::new(0),
             api_url,
             api_key,
@@ -39,33 +52,19 @@ impl FoodSource for UsdaClient {
 
     fn fetch(&self, current_page: usize) -> impl Future<Output = Result<Self::Data, SourceError>> {
         Box::pin(async move {
-            let client = reqwest::Client::new();
-            let request = client
+            let request = self
+                .http
                 .get(&self.api_url)
                 .query(&[
                     ("api_key", &self.api_key),
                     ("pageSize", &self.page_size.to_string()),
                     ("pageNumber", &current_page.to_string()),
                 ])
-                .build()
-                .expect("malformed USDA search request");
-
-            let response = match client.execute(request).await {
-                Ok(res) => res,
-                // TODO: if a request fails, we should store it somewhere and try again later
-                Err(_) => todo!(),
-            };
-
-            // TODO: if a request fails, we should store it somewhere and try again later
-            if !response.status().is_success() {
-                tracing::error!("{response:?}");
-                println!()
-            }
+                .build()?;
 
-            let data = match response.json::<UsdaFoodSearchResponse>().await {
-                Ok(body) => body,
-                Err(e) => return Err(e.into()),
-            };
+            let response = self.http.execute(request).await?;
+            let response = http::error_for_status(response).await?;
+            let data = response.json::<UsdaFoodSearchResponse>().await?;
 
             self.total_pages.store(data.total_pages, Ordering::SeqCst);
 
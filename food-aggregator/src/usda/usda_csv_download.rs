@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::usda_types::{UsdaFoodNutrient, UsdaFoodSearchFood, UsdaFoodSearchResponse};
+use crate::file_source::{self, FileIndex, PagedFile, column_position, csv_error};
+use crate::{FoodSource, SourceError};
+
+const FOOD: &str = "food.csv";
+const NUTRIENT: &str = "nutrient.csv";
+const FOOD_NUTRIENT: &str = "food_nutrient.csv";
+const SURVEY_FNDDS_FOOD: &str = "survey_fndds_food.csv";
+const WWEIA_FOOD_CATEGORY: &str = "wweia_food_category.csv";
+
+/// Reads one of the FoodData Central CSV downloads from the directory it unpacks to.
+///
+/// The download splits the dataset into tables: `food.csv` holds the foods, `nutrient.csv` the
+/// nutrient definitions and `food_nutrient.csv` one row per nutrient value of a food. Pages are
+/// cut from `food.csv`, while the rows of `food_nutrient.csv` are indexed by food once, so every
+/// fetch only parses the values of its own foods. FNDDS downloads also carry
+/// `survey_fndds_food.csv` and `wweia_food_category.csv`, which give foods their FNDDS code and
+/// WWEIA category.
+pub struct UsdaCsvDownload {
+    dir: PathBuf,
+    file: PagedFile<UsdaTables>,
+    page_size: usize,
+}
+
+/// Byte ranges of the `food_nutrient.csv` rows of every food
+type FoodNutrientRanges = HashMap<i32, Vec<Range<u64>>>;
+
+/// Everything besides `food.csv` a page needs, small tables are kept whole in memory
+#[derive(Debug)]
+struct UsdaTables {
+    food_columns: FoodColumns,
+    nutrients: HashMap<i32, NutrientDefinition>,
+    food_nutrient_columns: FoodNutrientColumns,
+    food_nutrients: FoodNutrientRanges,
+    survey_foods: HashMap<i32, SurveyFood>,
+}
+
+#[derive(Debug)]
+struct FoodColumns {
+    fdc_id: usize,
+    description: usize,
+}
+
+#[derive(Debug)]
+struct FoodNutrientColumns {
+    fdc_id: usize,
+    nutrient_id: usize,
+    amount: usize,
+}
+
+#[derive(Debug)]
+struct NutrientDefinition {
+    name: String,
+    number: String,
+    unit_name: String,
+}
+
+#[derive(Debug)]
+struct SurveyFood {
+    food_code: Option<i32>,
+    category: Option<(i32, String)>,
+}
+
+impl UsdaCsvDownload {
+    pub fn new(dir: impl Into<PathBuf>, page_size: usize) -> Self {
+        let dir = dir.into();
+
+        Self {
+            file: PagedFile::new(dir.join(FOOD)),
+            dir,
+            page_size,
+        }
+    }
+}
+
+impl FoodSource for UsdaCsvDownload {
+    type Data = UsdaFoodSearchResponse;
+
+    fn name(&self) -> &str {
+        "USDA"
+    }
+
+    fn is_finished(&self, current_page: usize) -> bool {
+        self.file.is_finished(current_page)
+    }
+
+    fn fetch(&self, current_page: usize) -> impl Future<Output = Result<Self::Data, SourceError>> {
+        let index_dir = self.dir.clone();
+        let dir = self.dir.clone();
+        let page_size = self.page_size;
+
+        Box::pin(async move {
+            let (total_pages, foods) = self
+                .file
+                .read(
+                    current_page,
+                    move |_| index_tables(&index_dir, page_size),
+                    move |_, tables, bytes| read_page(&dir, tables, &bytes),
+                )
+                .await?;
+
+            Ok(UsdaFoodSearchResponse { total_pages, foods })
+        })
+    }
+}
+
+fn csv_builder(has_headers: bool) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.has_headers(has_headers).flexible(true);
+    builder
+}
+
+fn index_tables(dir: &Path, page_size: usize) -> Result<FileIndex<UsdaTables>, SourceError> {
+    let (headers, pages) =
+        file_source::index_csv(&dir.join(FOOD), &csv_builder(true), page_size, |_| Ok(None))?;
+    let food_columns = FoodColumns {
+        fdc_id: column_position(&headers, "fdc_id")?,
+        description: column_position(&headers, "description")?,
+    };
+
+    let mut nutrients = HashMap::new();
+    for_each_row(
+        &dir.join(NUTRIENT),
+        ["id", "name", "nutrient_nbr", "unit_name"],
+        |[id, name, number, unit_name]| {
+            let nutrient = NutrientDefinition {
+                name: name.to_string(),
+                number: number.trim().to_string(),
+                unit_name: unit_name.to_string(),
+            };
+            nutrients.insert(parse(id)?, nutrient);
+            Ok(())
+        },
+    )?;
+
+    let (food_nutrient_columns, food_nutrients) = index_food_nutrients(&dir.join(FOOD_NUTRIENT))?;
+    let survey_foods = survey_foods(dir)?;
+
+    Ok(FileIndex {
+        header: UsdaTables {
+            food_columns,
+            nutrients,
+            food_nutrient_columns,
+            food_nutrients,
+            survey_foods,
+        },
+        pages,
+    })
+}
+
+fn index_food_nutrients(
+    path: &Path,
+) -> Result<(FoodNutrientColumns, FoodNutrientRanges), SourceError> {
+    let mut reader = csv_builder(true).from_path(path).map_err(csv_error)?;
+    let headers = file_source::csv_headers(&mut reader)?;
+    let columns = FoodNutrientColumns {
+        fdc_id: column_position(&headers, "fdc_id")?,
+        nutrient_id: column_position(&headers, "nutrient_id")?,
+        amount: column_position(&headers, "amount")?,
+    };
+
+    let mut food_nutrients = FoodNutrientRanges::new();
+    let mut last_food = None;
+    let mut record = csv::StringRecord::new();
+    loop {
+        let start = reader.position().byte();
+        if !reader.read_record(&mut record).map_err(csv_error)? {
+            break;
+        }
+        let end = reader.position().byte();
+
+        // The rows of a food are next to each other in the downloads, so they usually end up as a
+        // single range
+        let fdc_id = parse::<i32>(record.get(columns.fdc_id).unwrap_or_default())?;
+        let ranges = food_nutrients.entry(fdc_id).or_default();
+        match ranges.last_mut() {
+            Some(range) if last_food == Some(fdc_id) => range.end = end,
+            _ => ranges.push(start..end),
+        }
+        last_food = Some(fdc_id);
+    }
+
+    Ok((columns, food_nutrients))
+}
+
+/// FNDDS codes and WWEIA categories of the survey foods, only FNDDS downloads have them
+fn survey_foods(dir: &Path) -> Result<HashMap<i32, SurveyFood>, SourceError> {
+    let mut survey_foods = HashMap::new();
+    let survey_path = dir.join(SURVEY_FNDDS_FOOD);
+    if !survey_path.exists() {
+        return Ok(survey_foods);
+    }
+
+    let mut categories = HashMap::new();
+    let categories_path = dir.join(WWEIA_FOOD_CATEGORY);
+    if categories_path.exists() {
+        for_each_row(
+            &categories_path,
+            ["wweia_food_category", "wweia_food_category_description"],
+            |[code, description]| {
+                categories.insert(parse::<i32>(code)?, description.to_string());
+                Ok(())
+            },
+        )?;
+    }
+
+    for_each_row(
+        &survey_path,
+        ["fdc_id", "food_code", "wweia_category_code"],
+        |[fdc_id, food_code, category_code]| {
+            let category = category_code.parse::<i32>().ok().and_then(|code| {
+                let description = categories.get(&code)?;
+                Some((code, description.clone()))
+            });
+            let survey_food = SurveyFood {
+                food_code: food_code.parse().ok(),
+                category,
+            };
+            survey_foods.insert(parse(fdc_id)?, survey_food);
+            Ok(())
+        },
+    )?;
+
+    Ok(survey_foods)
+}
+
+fn read_page(
+    dir: &Path,
+    tables: &UsdaTables,
+    bytes: &[u8],
+) -> Result<Vec<UsdaFoodSearchFood>, SourceError> {
+    let mut foods = vec![];
+    for record in csv_builder(false).from_reader(bytes).records() {
+        let record = record.map_err(csv_error)?;
+        let fdc_id = parse(record.get(tables.food_columns.fdc_id).unwrap_or_default())?;
+        let survey_food = tables.survey_foods.get(&fdc_id);
+        let category = survey_food.and_then(|survey_food| survey_food.category.as_ref());
+
+        foods.push(UsdaFoodSearchFood {
+            fdc_id,
+            description: record
+                .get(tables.food_columns.description)
+                .unwrap_or_default()
+                .to_string(),
+            food_code: survey_food.and_then(|survey_food| survey_food.food_code),
+            food_category: category.map(|(_, description)| description.clone()),
+            food_category_id: category.map(|(code, _)| *code),
+            food_nutrients: vec![],
+        });
+    }
+
+    let positions = foods
+        .iter()
+        .enumerate()
+        .map(|(position, food)| (food.fdc_id, position))
+        .collect::<HashMap<_, _>>();
+
+    let columns = &tables.food_nutrient_columns;
+    let path = dir.join(FOOD_NUTRIENT);
+    for range in nutrient_ranges(tables, &foods) {
+        let bytes = file_source::read_range(&path, range)?;
+        for record in csv_builder(false).from_reader(bytes.as_slice()).records() {
+            let record = record.map_err(csv_error)?;
+            let fdc_id = parse::<i32>(record.get(columns.fdc_id).unwrap_or_default())?;
+            let nutrient_id = parse::<i32>(record.get(columns.nutrient_id).unwrap_or_default())?;
+            let (Some(position), Some(nutrient)) =
+                (positions.get(&fdc_id), tables.nutrients.get(&nutrient_id))
+            else {
+                continue;
+            };
+
+            foods[*position].food_nutrients.push(UsdaFoodNutrient {
+                nutrient_name: nutrient.name.clone(),
+                nutrient_number: Some(nutrient.number.clone()).filter(|number| !number.is_empty()),
+                unit_name: nutrient.unit_name.clone(),
+                value: record
+                    .get(columns.amount)
+                    .and_then(|amount| amount.parse().ok()),
+            });
+        }
+    }
+
+    Ok(foods)
+}
+
+/// Ranges of `food_nutrient.csv` holding the values of `foods`, merging the ones that touch so
+/// the values of consecutive foods are read at once
+fn nutrient_ranges(tables: &UsdaTables, foods: &[UsdaFoodSearchFood]) -> Vec<Range<u64>> {
+    let mut ranges = foods
+        .iter()
+        .filter_map(|food| tables.food_nutrients.get(&food.fdc_id))
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged = Vec::<Range<u64>>::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Calls `row` with the given columns of every row of a table
+fn for_each_row<const N: usize>(
+    path: &Path,
+    columns: [&str; N],
+    mut row: impl FnMut([&str; N]) -> Result<(), SourceError>,
+) -> Result<(), SourceError> {
+    let mut reader = csv_builder(true).from_path(path).map_err(csv_error)?;
+    let headers = file_source::csv_headers(&mut reader)?;
+    let mut positions = [0; N];
+    for (position, column) in positions.iter_mut().zip(columns) {
+        *position = column_position(&headers, column)?;
+    }
+
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        row(positions.map(|position| record.get(position).unwrap_or_default()))?;
+    }
+
+    Ok(())
+}
+
+fn parse<T: FromStr>(field: &str) -> Result<T, SourceError> {
+    field
+        .trim()
+        .parse()
+        .map_err(|_| SourceError::Decode(format!("invalid number `{field}` in USDA table")))
+}
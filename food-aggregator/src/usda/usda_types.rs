@@ -16,6 +16,10 @@ impl FoodData for UsdaFoodSearchResponse {
     fn entries(&self) -> Self::EntryIter<'_> {
         self.foods.iter()
     }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -53,8 +57,8 @@ impl FoodEntry for UsdaFoodSearchFood {
         self.food_code
     }
 
-    fn id(&self) -> i32 {
-        self.fdc_id
+    fn id(&self) -> String {
+        self.fdc_id.to_string()
     }
 
     fn nutrients(&self) -> Self::NutrientIter<'_> {
@@ -66,6 +70,8 @@ impl FoodEntry for UsdaFoodSearchFood {
 #[serde(rename_all = "camelCase")]
 pub struct UsdaFoodNutrient {
     pub nutrient_name: String,
+    #[serde(default)]
+    pub nutrient_number: Option<String>,
     pub unit_name: String,
     #[serde(default)]
     pub value: Option<f32>,
@@ -76,11 +82,93 @@ impl FoodEntryNutrient for UsdaFoodNutrient {
         &self.nutrient_name
     }
 
+    fn number(&self) -> Option<&str> {
+        self.nutrient_number.as_deref()
+    }
+
     fn unit_name(&self) -> &str {
         &self.unit_name
     }
 
-    fn value(&self) -> f32 {
-        self.value.unwrap_or_default()
+    fn value(&self) -> Option<f32> {
+        self.value
+    }
+}
+
+/// Food as found in the FoodData Central bulk downloads, which nest the nutrient definition
+/// instead of flattening it like the search endpoint does
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsdaBulkFood {
+    pub fdc_id: i32,
+    pub description: String,
+    /// FNDDS codes are numbers in some releases and strings in others
+    #[serde(default)]
+    pub food_code: Option<serde_json::Value>,
+    #[serde(default)]
+    pub wweia_food_category: Option<UsdaBulkWweiaCategory>,
+    #[serde(default)]
+    pub food_nutrients: Vec<UsdaBulkFoodNutrient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsdaBulkWweiaCategory {
+    pub wweia_food_category_code: i32,
+    pub wweia_food_category_description: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsdaBulkFoodNutrient {
+    pub nutrient: UsdaBulkNutrient,
+    #[serde(default)]
+    pub amount: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsdaBulkNutrient {
+    pub name: String,
+    #[serde(default)]
+    pub number: Option<String>,
+    pub unit_name: String,
+}
+
+impl From<UsdaBulkFood> for UsdaFoodSearchFood {
+    fn from(food: UsdaBulkFood) -> Self {
+        let food_code = food.food_code.and_then(|code| match code {
+            serde_json::Value::Number(code) => code.as_i64().and_then(|code| code.try_into().ok()),
+            serde_json::Value::String(code) => code.parse().ok(),
+            _ => None,
+        });
+
+        let (food_category_id, food_category) = match food.wweia_food_category {
+            Some(category) => (
+                Some(category.wweia_food_category_code),
+                Some(category.wweia_food_category_description),
+            ),
+            None => (None, None),
+        };
+
+        let food_nutrients = food
+            .food_nutrients
+            .into_iter()
+            .map(|nutrient| UsdaFoodNutrient {
+                nutrient_name: nutrient.nutrient.name,
+                nutrient_number: nutrient.nutrient.number,
+                unit_name: nutrient.nutrient.unit_name,
+                value: nutrient.amount,
+            })
+            .collect();
+
+        Self {
+            fdc_id: food.fdc_id,
+            description: food.description,
+            food_code,
+            food_category,
+            food_category_id,
+            food_nutrients,
+        }
     }
 }